@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use serde::{Serialize, Deserialize};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+use warp::{Reply, Filter, reject, Rejection, reply::{json, with_status, Response}, path, http::StatusCode};
+
+use crate::models::poke_models::{PokemonResponse, VersionSelection};
+use crate::language::DEFAULT_LANGUAGE;
+use crate::server::{fetch_pokemon, translate_pokemon, translation_key, Fetch, translation_type, with_cache, with_fallback_languages, with_flights, with_languages, with_poke_client, with_species, with_translation_client, with_version};
+use crate::singleflight::SingleFlight;
+use crate::species::SpeciesCache;
+use crate::util::{PokeClient, TranslationClient, TranslationType, CacheKey, PokError, SharedError, Freshness, CacheWrapper, ErrorReply};
+
+/// Maximum number of pokemon warmed at once by a single warm-up request
+const WARM_CONCURRENCY: usize = 4;
+
+/// Maximum number of pokemon a single warm-up request may name
+const MAX_WARM_NAMES: usize = 200;
+
+/// Maximum size of a warm-up request body, in bytes
+const MAX_WARM_BODY: u64 = 16 * 1024;
+
+#[derive(Serialize)]
+struct CacheSummary {
+  entries: BTreeMap<&'static str, u64>,
+  total: u64,
+}
+
+#[derive(Serialize)]
+struct CacheEntry {
+  name: String,
+  translation_type: &'static str,
+  languages: Vec<String>,
+  version: VersionSelection,
+  freshness: Freshness,
+  value: PokemonResponse,
+}
+
+/// Body of a warm-up request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WarmRequest {
+  names: Vec<String>,
+  /// Whether to also translate each pokemon, spending translation quota
+  #[serde(default)]
+  translated: bool,
+}
+
+#[derive(Serialize, Default)]
+struct WarmReport {
+  warmed: Vec<String>,
+  failed: Vec<WarmFailure>,
+}
+
+#[derive(Serialize)]
+struct WarmFailure {
+  name: String,
+  error: String,
+}
+
+/// Compare two byte strings in time independent of where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Filter admitting only requests bearing the admin token
+///
+/// Without a token every request is rejected as not found, so that the admin
+/// routes are indistinguishable from routes that don't exist.
+fn authorize(token: Option<String>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+  let token: Option<Arc<str>> = token.map(Arc::from);
+
+  warp::header::optional::<String>("authorization")
+    .and_then(move |header: Option<String>| {
+      let token = token.clone();
+      async move {
+        let token = match token {
+          Some(token) => token,
+          None => return Err(reject::not_found()),
+        };
+
+        let bearer = header.as_deref().and_then(|header| header.strip_prefix("Bearer "));
+        match bearer {
+          Some(bearer) if constant_time_eq(bearer.as_bytes(), token.as_bytes()) => Ok(()),
+          _ => Err(reject::custom(PokError::Unauthorized)),
+        }
+      }
+    })
+    .untuple_one()
+}
+
+/// Count cached entries by translation type
+async fn summary(
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> Result<Response, Infallible> {
+  let mut entries: BTreeMap<&'static str, u64> = [TranslationType::None, TranslationType::Yoda, TranslationType::Shakespeare]
+    .iter()
+    .map(|translation_type| (translation_type.label(), 0))
+    .collect();
+  for key in cache.keys() {
+    *entries.entry(key.translation_type.label()).or_default() += 1;
+  }
+
+  let total = entries.values().sum();
+  Ok(json(&CacheSummary { entries, total }).into_response())
+}
+
+/// Show a single cached entry, along with how fresh it is
+///
+/// Translations are cached by the language and game of the description they
+/// were made from, whatever was asked for. Unless a game is named, that of a
+/// translation is the one the untranslated entry for the same request is from.
+async fn entry(
+  translation_type: TranslationType,
+  name: String,
+  languages: Vec<String>,
+  version: VersionSelection,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> Result<Response, Infallible> {
+  let (languages, version) = match (translation_type, version) {
+    (TranslationType::None, version) => (languages, version),
+    (_, VersionSelection::Version(version)) => (vec![DEFAULT_LANGUAGE.to_owned()], VersionSelection::Version(version)),
+    (_, version) => {
+      let untranslated = CacheKey::new(name.clone(), TranslationType::None).languages(languages).version(version.clone());
+      let version = match cache.get(&untranslated).await.as_ref().and_then(PokemonResponse::version) {
+        Some(served) => VersionSelection::Version(served.to_owned()),
+        None => version,
+      };
+      (vec![DEFAULT_LANGUAGE.to_owned()], version)
+    },
+  };
+  match cache.lookup(&CacheKey::new(name.clone(), translation_type).languages(languages.clone()).version(version.clone())).await {
+    Some((value, freshness)) => Ok(json(&CacheEntry {
+      name,
+      translation_type: translation_type.label(),
+      languages,
+      version,
+      freshness,
+      value,
+    }).into_response()),
+    // Replied to rather than rejected, as warp would otherwise report the
+    // method expected by the delete route, as a 405
+    None => Ok(with_status(json(&ErrorReply {
+      message: String::from("No cached entry"),
+    }), StatusCode::NOT_FOUND).into_response()),
+  }
+}
+
+/// Invalidate an entry in the given languages and version, along with any
+/// other languages and versions of it held in memory, and forget it should it
+/// have been remembered as not found, and its species
+async fn invalidate(
+  translation_type: TranslationType,
+  name: String,
+  languages: Vec<String>,
+  version: VersionSelection,
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> Result<Response, Infallible> {
+  let requested = CacheKey::new(name.clone(), translation_type).languages(languages).version(version);
+  let others = cache.keys().into_iter()
+    .filter(|key| key.name == name && key.translation_type == translation_type && *key != requested);
+  for key in others.chain(std::iter::once(requested.clone())) {
+    cache.invalidate(&key).await;
+  }
+  poke_client.forget_not_found(&name).await;
+  species.invalidate(&name).await;
+  info!(pokemon = %name, translation_type = translation_type.label(), "invalidated cache entry");
+
+  Ok(with_status(warp::reply(), StatusCode::NO_CONTENT).into_response())
+}
+
+async fn invalidate_all(
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> Result<Response, Infallible> {
+  cache.invalidate_all().await;
+  poke_client.forget_all_not_found().await;
+  species.invalidate_all();
+  info!("invalidated all cache entries");
+
+  Ok(with_status(warp::reply(), StatusCode::NO_CONTENT).into_response())
+}
+
+/// Fetch, and optionally translate, a pokemon - replacing anything cached
+///
+/// As when serving requests, descriptions in languages other than English are
+/// left untranslated.
+#[allow(clippy::too_many_arguments)]
+async fn warm_one(
+  name: String,
+  translated: bool,
+  languages: Arc<Vec<String>>,
+  poke_client: impl PokeClient,
+  translation_client: impl TranslationClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<(), SharedError> {
+  let key = CacheKey::new(name, TranslationType::None).languages(languages.to_vec());
+  let pokemon = fetch_pokemon(key.clone(), Fetch::Refresh, poke_client, species, cache.clone(), flights.clone()).await?;
+
+  if translated && pokemon.language() == DEFAULT_LANGUAGE {
+    let key = translation_key(&pokemon, translation_type(&pokemon));
+    translate_pokemon(key, Fetch::Refresh, pokemon, translation_client, cache, flights).await?;
+  }
+
+  Ok(())
+}
+
+/// Warm the cache with each of the requested pokemon, a few at a time
+///
+/// Failures are reported per pokemon rather than failing the request, but
+/// naming more than `MAX_WARM_NAMES` pokemon is rejected outright.
+async fn warm(
+  request: WarmRequest,
+  languages: Arc<Vec<String>>,
+  poke_client: impl PokeClient,
+  translation_client: impl TranslationClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<Response, Rejection> {
+  if request.names.len() > MAX_WARM_NAMES {
+    return Err(reject::custom(PokError::TooManyNames(request.names.len())))
+  }
+
+  // Each task is only spawned once a permit is free for it
+  let permits = Arc::new(Semaphore::new(WARM_CONCURRENCY));
+  let mut tasks = Vec::with_capacity(request.names.len());
+  for name in request.names {
+    let permit = permits.clone().acquire_owned().await;
+    let work = warm_one(name.clone(), request.translated, languages.clone(), poke_client.clone(), translation_client.clone(), species.clone(), cache.clone(), flights.clone());
+    let task = tokio::spawn(async move {
+      let _permit = permit;
+      work.await
+    });
+    tasks.push((name, task));
+  }
+
+  let mut report = WarmReport::default();
+  for (name, task) in tasks {
+    match task.await {
+      Ok(Ok(())) => report.warmed.push(name),
+      Ok(Err(err)) => {
+        warn!(pokemon = %name, error = %err, "failed to warm cache");
+        report.failed.push(WarmFailure { name, error: err.to_string() });
+      },
+      Err(err) => {
+        warn!(pokemon = %name, error = %err, "cache warming task failed");
+        report.failed.push(WarmFailure { name, error: err.to_string() });
+      },
+    }
+  }
+  info!(warmed = report.warmed.len(), failed = report.failed.len(), "warmed cache");
+
+  Ok(json(&report).into_response())
+}
+
+/// Routes for operators to inspect and manage the cache, under "admin/cache"
+///
+/// - `GET /admin/cache` counts entries by translation type
+/// - `GET /admin/cache/{type}/{name}` shows an entry and how fresh it is
+/// - `DELETE /admin/cache/{type}/{name}` invalidates an entry
+/// - `DELETE /admin/cache` invalidates every entry
+/// - `POST /admin/cache/warm` fetches, and replaces any cached, entries for a
+///   list of up to `MAX_WARM_NAMES` names, translating them too if asked
+///
+/// Invalidations also forget pokemon remembered as not found by Pokeapi, and
+/// the species held in memory.
+///
+/// Translation types are given by their labels: "none", "yoda" or
+/// "shakespeare". Entries are those described in the `languages` the router
+/// falls back to, or those given by a `lang` query parameter, from the first
+/// game version unless a `version` query parameter is given - translations
+/// being cached in English, by the game served - and are warmed in the
+/// fallback languages from the first version. Every route requires an
+/// `Authorization: Bearer` header carrying `token`, and without a token none
+/// are served.
+pub fn routes(
+  token: Option<String>,
+  languages: Arc<Vec<String>>,
+  poke_client: impl PokeClient,
+  translation_client: impl TranslationClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+  let summary_route = path!("cache")
+    .and(warp::get())
+    .and(with_cache(cache.clone()))
+    .and_then(summary);
+
+  let invalidate_all_route = path!("cache")
+    .and(warp::delete())
+    .and(with_poke_client(poke_client.clone()))
+    .and(with_species(species.clone()))
+    .and(with_cache(cache.clone()))
+    .and_then(invalidate_all);
+
+  let warm_route = path!("cache" / "warm")
+    .and(warp::post())
+    .and(warp::body::content_length_limit(MAX_WARM_BODY))
+    .and(warp::body::json())
+    .and(with_fallback_languages(languages.clone()))
+    .and(with_poke_client(poke_client.clone()))
+    .and(with_translation_client(translation_client))
+    .and(with_species(species.clone()))
+    .and(with_cache(cache.clone()))
+    .and(with_flights(flights))
+    .and_then(warm);
+
+  let entry_route = path!("cache" / TranslationType / String)
+    .and(warp::get())
+    .and(with_languages(languages.clone()))
+    .and(with_version())
+    .and(with_cache(cache.clone()))
+    .and_then(entry);
+
+  let invalidate_route = path!("cache" / TranslationType / String)
+    .and(warp::delete())
+    .and(with_languages(languages))
+    .and(with_version())
+    .and(with_poke_client(poke_client))
+    .and(with_species(species))
+    .and(with_cache(cache))
+    .and_then(invalidate);
+
+  // Authorizing ahead of matching methods reports a bad token, rather than
+  // the method of whichever route happened to be tried
+  warp::path("admin")
+    .and(authorize(token))
+    .and(
+      summary_route
+        .or(invalidate_all_route).unify()
+        .or(warm_route).unify()
+        .or(entry_route).unify()
+        .or(invalidate_route).unify()
+    )
+}
@@ -1,51 +1,349 @@
+use std::{future::Future, io, time::{Duration, Instant}};
+
 use async_trait::async_trait;
-use hyper::{Client, client::HttpConnector, Uri, body::to_bytes};
+use hyper::{Client, client::HttpConnector, Method, StatusCode, Uri, body::{to_bytes, Bytes}, header::RETRY_AFTER};
 use hyper_tls::HttpsConnector;
+use moka::future::Cache;
+use tokio::time::timeout;
+use tracing::{debug, instrument, warn};
 use serde_json::from_slice;
 use urlencoding::encode;
 
-use super::util::{PokeClient, TranslationClient, TranslationType, PokError};
-use super::models::{poke_models::PokemonSpecies, poke_models::PokemonResponse, translation_models::TranslationUnit};
+use super::config::Config;
+use super::breaker::{CircuitBreaker, BreakerStatus};
+use super::limiter::RateLimiter;
+use super::metrics;
+use super::retry::{RetryPolicy, parse_retry_after};
+use super::util::{PokeClient, TranslationClient, TranslationType, PokError, io_error_kind};
+use super::models::{poke_models::NamedAPIResourceList, poke_models::PokemonSpecies, poke_models::PokemonResponse, translation_models::TranslationUnit};
 
 /// An "API" that can connect to a given API and make requests
 /// 
 /// This object exists to provide an HTTP/S client by which API requests can be 
 /// made in the PokeClient and TranslationClient implementations.
 /// 
-/// Includes fields for testing purposes allowing the override of the target url
-/// and https functionality.
+/// Includes fields allowing the override of the target url and https 
+/// functionality for each upstream API, both for testing purposes and for 
+/// deployments that proxy or mirror the upstream APIs.
+/// 
+/// Failed requests are not retried unless a retry policy is set, and 
+/// translation requests are only guarded by a circuit breaker or rate limiter 
+/// if one is set.
+/// 
+/// No timeouts are applied unless set - a stalled upstream would otherwise 
+/// hold a request open indefinitely, so production use should always set them 
+/// (as `from_config` does).
+/// 
+/// Pokemon that Pokeapi doesn't know of are reported as `PokError::NotFound`, 
+/// and only remembered as such if negative caching is enabled.
 #[derive(Clone)]
 pub struct API {
   client: Client<HttpsConnector<HttpConnector>>,
-  uri_override: Option<String>,
-  https: bool,
+  pokeapi_override: Option<String>,
+  pokeapi_https: bool,
+  translation_override: Option<String>,
+  translation_https: bool,
+  read_timeout: Option<Duration>,
+  deadline: Option<Duration>,
+  retry: RetryPolicy,
+  translation_breaker: Option<CircuitBreaker>,
+  translation_limiter: Option<RateLimiter>,
+  not_found: Option<Cache<String, ()>>,
+}
+
+/// The most unknown pokemon names remembered at once
+/// 
+/// Unknown names are unbounded, and may well come from scanners or typos, so 
+/// this is kept well above the number of pokemon without being a memory risk.
+const NOT_FOUND_CAPACITY: u64 = 10_000;
+
+impl Default for API {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl API {
   pub fn new() -> Self {
     Self {
-      client: Client::builder()
-        .build(HttpsConnector::new()),
-      uri_override: None,
-      https: true,
+      client: Self::build_client(None),
+      pokeapi_override: None,
+      pokeapi_https: true,
+      translation_override: None,
+      translation_https: true,
+      read_timeout: None,
+      deadline: None,
+      retry: RetryPolicy::none(),
+      translation_breaker: None,
+      translation_limiter: None,
+      not_found: None,
     }
   }
 
-  /// Set the URI override - this host will be contacted instead of the 
-  /// designated API address.
-  pub fn override_uri(mut self, over_ride: String) -> Self {
-    self.uri_override = Some(over_ride);
+  fn build_client(connect_timeout: Option<Duration>) -> Client<HttpsConnector<HttpConnector>> {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(connect_timeout);
+
+    Client::builder()
+      .build(HttpsConnector::new_with_connector(http))
+  }
+
+  /// Build an API from the upstream sections of the given configuration
+  pub fn from_config(config: &Config) -> Self {
+    let mut api = Self::new();
+
+    if let Some(host) = &config.pokeapi.host {
+      api = api.override_pokeapi_uri(host.clone());
+    }
+    if let Some(host) = &config.funtranslations.host {
+      api = api.override_translation_uri(host.clone());
+    }
+    if config.breaker.enabled {
+      api = api.translation_breaker(config.breaker.breaker());
+    }
+    api = api.translation_limiter(config.limiter.limiter());
+    if let Some(ttl) = config.cache.not_found_ttl() {
+      api = api.cache_not_found(ttl);
+    }
+    api.pokeapi_https = config.pokeapi.https;
+    api.translation_https = config.funtranslations.https;
+
+    api.connect_timeout(config.timeouts.connect())
+      .read_timeout(config.timeouts.read())
+      .deadline(config.timeouts.deadline())
+      .retry_policy(config.retry.policy())
+  }
+
+  /// Set the URI override for both APIs - this host will be contacted instead 
+  /// of the designated API addresses.
+  pub fn override_uri(self, over_ride: String) -> Self {
+    self.override_pokeapi_uri(over_ride.clone())
+      .override_translation_uri(over_ride)
+  }
+
+  /// Set the URI override for Pokeapi only
+  pub fn override_pokeapi_uri(mut self, over_ride: String) -> Self {
+    self.pokeapi_override = Some(over_ride);
+    self
+  }
+
+  /// Set the URI override for the funtranslations API only
+  pub fn override_translation_uri(mut self, over_ride: String) -> Self {
+    self.translation_override = Some(over_ride);
     self
   }
 
-  /// Disable https connectivity
+  /// Disable https connectivity for both APIs
   /// 
   /// This should probably only be used during testing, but there do still exist
   /// non-https sites.
   pub fn disable_https(mut self) -> Self {
-    self.https = false;
+    self.pokeapi_https = false;
+    self.translation_https = false;
     self
   }
+
+  /// Set the maximum time allowed to establish a connection to an upstream API
+  pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+    self.client = Self::build_client(Some(connect_timeout));
+    self
+  }
+
+  /// Set the maximum time allowed waiting on the response headers, and then 
+  /// again on the response body, once connected
+  pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+    self.read_timeout = Some(read_timeout);
+    self
+  }
+
+  /// Set the maximum time allowed for a request as a whole, from connecting 
+  /// through to reading the last byte of the body
+  pub fn deadline(mut self, deadline: Duration) -> Self {
+    self.deadline = Some(deadline);
+    self
+  }
+
+  /// Set the policy used to retry failed requests
+  pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+    self.retry = retry;
+    self
+  }
+
+  /// Guard requests to the funtranslations API with a circuit breaker
+  /// 
+  /// While the breaker is open, `translate` fails immediately with 
+  /// `PokError::CircuitOpen` rather than contacting the API.
+  pub fn translation_breaker(mut self, breaker: CircuitBreaker) -> Self {
+    self.translation_breaker = Some(breaker);
+    self
+  }
+
+  /// Limit the rate of requests to the funtranslations API
+  /// 
+  /// When a translation type's quota is exhausted, `translate` fails with 
+  /// `PokError::RateLimited` rather than spending a request we know will be 
+  /// rejected with a 429.
+  pub fn translation_limiter(mut self, limiter: RateLimiter) -> Self {
+    self.translation_limiter = Some(limiter);
+    self
+  }
+
+  /// Remember pokemon that Pokeapi doesn't know of for `ttl`
+  /// 
+  /// Requests for them within that time fail immediately with 
+  /// `PokError::NotFound`, rather than asking Pokeapi again.
+  pub fn cache_not_found(mut self, ttl: Duration) -> Self {
+    self.not_found = Some(Cache::builder()
+      .max_capacity(NOT_FOUND_CAPACITY)
+      .time_to_live(ttl)
+      .build());
+    self
+  }
+
+  /// Request the raw JSON describing a pokemon species from Pokeapi
+  /// 
+  /// Unknown species are reported as `PokError::NotFound`, but not remembered 
+  /// as such - see `get_pokemon` for that.
+  pub async fn get_species_json(&self, pokemon: &str) -> Result<Bytes, PokError> {
+    let res = self
+      .get("pokeapi", Uri::builder()
+        .scheme(if self.pokeapi_https { "https" } else { "http" })
+        .authority(self.get_pokeapi_url())
+        .path_and_query(format!("/api/v2/pokemon-species/{}", pokemon))
+        .build()?
+      )
+      .await;
+
+    match res {
+      Err(PokError::Unavailable(StatusCode::NOT_FOUND)) => Err(PokError::NotFound),
+      res => res,
+    }
+  }
+
+  /// Perform a GET request, returning the body of a successful response
+  /// 
+  /// Failed attempts are retried according to the retry policy, with the 
+  /// deadline applying across all attempts. Any non-success status is returned 
+  /// as `PokError::Unavailable`, and any expired timeout as `PokError::Timeout`.
+  /// 
+  /// `upstream` names the API being contacted in metrics.
+  async fn get(&self, upstream: &'static str, uri: Uri) -> Result<Bytes, PokError> {
+    self.get_limited(upstream, uri, None).await
+  }
+
+  /// Perform a GET request as in `get`, taking a token from `limit` ahead of 
+  /// every attempt, retries included
+  /// 
+  /// Without a token for the first attempt, fails with `PokError::RateLimited` 
+  /// having made no request. Without one for a retry, the failure of the 
+  /// previous attempt is returned instead.
+  #[instrument(name = "upstream", skip_all, fields(upstream = upstream, host = uri.host().unwrap_or_default(), path = uri.path()))]
+  async fn get_limited(&self, upstream: &'static str, uri: Uri, limit: Option<(&RateLimiter, TranslationType)>) -> Result<Bytes, PokError> {
+    let started = Instant::now();
+
+    let res = within(self.deadline, async {
+      let mut attempt = 1;
+      let mut last_error = None;
+      loop {
+        if let Some((limiter, translate_to)) = limit {
+          if let Err(err) = limiter.acquire(translate_to).await {
+            debug!(attempt, translation_type = ?translate_to, "translation quota exhausted, skipping upstream");
+            return Err(last_error.unwrap_or(err))
+          }
+        }
+
+        let (error, retry_after) = match self.attempt(uri.clone()).await {
+          Ok(bytes) => return Ok(bytes),
+          Err(failure) => failure,
+        };
+
+        match self.retry.next_delay(&Method::GET, attempt, &error, retry_after) {
+          Some(delay) => {
+            warn!(attempt, error = %error, delay_ms = delay.as_millis() as u64, "retrying upstream request");
+            tokio::time::sleep(delay).await
+          },
+          None => return Err(error),
+        }
+        last_error = Some(error);
+        attempt += 1;
+      }
+    }).await;
+
+    let elapsed = started.elapsed();
+    metrics::record_upstream(upstream, elapsed, res.as_ref().err());
+
+    let latency_ms = elapsed.as_millis() as u64;
+    match &res {
+      Ok(_) => debug!(latency_ms, "upstream request succeeded"),
+      // Unknown resources are an expected answer, not a failing upstream
+      Err(PokError::Unavailable(StatusCode::NOT_FOUND)) => debug!(latency_ms, "upstream resource not found"),
+      Err(error) => warn!(latency_ms, error = %error, "upstream request failed"),
+    }
+
+    res
+  }
+
+  /// Make a single attempt at a GET request
+  /// 
+  /// On failure, any delay requested by the upstream's `Retry-After` header is 
+  /// returned alongside the error.
+  async fn attempt(&self, uri: Uri) -> Result<Bytes, (PokError, Option<Duration>)> {
+    let started = Instant::now();
+    let res = within(self.read_timeout, async {
+      self.client.get(uri).await.map_err(connect_error)
+    }).await.map_err(|err| (err, None))?;
+
+    debug!(
+      status = res.status().as_u16(),
+      latency_ms = started.elapsed().as_millis() as u64,
+      "upstream responded"
+    );
+
+    if !res.status().is_success() {
+      let retry_after = res.headers().get(RETRY_AFTER).and_then(parse_retry_after);
+      return Err((PokError::Unavailable(res.status()), retry_after))
+    }
+
+    within(self.read_timeout, async {
+      Ok(to_bytes(res.into_body()).await?)
+    }).await.map_err(|err| (err, None))
+  }
+
+  /// Check that a host is reachable, without retries
+  /// 
+  /// Any response short of a server error counts as reachable - we only care 
+  /// that the upstream is up and answering, not what it has to say.
+  async fn probe(&self, uri: Uri) -> Result<(), PokError> {
+    within(self.deadline, async {
+      let res = within(self.read_timeout, async {
+        self.client.get(uri).await.map_err(connect_error)
+      }).await?;
+
+      if res.status().is_server_error() {
+        Err(PokError::Unavailable(res.status()))
+      } else {
+        Ok(())
+      }
+    }).await
+  }
+}
+
+/// Bound a fallible future by an optional time limit
+async fn within<T>(limit: Option<Duration>, fut: impl Future<Output = Result<T, PokError>>) -> Result<T, PokError> {
+  match limit {
+    Some(limit) => timeout(limit, fut).await.map_err(|_| PokError::Timeout)?,
+    None => fut.await,
+  }
+}
+
+/// Hyper reports an expired connect timeout as an opaque connection error - 
+/// dig out the underlying io error so it can be reported as a timeout.
+fn connect_error(err: hyper::Error) -> PokError {
+  match io_error_kind(&err) {
+    Some(io::ErrorKind::TimedOut) => PokError::Timeout,
+    _ => PokError::Hyper(err),
+  }
 }
 
 #[async_trait]
@@ -54,27 +352,65 @@ impl PokeClient for API {
   const POKEAPI: &'static str = "pokeapi.co";
 
   fn get_pokeapi_url(&self) -> String {
-    self.uri_override.clone().unwrap_or(Self::POKEAPI.to_string())
+    self.pokeapi_override.clone().unwrap_or_else(|| Self::POKEAPI.to_string())
   }
 
   async fn get_pokemon(&self, pokemon: String) -> Result<PokemonSpecies, PokError> {
-    let res = self.client
-      .get(Uri::builder()
-        .scheme(if self.https { "https" } else { "http" })
+    if let Some(not_found) = &self.not_found {
+      if not_found.get(&pokemon).is_some() {
+        debug!(pokemon = %pokemon, "pokemon recently not found, skipping upstream");
+        return Err(PokError::NotFound)
+      }
+    }
+
+    let bytes = match self.get_species_json(&pokemon).await {
+      Err(PokError::NotFound) => {
+        if let Some(not_found) = &self.not_found {
+          not_found.insert(pokemon, ()).await;
+        }
+        return Err(PokError::NotFound)
+      },
+      res => res?,
+    };
+
+    let species = from_slice::<PokemonSpecies>(&bytes)?;
+
+    Ok(species)
+  }
+
+  async fn forget_not_found(&self, pokemon: &str) {
+    if let Some(not_found) = &self.not_found {
+      not_found.invalidate(pokemon).await;
+    }
+  }
+
+  async fn forget_all_not_found(&self) {
+    if let Some(not_found) = &self.not_found {
+      not_found.invalidate_all();
+    }
+  }
+
+  async fn list_species(&self, offset: u64, limit: u64) -> Result<NamedAPIResourceList, PokError> {
+    let bytes = self
+      .get("pokeapi", Uri::builder()
+        .scheme(if self.pokeapi_https { "https" } else { "http" })
         .authority(self.get_pokeapi_url())
-        .path_and_query(format!("/api/v2/pokemon-species/{}", pokemon))
+        .path_and_query(format!("/api/v2/pokemon-species?offset={}&limit={}", offset, limit))
         .build()?
       )
       .await?;
 
-    if !res.status().is_success() {
-      return Err(PokError::Unavailable(res.status()))
-    }
-
-    let bytes = to_bytes(res.into_body()).await?;
-    let species = from_slice::<PokemonSpecies>(&bytes)?;
+    Ok(from_slice::<NamedAPIResourceList>(&bytes)?)
+  }
 
-    Ok(species)
+  /// Request the API root, which lists Pokeapi's resources
+  async fn ping(&self) -> Result<(), PokError> {
+    self.probe(Uri::builder()
+      .scheme(if self.pokeapi_https { "https" } else { "http" })
+      .authority(self.get_pokeapi_url())
+      .path_and_query("/api/v2/")
+      .build()?
+    ).await
   }
 }
 
@@ -84,32 +420,72 @@ impl TranslationClient for API {
   const TRANSLATION_API: &'static str = "api.funtranslations.com";
 
   fn get_translation_url(&self) -> String {
-    self.uri_override.clone().unwrap_or(Self::TRANSLATION_API.to_string())
+    self.translation_override.clone().unwrap_or_else(|| Self::TRANSLATION_API.to_string())
+  }
+
+  fn backend_name(&self) -> &'static str {
+    "funtranslations"
   }
 
   async fn translate(&self, pokemon: &PokemonResponse, translate_to: TranslationType) -> Result<String, PokError> {
     let desc = pokemon.description();
+    let uri = Uri::builder()
+      .scheme(if self.translation_https { "https" } else { "http" })
+      .authority(self.get_translation_url())
+      .path_and_query(format!("/translate/{}?text={}", translate_to, encode(desc)))
+      .build()?;
 
-    let res = self.client
-      .get(Uri::builder()
-        .scheme(if self.https { "https" } else { "http" })
-        .authority(self.get_translation_url())
-        .path_and_query(format!("/translate/{}?text={}", translate_to.to_string(), encode(&desc)))
-        .build()?
-      )
-      .await?;
+    // Checked ahead of the limiter, so that an open breaker spends no quota
+    let permit = match &self.translation_breaker {
+      Some(breaker) => match breaker.try_acquire() {
+        Some(permit) => Some(permit),
+        None => {
+          debug!(translation_type = ?translate_to, "circuit breaker open, skipping upstream");
+          return Err(PokError::CircuitOpen)
+        },
+      },
+      None => None,
+    };
+
+    let limit = self.translation_limiter.as_ref().map(|limiter| (limiter, translate_to));
+    let res = self.get_limited("funtranslations", uri, limit).await;
+
+    // No request was made, so the upstream is neither failing nor recovered
+    if let Err(PokError::RateLimited) = res {
+      if let Some(permit) = permit {
+        permit.release();
+      }
+      return Err(PokError::RateLimited)
+    }
 
-    return if !res.status().is_success() {
-      if res.status() == 429 {
-        println!("Rate limited by Funtranslations API")
+    if let Err(PokError::Unavailable(status)) = &res {
+      if *status == 429 {
+        warn!("Rate limited by Funtranslations API")
       }
-      Err(PokError::Unavailable(res.status()))
-    } else {
-      let bytes = to_bytes(res.into_body()).await?;
-      let translation_unit = from_slice::<TranslationUnit>(&bytes)?;
-      let translation = translation_unit.contents().translated().to_owned();
+    }
 
-      Ok(translation)
+    // Only a parsed translation counts as a success - a permit dropped on 
+    // any error records a failure
+    let translation_unit = from_slice::<TranslationUnit>(&res?)?;
+    if let Some(permit) = permit {
+      permit.success();
     }
+
+    Ok(translation_unit.contents().translated().to_owned())
+  }
+
+  fn breaker_status(&self) -> Option<BreakerStatus> {
+    self.translation_breaker.as_ref().map(CircuitBreaker::status)
+  }
+
+  /// Request the site root - translation endpoints count against our quota, 
+  /// and the circuit breaker and rate limiter are deliberately bypassed.
+  async fn ping(&self) -> Result<(), PokError> {
+    self.probe(Uri::builder()
+      .scheme(if self.translation_https { "https" } else { "http" })
+      .authority(self.get_translation_url())
+      .path_and_query("/")
+      .build()?
+    ).await
   }
 }
@@ -0,0 +1,183 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// The externally visible state of a circuit breaker
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+  /// Requests flow through as normal
+  Closed,
+  /// Requests are refused without contacting the upstream
+  Open,
+  /// The cool-down has passed, and a single trial request is allowed through
+  HalfOpen,
+}
+
+/// A snapshot of a circuit breaker, as reported by the status endpoint
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BreakerStatus {
+  pub state: BreakerState,
+  pub consecutive_failures: u32,
+  /// Milliseconds until an open breaker will allow a trial request
+  pub retry_in_ms: Option<u64>,
+}
+
+struct Inner {
+  state: BreakerState,
+  consecutive_failures: u32,
+  opened_at: Option<Instant>,
+  trial_in_flight: bool,
+}
+
+/// A circuit breaker guarding calls to an upstream API
+///
+/// After `failure_threshold` consecutive failures the breaker opens, and all
+/// calls are refused for the `cool_down` window. Once that has passed the
+/// breaker is half-open: a single trial call is let through, closing the
+/// breaker again if it succeeds or re-opening it for another window if not.
+///
+/// Clones share state, so a breaker can be cloned into each request handler.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+  inner: Arc<Mutex<Inner>>,
+  failure_threshold: u32,
+  cool_down: Duration,
+}
+
+impl CircuitBreaker {
+  pub fn new(failure_threshold: u32, cool_down: Duration) -> Self {
+    Self {
+      inner: Arc::new(Mutex::new(Inner {
+        state: BreakerState::Closed,
+        consecutive_failures: 0,
+        opened_at: None,
+        trial_in_flight: false,
+      })),
+      failure_threshold: failure_threshold.max(1),
+      cool_down,
+    }
+  }
+
+  /// Ask to make a call, returning None if it should be skipped.
+  ///
+  /// Callers given a permit report the outcome through it. A permit dropped 
+  /// without an outcome, such as by a cancelled request, counts as a failure - 
+  /// otherwise a half-open trial would never finish, and the breaker would 
+  /// refuse every call from then on.
+  pub fn try_acquire(&self) -> Option<BreakerPermit> {
+    if self.acquire() {
+      Some(BreakerPermit { breaker: self.clone(), resolved: false })
+    } else {
+      None
+    }
+  }
+
+  fn acquire(&self) -> bool {
+    let mut inner = self.inner.lock().unwrap();
+
+    match inner.state {
+      BreakerState::Closed => true,
+      BreakerState::Open => {
+        let cooled_down = match inner.opened_at {
+          Some(at) => at.elapsed() >= self.cool_down,
+          None => true,
+        };
+
+        if cooled_down {
+          inner.state = BreakerState::HalfOpen;
+          inner.trial_in_flight = true;
+          true
+        } else {
+          false
+        }
+      },
+      BreakerState::HalfOpen => {
+        if inner.trial_in_flight {
+          false
+        } else {
+          inner.trial_in_flight = true;
+          true
+        }
+      },
+    }
+  }
+
+  /// Report that a permitted call succeeded, closing the breaker.
+  fn record_success(&self) {
+    let mut inner = self.inner.lock().unwrap();
+
+    inner.state = BreakerState::Closed;
+    inner.consecutive_failures = 0;
+    inner.opened_at = None;
+    inner.trial_in_flight = false;
+  }
+
+  /// Report that a permitted call failed, opening the breaker if the failure
+  /// threshold has been reached or the failed call was a half-open trial.
+  fn record_failure(&self) {
+    let mut inner = self.inner.lock().unwrap();
+
+    inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+    inner.trial_in_flight = false;
+
+    if inner.state == BreakerState::HalfOpen || inner.consecutive_failures >= self.failure_threshold {
+      inner.state = BreakerState::Open;
+      inner.opened_at = Some(Instant::now());
+    }
+  }
+
+  /// Get a snapshot of the breaker's current state.
+  pub fn status(&self) -> BreakerStatus {
+    let inner = self.inner.lock().unwrap();
+
+    let retry_in_ms = match (inner.state, inner.opened_at) {
+      (BreakerState::Open, Some(at)) => Some(self.cool_down.saturating_sub(at.elapsed()).as_millis() as u64),
+      _ => None,
+    };
+
+    BreakerStatus {
+      state: inner.state,
+      consecutive_failures: inner.consecutive_failures,
+      retry_in_ms,
+    }
+  }
+}
+
+/// Permission to make a single call through a circuit breaker
+///
+/// Recorded as a failure if dropped without `success` or `failure`.
+pub struct BreakerPermit {
+  breaker: CircuitBreaker,
+  resolved: bool,
+}
+
+impl BreakerPermit {
+  /// Report that the call succeeded, closing the breaker.
+  pub fn success(mut self) {
+    self.resolved = true;
+    self.breaker.record_success();
+  }
+
+  /// Report that the call failed, see `CircuitBreaker::record_failure`.
+  pub fn failure(mut self) {
+    self.resolved = true;
+    self.breaker.record_failure();
+  }
+
+  /// Give up the call without making it, leaving the breaker as it was - bar 
+  /// allowing another half-open trial.
+  pub fn release(mut self) {
+    self.resolved = true;
+    self.breaker.inner.lock().unwrap().trial_in_flight = false;
+  }
+}
+
+impl Drop for BreakerPermit {
+  fn drop(&mut self) {
+    if !self.resolved {
+      self.breaker.record_failure();
+    }
+  }
+}
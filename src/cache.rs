@@ -0,0 +1,5 @@
+pub mod disk;
+pub mod expiring;
+pub mod partitioned;
+pub mod redis;
+pub mod tiered;
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, ErrorKind};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::util::{CacheWrapper, Freshness};
+
+/// A single line of the cache file
+///
+/// A record without a value is a tombstone, marking its key as invalidated. 
+/// Records are stamped with the unix time they were written at, records from 
+/// before stamps were written being taken as written when the file was last 
+/// modified.
+#[derive(Serialize, Deserialize)]
+struct Record<K, V> {
+  key: K,
+  value: Option<V>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  written_at: Option<u64>,
+}
+
+/// Gives the time to live of a key's record, if it expires at all
+type TimeToLive<K> = Arc<dyn Fn(&K) -> Option<Duration> + Send + Sync>;
+
+/// How long records of a DiskCache stay restorable, and when its file is 
+/// compacted
+pub struct DiskOptions<K> {
+  time_to_live: TimeToLive<K>,
+  compact_threshold: u64,
+}
+
+impl<K> Default for DiskOptions<K> {
+  /// Records never expire, and the file is compacted past 1 MiB
+  fn default() -> Self {
+    Self {
+      time_to_live: Arc::new(|_| None),
+      compact_threshold: 1024 * 1024,
+    }
+  }
+}
+
+impl<K> DiskOptions<K> {
+  /// Set the time to live of each key's record - records older than it are 
+  /// dropped rather than restored.
+  pub fn time_to_live(mut self, time_to_live: impl Fn(&K) -> Option<Duration> + Send + Sync + 'static) -> Self {
+    self.time_to_live = Arc::new(time_to_live);
+    self
+  }
+
+  /// Set the size, in bytes, past which the file is compacted while running, 
+  /// should it also have doubled in size since it was last compacted.
+  pub fn compact_threshold(mut self, bytes: u64) -> Self {
+    self.compact_threshold = bytes;
+    self
+  }
+}
+
+/// The file being appended to, and how far it has grown
+struct Appender {
+  file: File,
+  size: u64,
+  compacted_size: u64,
+}
+
+/// A CacheWrapper that persists every insert to disk
+///
+/// Entries are appended to a file of newline delimited JSON records, and all 
+/// reads are served by the wrapped in-memory cache. On opening, the file is 
+/// replayed into the in-memory cache so that entries - most importantly the 
+/// expensive, rate limited translations - survive restarts. The file is then 
+/// compacted, dropping all but the latest record for each key, along with any 
+/// keys since invalidated and any records past their time to live. The file 
+/// is compacted in the same way while running, once it has grown past the 
+/// compaction threshold, see `DiskOptions`.
+///
+/// Restored entries are inserted afresh, so may stay cached in memory for up 
+/// to their time to live again.
+///
+/// Writing to disk is best effort: a failed write is logged, but the entry is 
+/// still cached in memory and the request it belongs to is unaffected.
+pub struct DiskCache<K, V, C> {
+  inner: C,
+  appender: Arc<Mutex<Appender>>,
+  path: PathBuf,
+  time_to_live: TimeToLive<K>,
+  compact_threshold: u64,
+  _entries: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, C: Clone> Clone for DiskCache<K, V, C> {
+  fn clone(&self) -> Self {
+    Self {
+      inner: self.inner.clone(),
+      appender: self.appender.clone(),
+      path: self.path.clone(),
+      time_to_live: self.time_to_live.clone(),
+      compact_threshold: self.compact_threshold,
+      _entries: PhantomData,
+    }
+  }
+}
+
+impl<K, V, C> DiskCache<K, V, C>
+where
+  K: Serialize + DeserializeOwned + Hash + Eq + Send + Sync + 'static,
+  V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+  C: CacheWrapper<K, V>,
+{
+  /// Open (or create) the cache file at `path`, warming `inner` from it, 
+  /// with the default options.
+  ///
+  /// Lines that cannot be parsed, such as one cut short by a crash part way 
+  /// through a write, are skipped with a warning.
+  pub async fn open(path: impl AsRef<Path>, inner: C) -> io::Result<Self> {
+    Self::open_with(path, inner, DiskOptions::default()).await
+  }
+
+  /// Open (or create) the cache file at `path` as in `open`, following the 
+  /// given options.
+  pub async fn open_with(path: impl AsRef<Path>, inner: C, options: DiskOptions<K>) -> io::Result<Self> {
+    let path = path.as_ref().to_path_buf();
+
+    let (records, size) = compact(&path, &options.time_to_live).await?;
+
+    let restored = records.len();
+    for record in records {
+      if let Some(value) = record.value {
+        inner.insert(record.key, value).await;
+      }
+    }
+    info!(path = %path.display(), restored, "warmed cache from disk");
+
+    Ok(Self {
+      inner,
+      appender: Arc::new(Mutex::new(Appender {
+        file: open_append(&path).await?,
+        size,
+        compacted_size: size,
+      })),
+      path,
+      time_to_live: options.time_to_live,
+      compact_threshold: options.compact_threshold,
+      _entries: PhantomData,
+    })
+  }
+
+  async fn append(&self, record: &Record<&K, &V>) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+
+    let mut appender = self.appender.lock().await;
+    appender.file.write_all(&line).await?;
+    appender.file.flush().await?;
+    appender.size += line.len() as u64;
+
+    if appender.size > self.compact_threshold && appender.size > appender.compacted_size * 2 {
+      let (records, size) = compact::<K, V>(&self.path, &self.time_to_live).await?;
+      appender.file = open_append(&self.path).await?;
+      appender.size = size;
+      appender.compacted_size = size;
+      info!(path = %self.path.display(), records = records.len(), size, "compacted cache file");
+    }
+
+    Ok(())
+  }
+
+  async fn truncate(&self) -> io::Result<()> {
+    let mut appender = self.appender.lock().await;
+    appender.file.set_len(0).await?;
+    appender.size = 0;
+    appender.compacted_size = 0;
+    Ok(())
+  }
+}
+
+async fn open_append(path: &Path) -> io::Result<File> {
+  OpenOptions::new()
+    .append(true)
+    .create(true)
+    .open(path)
+    .await
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
+
+/// Rewrite the cache file at `path` with only the latest live record of each 
+/// key, returning those records, oldest first, and the size of the new file
+async fn compact<K, V>(path: &Path, time_to_live: &TimeToLive<K>) -> io::Result<(Vec<Record<K, V>>, u64)>
+where
+  K: Serialize + DeserializeOwned + Hash + Eq,
+  V: Serialize + DeserializeOwned,
+{
+  let contents = match fs::read_to_string(path).await {
+    Ok(contents) => contents,
+    Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+    Err(err) => return Err(err),
+  };
+  let modified = match fs::metadata(path).await.and_then(|metadata| metadata.modified()) {
+    Ok(modified) => modified.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default(),
+    Err(_) => now_secs(),
+  };
+
+  // Later records supersede earlier ones for the same key
+  let mut latest: HashMap<K, (usize, Option<V>, u64)> = HashMap::new();
+  let mut skipped = 0;
+  for (line_number, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+    match serde_json::from_str::<Record<K, V>>(line) {
+      Ok(record) => {
+        latest.insert(record.key, (line_number, record.value, record.written_at.unwrap_or(modified)));
+      },
+      Err(err) => {
+        warn!(path = %path.display(), line = line_number + 1, error = %err, "skipping unreadable cache record");
+        skipped += 1;
+      },
+    }
+  }
+
+  // Tombstones, and records past their key's time to live, are dropped
+  let now = now_secs();
+  let live = latest.len();
+  let mut records: Vec<(usize, Record<K, V>)> = latest.into_iter()
+    .filter(|(_, (_, value, _))| value.is_some())
+    .filter(|(key, (_, _, written_at))| match time_to_live(key) {
+      Some(ttl) => now.saturating_sub(*written_at) < ttl.as_secs(),
+      None => true,
+    })
+    .map(|(key, (line_number, value, written_at))| (line_number, Record { key, value, written_at: Some(written_at) }))
+    .collect();
+  let dropped = live - records.len();
+  records.sort_by_key(|(line_number, _)| *line_number);
+
+  let mut compacted = String::new();
+  for (_, record) in &records {
+    compacted.push_str(&serde_json::to_string(record)?);
+    compacted.push('\n');
+  }
+
+  let temp = path.with_extension("compacting");
+  fs::write(&temp, &compacted).await?;
+  fs::rename(&temp, path).await?;
+  if skipped > 0 || dropped > 0 {
+    info!(path = %path.display(), skipped, dropped, "dropped cache records");
+  }
+
+  Ok((records.into_iter().map(|(_, record)| record).collect(), compacted.len() as u64))
+}
+
+#[async_trait]
+impl<K, V, C> CacheWrapper<K, V> for DiskCache<K, V, C>
+where
+  K: Serialize + DeserializeOwned + Hash + Eq + Send + Sync + 'static,
+  V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+  C: CacheWrapper<K, V>,
+{
+  async fn get(&self, key: &K) -> Option<V> {
+    self.inner.get(key).await
+  }
+
+  async fn lookup(&self, key: &K) -> Option<(V, Freshness)> {
+    self.inner.lookup(key).await
+  }
+
+  async fn insert(&self, key: K, value: V) {
+    if let Err(err) = self.append(&Record { key: &key, value: Some(&value), written_at: Some(now_secs()) }).await {
+      warn!(path = %self.path.display(), error = %err, "failed to persist cache entry");
+    }
+
+    self.inner.insert(key, value).await
+  }
+
+  async fn invalidate(&self, key: &K) {
+    if let Err(err) = self.append(&Record { key, value: None, written_at: Some(now_secs()) }).await {
+      warn!(path = %self.path.display(), error = %err, "failed to persist cache invalidation");
+    }
+
+    self.inner.invalidate(key).await
+  }
+
+  async fn invalidate_all(&self) {
+    if let Err(err) = self.truncate().await {
+      warn!(path = %self.path.display(), error = %err, "failed to truncate cache file");
+    }
+
+    self.inner.invalidate_all().await
+  }
+
+  fn keys(&self) -> Vec<K> {
+    self.inner.keys()
+  }
+
+  fn entry_count(&self) -> u64 {
+    self.inner.entry_count()
+  }
+}
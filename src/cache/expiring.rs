@@ -0,0 +1,120 @@
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use moka::future::{Cache, ConcurrentCacheExt};
+
+use crate::metrics::{self, CacheLabel, CacheOperation};
+use crate::util::{CacheWrapper, Freshness};
+
+/// An in-memory CacheWrapper with soft and hard expiry
+///
+/// Values are stamped with the time they were inserted. Past the soft time to
+/// live they are reported as stale, and past the hard time to live as
+/// expired, but they are kept until the underlying moka cache evicts them -
+/// so that an expired value can still be served should refreshing it fail.
+/// Values never go stale or expire unless the respective time to live is set.
+///
+/// Like `MokaCache`, hits, misses and inserts are counted in the exported
+/// cache metrics. Expired values count as misses.
+#[derive(Clone)]
+pub struct ExpiringCache<K: Hash + Eq + Send + Sync + 'static, V: Clone + Send + Sync + 'static> {
+  cache: Cache<K, (V, Instant)>,
+  soft_ttl: Option<Duration>,
+  hard_ttl: Option<Duration>,
+}
+
+impl<K, V> ExpiringCache<K, V>
+where
+  K: Hash + Eq + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+  /// Wrap a moka cache, whose own expiry and capacity decide when values are
+  /// evicted entirely.
+  pub fn new(cache: Cache<K, (V, Instant)>) -> Self {
+    Self {
+      cache,
+      soft_ttl: None,
+      hard_ttl: None,
+    }
+  }
+
+  /// Set the age past which values are stale.
+  pub fn soft_ttl(mut self, soft_ttl: Option<Duration>) -> Self {
+    self.soft_ttl = soft_ttl;
+    self
+  }
+
+  /// Set the age past which values are expired.
+  pub fn hard_ttl(mut self, hard_ttl: Option<Duration>) -> Self {
+    self.hard_ttl = hard_ttl;
+    self
+  }
+
+  fn freshness(&self, inserted: Instant) -> Freshness {
+    let age = inserted.elapsed();
+    let past = |ttl: Option<Duration>| match ttl {
+      Some(ttl) => age >= ttl,
+      None => false,
+    };
+
+    if past(self.hard_ttl) {
+      Freshness::Expired
+    } else if past(self.soft_ttl) {
+      Freshness::Stale
+    } else {
+      Freshness::Fresh
+    }
+  }
+}
+
+#[async_trait]
+impl<K, V> CacheWrapper<K, V> for ExpiringCache<K, V>
+where
+  K: CacheLabel + Clone + Hash + Eq + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+  async fn get(&self, key: &K) -> Option<V> {
+    match self.lookup(key).await {
+      Some((value, Freshness::Fresh)) | Some((value, Freshness::Stale)) => Some(value),
+      _ => None,
+    }
+  }
+
+  async fn lookup(&self, key: &K) -> Option<(V, Freshness)> {
+    let entry = self.cache.get(key)
+      .map(|(value, inserted)| (value, self.freshness(inserted)));
+
+    let operation = match entry {
+      Some((_, Freshness::Fresh)) | Some((_, Freshness::Stale)) => CacheOperation::Hit,
+      _ => CacheOperation::Miss,
+    };
+    metrics::record_cache(key, operation);
+
+    entry
+  }
+
+  async fn insert(&self, key: K, value: V) {
+    metrics::record_cache(&key, CacheOperation::Insert);
+    self.cache.insert(key, (value, Instant::now())).await
+  }
+
+  async fn invalidate(&self, key: &K) {
+    self.cache.invalidate(key).await
+  }
+
+  async fn invalidate_all(&self) {
+    self.cache.invalidate_all();
+  }
+
+  /// Includes the keys of expired values, which are still held
+  fn keys(&self) -> Vec<K> {
+    self.cache.iter().map(|(key, _)| K::clone(&key)).collect()
+  }
+
+  fn entry_count(&self) -> u64 {
+    // Moka applies writes lazily - flush them so the count is current
+    self.cache.sync();
+    self.cache.entry_count()
+  }
+}
@@ -0,0 +1,85 @@
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+
+use crate::metrics::CacheLabel;
+use crate::util::{CacheWrapper, Freshness, TranslationType};
+
+/// A CacheWrapper keeping untranslated and translated entries apart
+///
+/// Untranslated entries are cheap to replace, costing a single Pokeapi
+/// request, while translations spend scarce funtranslations quota. Holding
+/// them in separate caches, each with its own capacity and expiry, stops a
+/// flood of untranslated entries from evicting translations.
+pub struct PartitionedCache<K, V, C> {
+  untranslated: C,
+  translated: C,
+  _entries: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, C: Clone> Clone for PartitionedCache<K, V, C> {
+  fn clone(&self) -> Self {
+    Self {
+      untranslated: self.untranslated.clone(),
+      translated: self.translated.clone(),
+      _entries: PhantomData,
+    }
+  }
+}
+
+impl<K: CacheLabel, V, C> PartitionedCache<K, V, C> {
+  pub fn new(untranslated: C, translated: C) -> Self {
+    Self {
+      untranslated,
+      translated,
+      _entries: PhantomData,
+    }
+  }
+
+  fn partition(&self, key: &K) -> &C {
+    match key.translation_type() {
+      TranslationType::None => &self.untranslated,
+      TranslationType::Yoda | TranslationType::Shakespeare => &self.translated,
+    }
+  }
+}
+
+#[async_trait]
+impl<K, V, C> CacheWrapper<K, V> for PartitionedCache<K, V, C>
+where
+  K: CacheLabel + Hash + Eq + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+  C: CacheWrapper<K, V>,
+{
+  async fn get(&self, key: &K) -> Option<V> {
+    self.partition(key).get(key).await
+  }
+
+  async fn lookup(&self, key: &K) -> Option<(V, Freshness)> {
+    self.partition(key).lookup(key).await
+  }
+
+  async fn insert(&self, key: K, value: V) {
+    self.partition(&key).insert(key, value).await
+  }
+
+  async fn invalidate(&self, key: &K) {
+    self.partition(key).invalidate(key).await
+  }
+
+  async fn invalidate_all(&self) {
+    self.untranslated.invalidate_all().await;
+    self.translated.invalidate_all().await;
+  }
+
+  fn keys(&self) -> Vec<K> {
+    let mut keys = self.untranslated.keys();
+    keys.extend(self.translated.keys());
+    keys
+  }
+
+  fn entry_count(&self) -> u64 {
+    self.untranslated.entry_count() + self.translated.entry_count()
+  }
+}
@@ -0,0 +1,270 @@
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+/// Errors that can occur while talking to a Redis compatible store
+#[derive(Error, Debug)]
+pub enum RedisError {
+  #[error("Redis connection error: {0}")]
+  Io(#[from] io::Error),
+  #[error("Redis took too long to respond")]
+  Timeout,
+  #[error("Redis sent a malformed reply: {0}")]
+  Protocol(&'static str),
+  #[error("Redis returned an error: {0}")]
+  Server(String),
+  #[error("Redis is unavailable, request skipped")]
+  Unavailable,
+}
+
+/// A reply to a single command, limited to those the supported commands give
+enum Reply {
+  Simple,
+  Integer(i64),
+  Bulk(Option<Vec<u8>>),
+  Array(Vec<Reply>),
+}
+
+/// A minimal client for a Redis compatible store, speaking RESP over TCP
+///
+/// Only the handful of commands needed for caching are supported. Commands
+/// share a single connection, which is (re)established lazily.
+///
+/// Any connection failure or timeout marks the store as down for
+/// `retry_interval`, during which commands fail immediately with
+/// `RedisError::Unavailable` - so an outage costs callers nothing more than a
+/// missed cache lookup, rather than a timeout on every request.
+#[derive(Clone)]
+pub struct RedisStore {
+  address: String,
+  timeout: Duration,
+  retry_interval: Duration,
+  connection: Arc<Mutex<Option<BufStream<TcpStream>>>>,
+  down_until: Arc<std::sync::Mutex<Option<Instant>>>,
+}
+
+impl RedisStore {
+  /// Create a client for the store at `address`, eg: "localhost:6379".
+  ///
+  /// No connection is made until the first command is sent.
+  pub fn new(address: impl Into<String>) -> Self {
+    Self {
+      address: address.into(),
+      timeout: Duration::from_millis(250),
+      retry_interval: Duration::from_secs(5),
+      connection: Default::default(),
+      down_until: Default::default(),
+    }
+  }
+
+  /// Set the time allowed for each command, including connecting.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Set how long to skip the store for after it fails.
+  pub fn retry_interval(mut self, retry_interval: Duration) -> Self {
+    self.retry_interval = retry_interval;
+    self
+  }
+
+  /// Get the value stored under `key`, if there is one.
+  pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, RedisError> {
+    match self.command(&[b"GET", key.as_bytes()]).await? {
+      Reply::Bulk(value) => Ok(value),
+      _ => Err(RedisError::Protocol("expected a bulk string reply to GET")),
+    }
+  }
+
+  /// Store `value` under `key`, expiring after `time_to_live` if given.
+  pub async fn set(&self, key: &str, value: &[u8], time_to_live: Option<Duration>) -> Result<(), RedisError> {
+    let reply = match time_to_live {
+      Some(ttl) => {
+        let millis = ttl.as_millis().max(1).to_string();
+        self.command(&[b"SET", key.as_bytes(), value, b"PX", millis.as_bytes()]).await?
+      },
+      None => self.command(&[b"SET", key.as_bytes(), value]).await?,
+    };
+
+    match reply {
+      Reply::Simple => Ok(()),
+      _ => Err(RedisError::Protocol("expected a simple string reply to SET")),
+    }
+  }
+
+  /// Delete `keys`, returning how many of them existed.
+  pub async fn del(&self, keys: &[String]) -> Result<u64, RedisError> {
+    if keys.is_empty() {
+      return Ok(0)
+    }
+
+    let mut args: Vec<&[u8]> = vec![b"DEL"];
+    args.extend(keys.iter().map(|key| key.as_bytes()));
+    match self.command(&args).await? {
+      Reply::Integer(deleted) => Ok(deleted.max(0) as u64),
+      _ => Err(RedisError::Protocol("expected an integer reply to DEL")),
+    }
+  }
+
+  /// List all keys starting with `prefix`, iterating with SCAN so as not to
+  /// block the store.
+  pub async fn scan(&self, prefix: &str) -> Result<Vec<String>, RedisError> {
+    let mut pattern = String::new();
+    for c in prefix.chars() {
+      if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+        pattern.push('\\');
+      }
+      pattern.push(c);
+    }
+    pattern.push('*');
+
+    let mut keys = Vec::new();
+    let mut cursor = String::from("0");
+    loop {
+      let reply = self.command(&[b"SCAN", cursor.as_bytes(), b"MATCH", pattern.as_bytes(), b"COUNT", b"100"]).await?;
+      let (next, batch) = match reply {
+        Reply::Array(mut parts) if parts.len() == 2 => match (parts.remove(0), parts.remove(0)) {
+          (Reply::Bulk(Some(next)), Reply::Array(batch)) => (next, batch),
+          _ => return Err(RedisError::Protocol("expected a cursor and keys in reply to SCAN")),
+        },
+        _ => return Err(RedisError::Protocol("expected an array reply to SCAN")),
+      };
+
+      for key in batch {
+        match key {
+          Reply::Bulk(Some(key)) => keys.push(String::from_utf8_lossy(&key).into_owned()),
+          _ => return Err(RedisError::Protocol("expected bulk string keys in reply to SCAN")),
+        }
+      }
+
+      cursor = String::from_utf8_lossy(&next).into_owned();
+      if cursor == "0" {
+        return Ok(keys)
+      }
+    }
+  }
+
+  /// Check that the store can be reached.
+  pub async fn ping(&self) -> Result<(), RedisError> {
+    match self.command(&[b"PING"]).await? {
+      Reply::Simple => Ok(()),
+      _ => Err(RedisError::Protocol("expected a simple string reply to PING")),
+    }
+  }
+
+  fn is_down(&self) -> bool {
+    match *self.down_until.lock().unwrap() {
+      Some(until) => Instant::now() < until,
+      None => false,
+    }
+  }
+
+  async fn command(&self, args: &[&[u8]]) -> Result<Reply, RedisError> {
+    if self.is_down() {
+      return Err(RedisError::Unavailable)
+    }
+
+    // The connection is taken for the duration of the command, and only put
+    // back once a reply has been read in full - a command that fails, or is
+    // cancelled part way through, drops it rather than leave its reply to be
+    // read by the next command. Waiting for the connection counts towards
+    // the timeout, so callers queued behind a stalled command give up too.
+    let res = timeout(self.timeout, async {
+      let mut connection = self.connection.lock().await;
+      let mut stream = match connection.take() {
+        Some(stream) => stream,
+        None => BufStream::new(TcpStream::connect(&self.address).await?),
+      };
+
+      write_command(&mut stream, args).await?;
+      let reply = read_reply(&mut stream).await;
+      if matches!(reply, Ok(_) | Err(RedisError::Server(_))) {
+        *connection = Some(stream);
+      }
+      reply
+    }).await.unwrap_or(Err(RedisError::Timeout));
+
+    let mut down_until = self.down_until.lock().unwrap();
+    match &res {
+      // The store answered, so the connection is still usable
+      Ok(_) | Err(RedisError::Server(_)) => {
+        if down_until.take().is_some() {
+          info!(address = %self.address, "redis available again");
+        }
+      },
+      Err(err) => {
+        if down_until.is_none() {
+          warn!(address = %self.address, error = %err, "redis unavailable, falling back to in-process cache");
+        }
+        *down_until = Some(Instant::now() + self.retry_interval);
+      },
+    }
+
+    res
+  }
+}
+
+async fn write_command(stream: &mut BufStream<TcpStream>, args: &[&[u8]]) -> io::Result<()> {
+  stream.write_all(format!("*{}\r\n", args.len()).as_bytes()).await?;
+  for arg in args {
+    stream.write_all(format!("${}\r\n", arg.len()).as_bytes()).await?;
+    stream.write_all(arg).await?;
+    stream.write_all(b"\r\n").await?;
+  }
+  stream.flush().await
+}
+
+/// Read a single reply, boxed as arrays nest replies recursively
+fn read_reply(stream: &mut BufStream<TcpStream>) -> Pin<Box<dyn Future<Output = Result<Reply, RedisError>> + Send + '_>> {
+  Box::pin(async move {
+    let mut line = Vec::new();
+    if stream.read_until(b'\n', &mut line).await? == 0 {
+      return Err(io::Error::from(ErrorKind::UnexpectedEof).into())
+    }
+
+    let line = line.strip_suffix(b"\r\n").ok_or(RedisError::Protocol("unterminated line"))?;
+    let (kind, rest) = line.split_first().ok_or(RedisError::Protocol("empty line"))?;
+    let rest = String::from_utf8_lossy(rest);
+
+    match kind {
+      b'+' => Ok(Reply::Simple),
+      b'-' => Err(RedisError::Server(rest.into_owned())),
+      b':' => rest.parse().map(Reply::Integer).map_err(|_| RedisError::Protocol("invalid integer")),
+      b'$' => {
+        let len: i64 = rest.parse().map_err(|_| RedisError::Protocol("invalid bulk string length"))?;
+        if len < 0 {
+          return Ok(Reply::Bulk(None))
+        }
+
+        let mut value = vec![0; len as usize + 2];
+        stream.read_exact(&mut value).await?;
+        if !value.ends_with(b"\r\n") {
+          return Err(RedisError::Protocol("unterminated bulk string"))
+        }
+        value.truncate(len as usize);
+
+        Ok(Reply::Bulk(Some(value)))
+      },
+      b'*' => {
+        let len: i64 = rest.parse().map_err(|_| RedisError::Protocol("invalid array length"))?;
+        let mut items = Vec::with_capacity(len.max(0) as usize);
+        for _ in 0..len {
+          items.push(read_reply(stream).await?);
+        }
+
+        Ok(Reply::Array(items))
+      },
+      _ => Err(RedisError::Protocol("unsupported reply type")),
+    }
+  })
+}
@@ -0,0 +1,197 @@
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{debug, warn};
+
+use crate::cache::redis::{RedisError, RedisStore};
+use crate::util::{CacheWrapper, Freshness};
+
+/// A CacheWrapper layering an in-process cache (L1) over a shared Redis
+/// compatible store (L2)
+///
+/// Lookups read through: an L1 miss falls back to L2, and anything found
+/// there is copied into L1. Inserts write through to both. Values are stored
+/// in L2 as JSON, under the JSON encoded key with a prefix, so that several
+/// replicas can share the entries - and translation quota - of one another.
+///
+/// L2 failures are never surfaced to callers. While L2 is down the cache
+/// behaves as if it were L1 alone, see `RedisStore` for how outages are
+/// detected.
+pub struct TieredCache<K, V, C> {
+  l1: C,
+  l2: RedisStore,
+  prefix: String,
+  time_to_live: Option<Duration>,
+  _entries: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, C: Clone> Clone for TieredCache<K, V, C> {
+  fn clone(&self) -> Self {
+    Self {
+      l1: self.l1.clone(),
+      l2: self.l2.clone(),
+      prefix: self.prefix.clone(),
+      time_to_live: self.time_to_live,
+      _entries: PhantomData,
+    }
+  }
+}
+
+impl<K, V, C> TieredCache<K, V, C> {
+  /// Layer `l1` over `l2`, storing L2 entries under the "pokedex:" prefix
+  /// without expiry.
+  pub fn new(l1: C, l2: RedisStore) -> Self {
+    Self {
+      l1,
+      l2,
+      prefix: String::from("pokedex:"),
+      time_to_live: None,
+      _entries: PhantomData,
+    }
+  }
+
+  /// Set the prefix of keys stored in L2.
+  pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+    self.prefix = prefix.into();
+    self
+  }
+
+  /// Set how long entries live in L2 for after insertion.
+  pub fn time_to_live(mut self, time_to_live: Option<Duration>) -> Self {
+    self.time_to_live = time_to_live;
+    self
+  }
+}
+
+impl<K: Serialize, V, C> TieredCache<K, V, C> {
+  fn l2_key(&self, key: &K) -> Option<String> {
+    match serde_json::to_string(key) {
+      Ok(key) => Some(format!("{}{}", self.prefix, key)),
+      Err(err) => {
+        warn!(error = %err, "failed to encode cache key");
+        None
+      }
+    }
+  }
+}
+
+/// Log an L2 failure - outages are logged by the store itself
+fn log_l2_error(operation: &'static str, err: RedisError) {
+  match err {
+    RedisError::Unavailable => (),
+    err => debug!(operation, error = %err, "shared cache request failed"),
+  }
+}
+
+impl<K, V, C> TieredCache<K, V, C>
+where
+  K: Serialize + Clone + Hash + Eq + Send + Sync + 'static,
+  V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+  C: CacheWrapper<K, V>,
+{
+  /// Look a key up in L2, copying anything found into L1
+  async fn read_through(&self, key: &K) -> Option<V> {
+    let l2_key = self.l2_key(key)?;
+    let bytes = match self.l2.get(&l2_key).await {
+      Ok(bytes) => bytes?,
+      Err(err) => {
+        log_l2_error("get", err);
+        return None
+      }
+    };
+
+    match serde_json::from_slice::<V>(&bytes) {
+      Ok(value) => {
+        debug!(key = %l2_key, "filled in-process cache from shared cache");
+        self.l1.insert(key.clone(), value.clone()).await;
+        Some(value)
+      },
+      Err(err) => {
+        warn!(key = %l2_key, error = %err, "ignoring unreadable shared cache entry");
+        None
+      }
+    }
+  }
+}
+
+#[async_trait]
+impl<K, V, C> CacheWrapper<K, V> for TieredCache<K, V, C>
+where
+  K: Serialize + Clone + Hash + Eq + Send + Sync + 'static,
+  V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+  C: CacheWrapper<K, V>,
+{
+  async fn get(&self, key: &K) -> Option<V> {
+    if let Some(value) = self.l1.get(key).await {
+      return Some(value)
+    }
+
+    self.read_through(key).await
+  }
+
+  /// Values filled from L2 are treated as fresh, their age in L2 being bounded 
+  /// only by the time to live they were stored with. Values that have expired 
+  /// in L1 are returned as is, to be refreshed by the caller.
+  async fn lookup(&self, key: &K) -> Option<(V, Freshness)> {
+    if let Some(entry) = self.l1.lookup(key).await {
+      return Some(entry)
+    }
+
+    self.read_through(key).await.map(|value| (value, Freshness::Fresh))
+  }
+
+  async fn insert(&self, key: K, value: V) {
+    let l2_entry = self.l2_key(&key).and_then(|l2_key| match serde_json::to_vec(&value) {
+      Ok(bytes) => Some((l2_key, bytes)),
+      Err(err) => {
+        warn!(key = %l2_key, error = %err, "failed to encode shared cache entry");
+        None
+      }
+    });
+
+    self.l1.insert(key, value).await;
+
+    if let Some((l2_key, bytes)) = l2_entry {
+      if let Err(err) = self.l2.set(&l2_key, &bytes, self.time_to_live).await {
+        log_l2_error("set", err);
+      }
+    }
+  }
+
+  async fn invalidate(&self, key: &K) {
+    self.l1.invalidate(key).await;
+
+    if let Some(l2_key) = self.l2_key(key) {
+      if let Err(err) = self.l2.del(&[l2_key]).await {
+        log_l2_error("del", err);
+      }
+    }
+  }
+
+  /// Clears every entry under the prefix in L2, including those inserted by
+  /// other replicas.
+  async fn invalidate_all(&self) {
+    self.l1.invalidate_all().await;
+
+    let res = match self.l2.scan(&self.prefix).await {
+      Ok(l2_keys) => self.l2.del(&l2_keys).await.map(|_| ()),
+      Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+      log_l2_error("invalidate_all", err);
+    }
+  }
+
+  /// The keys held in L1
+  fn keys(&self) -> Vec<K> {
+    self.l1.keys()
+  }
+
+  /// The number of entries held in L1 - L2 may be shared with other replicas
+  fn entry_count(&self) -> u64 {
+    self.l1.entry_count()
+  }
+}
@@ -0,0 +1,884 @@
+use std::{fs::read_to_string, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use hyper::{StatusCode, http::uri::Authority};
+use serde::Deserialize;
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+
+use crate::api::API;
+use crate::breaker::CircuitBreaker;
+use crate::language::{self, DEFAULT_LANGUAGE};
+use crate::limiter::{Limit, RateLimiter};
+use crate::prewarm::Prewarmer;
+use crate::retry::RetryPolicy;
+use crate::translation::{local::LocalTranslator, TranslationBackend, TranslationRegistry};
+use crate::util::TranslationType;
+
+/// Typed configuration for the server
+///
+/// Values are layered, from lowest to highest precedence: built in defaults, a
+/// TOML file, environment variables, then command line flags. Any section or
+/// field missing from the TOML file falls back to its default, so an empty file
+/// is a valid configuration.
+///
+/// Call `validate` (or use `load`/`from_cli`, which do so for you) before using
+/// a configuration - serde will happily accept a zero sized cache.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub server: ServerConfig,
+  pub cache: CacheConfig,
+  pub pokeapi: UpstreamConfig,
+  pub funtranslations: UpstreamConfig,
+  pub translation: TranslationConfig,
+  pub language: LanguageConfig,
+  pub timeouts: TimeoutConfig,
+  pub retry: RetryConfig,
+  pub breaker: BreakerConfig,
+  pub limiter: LimiterConfig,
+  pub log: LogConfig,
+  pub admin: AdminConfig,
+  pub prewarm: PrewarmConfig,
+  pub mirror: MirrorConfig,
+}
+
+/// Public facing server settings
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+  /// Address and port the server will listen on
+  pub bind: SocketAddr,
+  /// Seconds allowed for in-flight requests to complete on shutdown
+  pub drain_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    Self {
+      bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+      drain_timeout_secs: 30,
+    }
+  }
+}
+
+impl ServerConfig {
+  /// Get the time allowed for in-flight requests to complete on shutdown.
+  pub fn drain_timeout(&self) -> Duration {
+    Duration::from_secs(self.drain_timeout_secs)
+  }
+}
+
+/// Response cache settings
+///
+/// Untranslated and translated responses are cached separately, so that cheap
+/// untranslated entries can't evict translations. The top level policy fields
+/// (`capacity` through `hard_ttl_secs`) apply to untranslated entries, while
+/// the `translated` section applies to translations.
+///
+/// The default capacities are set at 1000, as there are just under that many
+/// pokemon, with many significantly more popular than others. Entries never
+/// expire unless a time to live or time to idle is given.
+///
+/// Pokemon unknown to Pokeapi are remembered for `not_found_ttl_secs`, or not at
+/// all if zero.
+///
+/// The disk backend needs a `path` to persist entries to, and the redis backend
+/// the `redis_address` of the shared store. Any time to live also applies to
+/// entries in the shared store.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+  pub backend: CacheBackend,
+  pub path: Option<PathBuf>,
+  pub redis_address: Option<String>,
+  pub capacity: u64,
+  pub time_to_live_secs: Option<u64>,
+  pub time_to_idle_secs: Option<u64>,
+  pub soft_ttl_secs: Option<u64>,
+  pub hard_ttl_secs: Option<u64>,
+  pub translated: CachePolicy,
+  pub not_found_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self {
+      backend: CacheBackend::Memory,
+      path: None,
+      redis_address: None,
+      capacity: 1_000,
+      time_to_live_secs: None,
+      time_to_idle_secs: None,
+      soft_ttl_secs: None,
+      hard_ttl_secs: None,
+      translated: CachePolicy::default(),
+      not_found_ttl_secs: 60,
+    }
+  }
+}
+
+/// Capacity and expiry of one partition of the cache
+///
+/// Entries are evicted entirely once past their time to live or time to idle.
+/// Before that, once past their soft time to live, entries are served stale
+/// while being refreshed in the background, and once past their hard time to
+/// live, are refreshed before being served - unless refreshing fails, in which
+/// case they are still served stale.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CachePolicy {
+  pub capacity: u64,
+  pub time_to_live_secs: Option<u64>,
+  pub time_to_idle_secs: Option<u64>,
+  pub soft_ttl_secs: Option<u64>,
+  pub hard_ttl_secs: Option<u64>,
+}
+
+impl Default for CachePolicy {
+  fn default() -> Self {
+    Self {
+      capacity: 1_000,
+      time_to_live_secs: None,
+      time_to_idle_secs: None,
+      soft_ttl_secs: None,
+      hard_ttl_secs: None,
+    }
+  }
+}
+
+impl CachePolicy {
+  /// Get the configured time to live, if any.
+  pub fn time_to_live(&self) -> Option<Duration> {
+    self.time_to_live_secs.map(Duration::from_secs)
+  }
+
+  /// Get the configured time to idle, if any.
+  pub fn time_to_idle(&self) -> Option<Duration> {
+    self.time_to_idle_secs.map(Duration::from_secs)
+  }
+
+  /// Get the configured soft time to live, if any.
+  pub fn soft_ttl(&self) -> Option<Duration> {
+    self.soft_ttl_secs.map(Duration::from_secs)
+  }
+
+  /// Get the configured hard time to live, if any.
+  pub fn hard_ttl(&self) -> Option<Duration> {
+    self.hard_ttl_secs.map(Duration::from_secs)
+  }
+}
+
+/// Where cached responses are kept
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+  /// In memory only, lost on restart
+  Memory,
+  /// In memory, persisted to a file and restored on startup
+  Disk,
+  /// In memory, in front of a Redis compatible store shared between replicas
+  Redis,
+}
+
+impl CacheConfig {
+  /// Get how long unknown pokemon are remembered for, if at all.
+  pub fn not_found_ttl(&self) -> Option<Duration> {
+    match self.not_found_ttl_secs {
+      0 => None,
+      secs => Some(Duration::from_secs(secs)),
+    }
+  }
+
+  /// Get the policy for untranslated entries.
+  pub fn untranslated(&self) -> CachePolicy {
+    CachePolicy {
+      capacity: self.capacity,
+      time_to_live_secs: self.time_to_live_secs,
+      time_to_idle_secs: self.time_to_idle_secs,
+      soft_ttl_secs: self.soft_ttl_secs,
+      hard_ttl_secs: self.hard_ttl_secs,
+    }
+  }
+}
+
+/// Connection settings for an upstream API
+///
+/// When no host is given, the well known address for that API is used.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamConfig {
+  pub host: Option<String>,
+  pub https: bool,
+}
+
+impl Default for UpstreamConfig {
+  fn default() -> Self {
+    Self {
+      host: None,
+      https: true,
+    }
+  }
+}
+
+/// Timeouts applied to upstream requests, in milliseconds
+///
+/// `connect_ms` bounds establishing a connection, `read_ms` bounds waiting on
+/// the response, and `deadline_ms` bounds the request as a whole.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+  pub connect_ms: u64,
+  pub read_ms: u64,
+  pub deadline_ms: u64,
+}
+
+impl Default for TimeoutConfig {
+  fn default() -> Self {
+    Self {
+      connect_ms: 5_000,
+      read_ms: 10_000,
+      deadline_ms: 15_000,
+    }
+  }
+}
+
+impl TimeoutConfig {
+  pub fn connect(&self) -> Duration {
+    Duration::from_millis(self.connect_ms)
+  }
+
+  pub fn read(&self) -> Duration {
+    Duration::from_millis(self.read_ms)
+  }
+
+  pub fn deadline(&self) -> Duration {
+    Duration::from_millis(self.deadline_ms)
+  }
+}
+
+/// Retry policy for failed upstream requests
+///
+/// See `RetryPolicy` for how each of these is applied. A `max_attempts` of 1
+/// disables retries entirely.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+  pub max_attempts: u32,
+  pub base_delay_ms: u64,
+  pub max_delay_ms: u64,
+  pub jitter: f64,
+  pub retryable_statuses: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+  fn default() -> Self {
+    let policy = RetryPolicy::default();
+
+    Self {
+      max_attempts: policy.max_attempts,
+      base_delay_ms: policy.base_delay.as_millis() as u64,
+      max_delay_ms: policy.max_delay.as_millis() as u64,
+      jitter: policy.jitter,
+      retryable_statuses: policy.retryable_statuses.iter().map(StatusCode::as_u16).collect(),
+    }
+  }
+}
+
+impl RetryConfig {
+  /// Build the retry policy described by this configuration.
+  ///
+  /// Statuses that are not valid HTTP status codes are skipped - `validate`
+  /// reports them.
+  pub fn policy(&self) -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: self.max_attempts,
+      base_delay: Duration::from_millis(self.base_delay_ms),
+      max_delay: Duration::from_millis(self.max_delay_ms),
+      jitter: self.jitter,
+      retryable_statuses: self.retryable_statuses.iter()
+        .filter_map(|status| StatusCode::from_u16(*status).ok())
+        .collect(),
+    }
+  }
+}
+
+/// Circuit breaker guarding the funtranslations API
+///
+/// After `failure_threshold` consecutive failed translations, translation is
+/// skipped for `cool_down_secs` and untranslated descriptions are served.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreakerConfig {
+  pub enabled: bool,
+  pub failure_threshold: u32,
+  pub cool_down_secs: u64,
+}
+
+impl Default for BreakerConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      failure_threshold: 3,
+      cool_down_secs: 300,
+    }
+  }
+}
+
+impl BreakerConfig {
+  /// Build the circuit breaker described by this configuration.
+  pub fn breaker(&self) -> CircuitBreaker {
+    CircuitBreaker::new(self.failure_threshold, Duration::from_secs(self.cool_down_secs))
+  }
+}
+
+/// Client side rate limits on funtranslations requests, per translation type
+///
+/// Defaults to the public funtranslations tier of 5 requests an hour and 60 a
+/// day. An empty list of limits leaves that translation type unlimited.
+/// Requests that would have to wait longer than `max_wait_ms` for quota are
+/// rejected locally rather than queued.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimiterConfig {
+  pub max_wait_ms: u64,
+  pub yoda: Vec<LimitConfig>,
+  pub shakespeare: Vec<LimitConfig>,
+}
+
+/// A single quota of `requests` every `per_secs` seconds
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LimitConfig {
+  pub requests: u32,
+  pub per_secs: u64,
+}
+
+impl Default for LimiterConfig {
+  fn default() -> Self {
+    let public_tier = vec![
+      LimitConfig { requests: 5, per_secs: 60 * 60 },
+      LimitConfig { requests: 60, per_secs: 24 * 60 * 60 },
+    ];
+
+    Self {
+      max_wait_ms: 0,
+      yoda: public_tier.clone(),
+      shakespeare: public_tier,
+    }
+  }
+}
+
+impl LimiterConfig {
+  /// Build the rate limiter described by this configuration.
+  pub fn limiter(&self) -> RateLimiter {
+    let limits = self.yoda.iter().map(|limit| (TranslationType::Yoda, limit))
+      .chain(self.shakespeare.iter().map(|limit| (TranslationType::Shakespeare, limit)));
+
+    limits.fold(RateLimiter::new(Duration::from_millis(self.max_wait_ms)), |limiter, (translate_to, limit)| {
+      limiter.limit(translate_to, Limit {
+        requests: limit.requests,
+        per: Duration::from_secs(limit.per_secs),
+      })
+    })
+  }
+}
+
+/// Translation backends, per translation type
+///
+/// Each list names the backends tried, in order, until one translates the
+/// description. Listing `local` after `funtranslations` keeps translating once
+/// funtranslations is rate limited, rather than serving untranslated
+/// descriptions.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranslationConfig {
+  pub yoda: Vec<TranslatorKind>,
+  pub shakespeare: Vec<TranslatorKind>,
+}
+
+impl Default for TranslationConfig {
+  fn default() -> Self {
+    Self {
+      yoda: vec![TranslatorKind::Funtranslations],
+      shakespeare: vec![TranslatorKind::Funtranslations],
+    }
+  }
+}
+
+impl TranslationConfig {
+  /// Build a registry of these backends, translating remotely through `api`.
+  pub fn registry(&self, api: API) -> TranslationRegistry {
+    let backend = |kind: &TranslatorKind| -> Arc<dyn TranslationBackend> {
+      match kind {
+        TranslatorKind::Funtranslations => Arc::new(api.clone()),
+        TranslatorKind::Local => Arc::new(LocalTranslator::new()),
+      }
+    };
+
+    TranslationRegistry::new()
+      .chain(TranslationType::Yoda, self.yoda.iter().map(backend))
+      .chain(TranslationType::Shakespeare, self.shakespeare.iter().map(backend))
+  }
+}
+
+/// Description language settings
+///
+/// Requests may ask for descriptions in other languages, by a `lang` query
+/// parameter or an Accept-Language header. Pokemon without a description in
+/// any of those are described in the first of the `fallback` languages they
+/// have one in. Languages are named as by Pokeapi, such as "en" or "ja-Hrkt".
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageConfig {
+  pub fallback: Vec<String>,
+}
+
+impl Default for LanguageConfig {
+  fn default() -> Self {
+    Self {
+      fallback: vec![DEFAULT_LANGUAGE.to_owned()],
+    }
+  }
+}
+
+/// A translation backend
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslatorKind {
+  /// The funtranslations API, subject to its rate limits
+  Funtranslations,
+  /// Rule based translation within the server, see `translation::local`
+  Local,
+}
+
+/// Runtime log output settings
+///
+/// `level` is a tracing filter directive, such as "info" or
+/// "truelayer_coding_challenge=debug,warn".
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+  pub level: String,
+  pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+  fn default() -> Self {
+    Self {
+      level: String::from("info"),
+      format: LogFormat::Text,
+    }
+  }
+}
+
+/// Operator facing admin API settings
+///
+/// The admin routes are only served when a `token` is set, and then only to
+/// requests bearing it. Prefer setting it by environment variable over the
+/// config file or command line, where it may be read by others.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+  pub token: Option<String>,
+}
+
+/// Startup cache pre-warming settings
+///
+/// When enabled, every pokemon species is fetched and cached in the background
+/// on startup, `page_size` names of the species list at a time and
+/// `concurrency` species at once.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrewarmConfig {
+  pub enabled: bool,
+  pub page_size: u64,
+  pub concurrency: usize,
+}
+
+impl Default for PrewarmConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      page_size: 100,
+      concurrency: 4,
+    }
+  }
+}
+
+impl PrewarmConfig {
+  /// Build a crawler following these settings.
+  pub fn prewarmer(&self) -> Prewarmer {
+    Prewarmer::new()
+      .page_size(self.page_size)
+      .concurrency(self.concurrency)
+  }
+}
+
+/// Offline Pokeapi mirror settings
+///
+/// When a `path` is set, pokemon are served from the mirror in that directory
+/// rather than from Pokeapi, see `mirror::Mirror`. The `mirror` subcommand
+/// downloads into the same directory.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MirrorConfig {
+  pub path: Option<PathBuf>,
+}
+
+/// How log lines are written
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+  /// Human readable lines
+  Text,
+  /// One JSON object per line, for log aggregators
+  Json,
+}
+
+/// Command line flags, each of which may also be set by environment variable
+///
+/// Every flag is optional - anything left unset falls through to the TOML file
+/// and then to the defaults. Clap gives flags precedence over environment
+/// variables for us.
+#[derive(Parser, Debug, Default)]
+#[clap(about, version)]
+pub struct Cli {
+  #[clap(subcommand)]
+  pub command: Option<Command>,
+  /// Path to a TOML configuration file
+  #[clap(long, value_parser, env = "POKEDEX_CONFIG")]
+  pub config: Option<PathBuf>,
+  /// Address and port to listen on
+  #[clap(long, value_parser, env = "POKEDEX_BIND")]
+  pub bind: Option<SocketAddr>,
+  /// Seconds allowed for in-flight requests to complete on shutdown
+  #[clap(long, value_parser, env = "POKEDEX_DRAIN_TIMEOUT_SECS")]
+  pub drain_timeout_secs: Option<u64>,
+  /// Where cached responses are kept
+  #[clap(long, value_enum, value_parser, env = "POKEDEX_CACHE_BACKEND")]
+  pub cache_backend: Option<CacheBackend>,
+  /// File the disk cache backend persists entries to
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_PATH")]
+  pub cache_path: Option<PathBuf>,
+  /// Address of the store the redis cache backend shares entries through
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_REDIS_ADDRESS")]
+  pub cache_redis_address: Option<String>,
+  /// Maximum number of cached untranslated responses
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_CAPACITY")]
+  pub cache_capacity: Option<u64>,
+  /// Seconds a cached untranslated response lives for after insertion
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_TTL_SECS")]
+  pub cache_ttl_secs: Option<u64>,
+  /// Seconds a cached untranslated response lives for after it was last read
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_TTI_SECS")]
+  pub cache_tti_secs: Option<u64>,
+  /// Seconds after which a cached untranslated response is refreshed in the background
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_SOFT_TTL_SECS")]
+  pub cache_soft_ttl_secs: Option<u64>,
+  /// Seconds after which a cached untranslated response is refreshed before serving
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_HARD_TTL_SECS")]
+  pub cache_hard_ttl_secs: Option<u64>,
+  /// Seconds unknown pokemon are remembered for, zero to disable
+  #[clap(long, value_parser, env = "POKEDEX_CACHE_NOT_FOUND_TTL_SECS")]
+  pub cache_not_found_ttl_secs: Option<u64>,
+  /// Maximum number of cached translated responses
+  #[clap(long, value_parser, env = "POKEDEX_TRANSLATED_CACHE_CAPACITY")]
+  pub translated_cache_capacity: Option<u64>,
+  /// Seconds a cached translated response lives for after insertion
+  #[clap(long, value_parser, env = "POKEDEX_TRANSLATED_CACHE_TTL_SECS")]
+  pub translated_cache_ttl_secs: Option<u64>,
+  /// Seconds a cached translated response lives for after it was last read
+  #[clap(long, value_parser, env = "POKEDEX_TRANSLATED_CACHE_TTI_SECS")]
+  pub translated_cache_tti_secs: Option<u64>,
+  /// Seconds after which a cached translated response is refreshed in the background
+  #[clap(long, value_parser, env = "POKEDEX_TRANSLATED_CACHE_SOFT_TTL_SECS")]
+  pub translated_cache_soft_ttl_secs: Option<u64>,
+  /// Seconds after which a cached translated response is refreshed before serving
+  #[clap(long, value_parser, env = "POKEDEX_TRANSLATED_CACHE_HARD_TTL_SECS")]
+  pub translated_cache_hard_ttl_secs: Option<u64>,
+  /// Host (authority) to contact instead of pokeapi.co
+  #[clap(long, value_parser, env = "POKEDEX_POKEAPI_HOST")]
+  pub pokeapi_host: Option<String>,
+  /// Whether to contact pokeapi over https
+  #[clap(long, value_parser, env = "POKEDEX_POKEAPI_HTTPS")]
+  pub pokeapi_https: Option<bool>,
+  /// Host (authority) to contact instead of api.funtranslations.com
+  #[clap(long, value_parser, env = "POKEDEX_FUNTRANSLATIONS_HOST")]
+  pub funtranslations_host: Option<String>,
+  /// Whether to contact funtranslations over https
+  #[clap(long, value_parser, env = "POKEDEX_FUNTRANSLATIONS_HTTPS")]
+  pub funtranslations_https: Option<bool>,
+  /// Comma separated backends tried, in order, for yoda translations
+  #[clap(long, value_enum, value_parser, value_delimiter = ',', env = "POKEDEX_YODA_TRANSLATORS")]
+  pub yoda_translators: Option<Vec<TranslatorKind>>,
+  /// Comma separated backends tried, in order, for shakespeare translations
+  #[clap(long, value_enum, value_parser, value_delimiter = ',', env = "POKEDEX_SHAKESPEARE_TRANSLATORS")]
+  pub shakespeare_translators: Option<Vec<TranslatorKind>>,
+  /// Comma separated languages descriptions fall back to, in order
+  #[clap(long, value_parser, value_delimiter = ',', env = "POKEDEX_FALLBACK_LANGUAGES")]
+  pub fallback_languages: Option<Vec<String>>,
+  /// Milliseconds allowed to establish an upstream connection
+  #[clap(long, value_parser, env = "POKEDEX_CONNECT_TIMEOUT_MS")]
+  pub connect_timeout_ms: Option<u64>,
+  /// Milliseconds allowed waiting on an upstream response
+  #[clap(long, value_parser, env = "POKEDEX_READ_TIMEOUT_MS")]
+  pub read_timeout_ms: Option<u64>,
+  /// Milliseconds allowed for an upstream request as a whole
+  #[clap(long, value_parser, env = "POKEDEX_DEADLINE_MS")]
+  pub deadline_ms: Option<u64>,
+  /// Maximum attempts made for each upstream request, including the first
+  #[clap(long, value_parser, env = "POKEDEX_RETRY_MAX_ATTEMPTS")]
+  pub retry_max_attempts: Option<u32>,
+  /// Log filter directive, overridden by RUST_LOG when set
+  #[clap(long, value_parser, env = "POKEDEX_LOG_LEVEL")]
+  pub log_level: Option<String>,
+  /// Log output format
+  #[clap(long, value_enum, value_parser, env = "POKEDEX_LOG_FORMAT")]
+  pub log_format: Option<LogFormat>,
+  /// Bearer token required by the admin API, which is disabled when unset
+  #[clap(long, value_parser, env = "POKEDEX_ADMIN_TOKEN", hide_env_values = true)]
+  pub admin_token: Option<String>,
+  /// Whether to cache every pokemon species in the background on startup
+  #[clap(long, value_parser, env = "POKEDEX_PREWARM")]
+  pub prewarm: Option<bool>,
+  /// Maximum number of species fetched at once while pre-warming
+  #[clap(long, value_parser, env = "POKEDEX_PREWARM_CONCURRENCY")]
+  pub prewarm_concurrency: Option<usize>,
+  /// Directory of an offline Pokeapi mirror to serve pokemon from
+  #[clap(long, value_parser, env = "POKEDEX_MIRROR_PATH")]
+  pub mirror_path: Option<PathBuf>,
+}
+
+/// Tasks to run instead of the server
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Download every species from Pokeapi into the mirror at `mirror.path`,
+  /// replacing any already there
+  Mirror {
+    /// Maximum number of species downloaded at once
+    #[clap(long, value_parser, default_value_t = 8)]
+    concurrency: usize,
+  },
+}
+
+/// Errors that can occur while loading or validating configuration
+#[derive(Error, Debug)]
+pub enum ConfigError {
+  #[error("Failed to read config file {0}: {1}")]
+  Io(PathBuf, std::io::Error),
+  #[error("Failed to parse config file: {0}")]
+  Parse(#[from] toml::de::Error),
+  #[error("Invalid configuration: `{field}` {reason}")]
+  Invalid {
+    field: &'static str,
+    reason: &'static str,
+  },
+}
+
+impl FromStr for Config {
+  type Err = ConfigError;
+
+  /// Parse a configuration from the contents of a TOML file.
+  fn from_str(toml: &str) -> Result<Self, Self::Err> {
+    Ok(toml::from_str(toml)?)
+  }
+}
+
+impl Config {
+  /// Load configuration from the process' arguments, environment and any
+  /// config file they point to.
+  pub fn load() -> Result<Self, ConfigError> {
+    Self::from_cli(Cli::parse())
+  }
+
+  /// Build a configuration from already parsed flags.
+  ///
+  /// Reads the TOML file named by the flags if there is one, then applies the
+  /// flags over the top and validates the result.
+  pub fn from_cli(cli: Cli) -> Result<Self, ConfigError> {
+    let mut config = match &cli.config {
+      Some(path) => read_to_string(path)
+        .map_err(|err| ConfigError::Io(path.clone(), err))?
+        .parse()?,
+      None => Config::default(),
+    };
+
+    config.apply(cli);
+    config.validate()?;
+
+    Ok(config)
+  }
+
+  /// Override any values set by flags or environment variables.
+  fn apply(&mut self, cli: Cli) {
+    fn set<T>(target: &mut T, value: Option<T>) {
+      if let Some(value) = value {
+        *target = value;
+      }
+    }
+
+    set(&mut self.server.bind, cli.bind);
+    set(&mut self.server.drain_timeout_secs, cli.drain_timeout_secs);
+    set(&mut self.cache.backend, cli.cache_backend);
+    set(&mut self.cache.path, cli.cache_path.map(Some));
+    set(&mut self.cache.redis_address, cli.cache_redis_address.map(Some));
+    set(&mut self.cache.capacity, cli.cache_capacity);
+    set(&mut self.cache.time_to_live_secs, cli.cache_ttl_secs.map(Some));
+    set(&mut self.cache.time_to_idle_secs, cli.cache_tti_secs.map(Some));
+    set(&mut self.cache.soft_ttl_secs, cli.cache_soft_ttl_secs.map(Some));
+    set(&mut self.cache.hard_ttl_secs, cli.cache_hard_ttl_secs.map(Some));
+    set(&mut self.cache.not_found_ttl_secs, cli.cache_not_found_ttl_secs);
+    set(&mut self.cache.translated.capacity, cli.translated_cache_capacity);
+    set(&mut self.cache.translated.time_to_live_secs, cli.translated_cache_ttl_secs.map(Some));
+    set(&mut self.cache.translated.time_to_idle_secs, cli.translated_cache_tti_secs.map(Some));
+    set(&mut self.cache.translated.soft_ttl_secs, cli.translated_cache_soft_ttl_secs.map(Some));
+    set(&mut self.cache.translated.hard_ttl_secs, cli.translated_cache_hard_ttl_secs.map(Some));
+    set(&mut self.pokeapi.host, cli.pokeapi_host.map(Some));
+    set(&mut self.pokeapi.https, cli.pokeapi_https);
+    set(&mut self.funtranslations.host, cli.funtranslations_host.map(Some));
+    set(&mut self.funtranslations.https, cli.funtranslations_https);
+    set(&mut self.translation.yoda, cli.yoda_translators);
+    set(&mut self.translation.shakespeare, cli.shakespeare_translators);
+    set(&mut self.language.fallback, cli.fallback_languages);
+    set(&mut self.timeouts.connect_ms, cli.connect_timeout_ms);
+    set(&mut self.timeouts.read_ms, cli.read_timeout_ms);
+    set(&mut self.timeouts.deadline_ms, cli.deadline_ms);
+    set(&mut self.retry.max_attempts, cli.retry_max_attempts);
+    set(&mut self.log.level, cli.log_level);
+    set(&mut self.log.format, cli.log_format);
+    set(&mut self.admin.token, cli.admin_token.map(Some));
+    set(&mut self.prewarm.enabled, cli.prewarm);
+    set(&mut self.prewarm.concurrency, cli.prewarm_concurrency);
+    set(&mut self.mirror.path, cli.mirror_path.map(Some));
+  }
+
+  /// Check that the configuration is usable, reporting the first problem found.
+  pub fn validate(&self) -> Result<(), ConfigError> {
+    fn invalid(field: &'static str, reason: &'static str) -> Result<(), ConfigError> {
+      Err(ConfigError::Invalid { field, reason })
+    }
+
+    /// Check a cache policy, whose fields are named in declaration order
+    fn validate_policy(policy: &CachePolicy, fields: [&'static str; 5]) -> Result<(), ConfigError> {
+      let [capacity, time_to_live, time_to_idle, soft_ttl, hard_ttl] = fields;
+
+      if policy.capacity == 0 {
+        return invalid(capacity, "must be greater than zero")
+      }
+      for (field, value) in [
+        (time_to_live, policy.time_to_live_secs),
+        (time_to_idle, policy.time_to_idle_secs),
+        (soft_ttl, policy.soft_ttl_secs),
+        (hard_ttl, policy.hard_ttl_secs),
+      ] {
+        if value == Some(0) {
+          return invalid(field, "must be greater than zero when set")
+        }
+      }
+      if let (Some(soft), Some(hard)) = (policy.soft_ttl_secs, policy.hard_ttl_secs) {
+        if soft > hard {
+          return invalid(soft_ttl, "must not be greater than the hard time to live")
+        }
+      }
+
+      Ok(())
+    }
+
+    fn valid_host(host: &Option<String>) -> bool {
+      match host {
+        Some(host) => host.parse::<Authority>().is_ok(),
+        None => true,
+      }
+    }
+
+    if self.cache.backend == CacheBackend::Disk && self.cache.path.is_none() {
+      return invalid("cache.path", "must be set when using the disk backend")
+    }
+    if self.cache.backend == CacheBackend::Redis && self.cache.redis_address.is_none() {
+      return invalid("cache.redis_address", "must be set when using the redis backend")
+    }
+    validate_policy(&self.cache.untranslated(), [
+      "cache.capacity",
+      "cache.time_to_live_secs",
+      "cache.time_to_idle_secs",
+      "cache.soft_ttl_secs",
+      "cache.hard_ttl_secs",
+    ])?;
+    validate_policy(&self.cache.translated, [
+      "cache.translated.capacity",
+      "cache.translated.time_to_live_secs",
+      "cache.translated.time_to_idle_secs",
+      "cache.translated.soft_ttl_secs",
+      "cache.translated.hard_ttl_secs",
+    ])?;
+    if !valid_host(&self.pokeapi.host) {
+      return invalid("pokeapi.host", "must be a bare host and optional port, eg: localhost:8000")
+    }
+    if !valid_host(&self.funtranslations.host) {
+      return invalid("funtranslations.host", "must be a bare host and optional port, eg: localhost:8000")
+    }
+    if self.translation.yoda.is_empty() {
+      return invalid("translation.yoda", "must name at least one backend")
+    }
+    if self.translation.shakespeare.is_empty() {
+      return invalid("translation.shakespeare", "must name at least one backend")
+    }
+    if self.language.fallback.is_empty() {
+      return invalid("language.fallback", "must name at least one language")
+    }
+    if !self.language.fallback.iter().all(|tag| language::is_valid_tag(tag)) {
+      return invalid("language.fallback", "must only contain language tags, eg: en or ja-Hrkt")
+    }
+    if self.timeouts.connect_ms == 0 {
+      return invalid("timeouts.connect_ms", "must be greater than zero")
+    }
+    if self.timeouts.read_ms == 0 {
+      return invalid("timeouts.read_ms", "must be greater than zero")
+    }
+    if self.timeouts.deadline_ms < self.timeouts.connect_ms.max(self.timeouts.read_ms) {
+      return invalid("timeouts.deadline_ms", "must be at least as long as the connect and read timeouts")
+    }
+    if self.retry.max_attempts == 0 {
+      return invalid("retry.max_attempts", "must be at least one")
+    }
+    if self.retry.base_delay_ms > self.retry.max_delay_ms {
+      return invalid("retry.base_delay_ms", "must not be greater than retry.max_delay_ms")
+    }
+    if !(0.0..=1.0).contains(&self.retry.jitter) {
+      return invalid("retry.jitter", "must be between 0 and 1")
+    }
+    for status in &self.retry.retryable_statuses {
+      match StatusCode::from_u16(*status) {
+        Err(_) => return invalid("retry.retryable_statuses", "must only contain valid HTTP status codes"),
+        Ok(status) if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS => {
+          return invalid("retry.retryable_statuses", "must not contain 4xx statuses other than 429")
+        },
+        Ok(_) => (),
+      }
+    }
+    if self.breaker.failure_threshold == 0 {
+      return invalid("breaker.failure_threshold", "must be greater than zero")
+    }
+    if self.limiter.yoda.iter().any(|limit| limit.requests == 0 || limit.per_secs == 0) {
+      return invalid("limiter.yoda", "limits must have non-zero requests and per_secs")
+    }
+    if self.limiter.shakespeare.iter().any(|limit| limit.requests == 0 || limit.per_secs == 0) {
+      return invalid("limiter.shakespeare", "limits must have non-zero requests and per_secs")
+    }
+    if EnvFilter::try_new(&self.log.level).is_err() {
+      return invalid("log.level", "must be a valid filter directive, eg: info")
+    }
+    if matches!(self.admin.token.as_deref(), Some(token) if token.trim().is_empty()) {
+      return invalid("admin.token", "must not be empty when set")
+    }
+    if self.prewarm.page_size == 0 {
+      return invalid("prewarm.page_size", "must be greater than zero")
+    }
+    if self.prewarm.concurrency == 0 {
+      return invalid("prewarm.concurrency", "must be greater than zero")
+    }
+
+    Ok(())
+  }
+}
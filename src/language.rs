@@ -0,0 +1,89 @@
+use crate::util::PokError;
+
+/// Language of descriptions served when none is asked for
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Most languages taken from a single request, ahead of the fallbacks
+///
+/// Languages are part of the cache key, so this bounds how many entries a
+/// client can create for one pokemon.
+pub const MAX_REQUESTED_LANGUAGES: usize = 4;
+
+/// Whether a language tag is well formed, such as "en" or "ja-Hrkt"
+pub fn is_valid_tag(tag: &str) -> bool {
+  !tag.is_empty()
+    && tag.len() <= 35
+    && tag.split('-').all(|subtag| !subtag.is_empty() && subtag.len() <= 8 && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Parse an Accept-Language header into its languages, most preferred first
+///
+/// Languages are ordered by quality, keeping header order between equals.
+/// Wildcards, malformed entries and those with a quality of zero are dropped.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+  let mut languages: Vec<(String, f32)> = header.split(',')
+    .filter_map(|entry| {
+      let mut parts = entry.split(';').map(str::trim);
+      let tag = parts.next()?;
+      let quality = parts
+        .find_map(|param| param.strip_prefix("q="))
+        .map_or(Some(1.0), |quality| quality.parse::<f32>().ok())?;
+
+      if tag == "*" || !is_valid_tag(tag) || quality <= 0.0 {
+        return None
+      }
+      Some((tag.to_lowercase(), quality))
+    })
+    .collect();
+
+  // Stable, so equally preferred languages keep their order
+  languages.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+  languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Choose the languages to look for a description in, most preferred first
+///
+/// A `lang` query parameter, itself a comma separated list, takes precedence
+/// over the Accept-Language header. At most `MAX_REQUESTED_LANGUAGES` are
+/// taken from either, followed by every `fallback` not already present.
+///
+/// Fails with `PokError::InvalidLanguage` should `lang` hold a malformed tag.
+pub fn negotiate(lang: Option<&str>, accept_language: Option<&str>, fallback: &[String]) -> Result<Vec<String>, PokError> {
+  let requested = match lang {
+    Some(lang) => {
+      let tags: Vec<String> = lang.split(',').map(|tag| tag.trim().to_lowercase()).collect();
+      if let Some(tag) = tags.iter().find(|tag| !is_valid_tag(tag)) {
+        return Err(PokError::InvalidLanguage(tag.clone()))
+      }
+      tags
+    },
+    None => accept_language.map(parse_accept_language).unwrap_or_default(),
+  };
+
+  let mut languages: Vec<String> = Vec::new();
+  for tag in requested {
+    if languages.len() == MAX_REQUESTED_LANGUAGES {
+      break
+    }
+    if !languages.contains(&tag) {
+      languages.push(tag);
+    }
+  }
+  for tag in fallback {
+    let tag = tag.to_lowercase();
+    if !languages.contains(&tag) {
+      languages.push(tag);
+    }
+  }
+
+  Ok(languages)
+}
+
+/// Whether `available`, as named by Pokeapi, satisfies the `wanted` language
+///
+/// Tags are compared ignoring case, and a regional tag such as "en-GB" is
+/// satisfied by its primary language, "en".
+pub fn matches(wanted: &str, available: &str) -> bool {
+  wanted.eq_ignore_ascii_case(available)
+    || matches!(wanted.split_once('-'), Some((primary, _)) if primary.eq_ignore_ascii_case(available))
+}
@@ -6,7 +6,22 @@
 //! by Moka. Utilises async/await where possible on a tokio runtime - runtime 
 //! provided by included libraries.
 
+pub mod config;
 pub mod util;
+pub mod cache;
 pub mod models;
 pub mod api;
-pub mod server;
\ No newline at end of file
+pub mod retry;
+pub mod breaker;
+pub mod limiter;
+pub mod logging;
+pub mod metrics;
+pub mod server;
+pub mod shutdown;
+pub mod singleflight;
+pub mod species;
+pub mod admin;
+pub mod prewarm;
+pub mod mirror;
+pub mod translation;
+pub mod language;
\ No newline at end of file
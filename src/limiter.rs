@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::util::{PokError, TranslationType};
+
+/// A quota of `requests` allowed every `per`
+///
+/// Tokens refill continuously rather than all at once at the end of each
+/// period, so a limit of 5 per hour allows a request every 12 minutes once the
+/// initial burst of 5 has been spent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+  pub requests: u32,
+  pub per: Duration,
+}
+
+struct Bucket {
+  limit: Limit,
+  tokens: f64,
+  last_refill: Instant,
+}
+
+impl Bucket {
+  fn new(limit: Limit) -> Self {
+    Self {
+      limit,
+      tokens: limit.requests as f64,
+      last_refill: Instant::now(),
+    }
+  }
+
+  fn refill(&mut self, now: Instant) {
+    let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+    let rate = self.limit.requests as f64 / self.limit.per.as_secs_f64();
+
+    self.tokens = (self.tokens + elapsed * rate).min(self.limit.requests as f64);
+    self.last_refill = now;
+  }
+
+  /// Time until a whole token will be available.
+  fn wait(&self) -> Duration {
+    if self.tokens >= 1.0 {
+      Duration::ZERO
+    } else {
+      self.limit.per.mul_f64((1.0 - self.tokens) / self.limit.requests as f64)
+    }
+  }
+}
+
+/// A client side token bucket rate limiter, keyed by translation type
+///
+/// Each translation type may have any number of limits (eg: an hourly and a
+/// daily quota), all of which must have a token free for a request to go
+/// ahead. Types without any limits are never limited.
+///
+/// When a request would have to wait longer than `max_wait` it is rejected
+/// with `PokError::RateLimited`. Shorter waits are queued: the tokens are taken
+/// immediately, leaving the bucket in debt, and the caller sleeps until they
+/// would have been available. This keeps queued requests in order.
+///
+/// Clones share state, so a limiter can be cloned into each request handler.
+#[derive(Clone)]
+pub struct RateLimiter {
+  buckets: Arc<Mutex<HashMap<TranslationType, Vec<Bucket>>>>,
+  max_wait: Duration,
+}
+
+impl Default for RateLimiter {
+  fn default() -> Self {
+    Self::new(Duration::ZERO)
+  }
+}
+
+impl RateLimiter {
+  pub fn new(max_wait: Duration) -> Self {
+    Self {
+      buckets: Default::default(),
+      max_wait,
+    }
+  }
+
+  /// Add a limit for the given translation type
+  pub fn limit(self, translate_to: TranslationType, limit: Limit) -> Self {
+    self.buckets.lock().unwrap()
+      .entry(translate_to)
+      .or_default()
+      .push(Bucket::new(limit));
+    self
+  }
+
+  /// Take a token for the given translation type, waiting for one to become
+  /// free if that can be done within `max_wait`.
+  pub async fn acquire(&self, translate_to: TranslationType) -> Result<(), PokError> {
+    let wait = {
+      let mut buckets = self.buckets.lock().unwrap();
+      let buckets = match buckets.get_mut(&translate_to) {
+        Some(buckets) => buckets,
+        None => return Ok(()),
+      };
+
+      let now = Instant::now();
+      let wait = buckets.iter_mut()
+        .map(|bucket| {
+          bucket.refill(now);
+          bucket.wait()
+        })
+        .max()
+        .unwrap_or(Duration::ZERO);
+
+      if wait > self.max_wait {
+        return Err(PokError::RateLimited)
+      }
+
+      for bucket in buckets.iter_mut() {
+        bucket.tokens -= 1.0;
+      }
+
+      wait
+    };
+
+    if !wait.is_zero() {
+      tokio::time::sleep(wait).await;
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,23 @@
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogConfig, LogFormat};
+
+/// Install the global tracing subscriber
+///
+/// The `RUST_LOG` environment variable, when set, takes precedence over the
+/// configured level, allowing per-module filtering such as
+/// `RUST_LOG=truelayer_coding_challenge=debug,hyper=info`.
+///
+/// Panics if a global subscriber has already been installed.
+pub fn init(config: &LogConfig) {
+  let filter = EnvFilter::try_from_default_env()
+    .unwrap_or_else(|_| EnvFilter::new(&config.level));
+
+  let subscriber = tracing_subscriber::fmt()
+    .with_env_filter(filter);
+
+  match config.format {
+    LogFormat::Text => subscriber.init(),
+    LogFormat::Json => subscriber.json().flatten_event(true).init(),
+  }
+}
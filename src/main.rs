@@ -1,29 +1,162 @@
+use std::process::exit;
+
+use clap::Parser;
 use moka::future::Cache;
+use tracing::{error, info};
 
 extern crate truelayer_coding_challenge;
 
 use truelayer_coding_challenge::{
-  util::{TranslationType, MokaCache},
+  util::{PokeClient, TranslationClient, CacheKey, CacheWrapper, TranslationType},
   models::poke_models::PokemonResponse,
   api::API,
-  server::router,
+  cache::{disk::{DiskCache, DiskOptions}, expiring::ExpiringCache, partitioned::PartitionedCache, redis::RedisStore, tiered::TieredCache},
+  config::{CacheBackend, CachePolicy, Cli, Command, Config},
+  logging,
+  mirror::{self, Mirror},
+  server::{router_with_options, RouterOptions},
+  shutdown,
+  species::SpeciesCache,
 };
 
 #[tokio::main]
 async fn main() {
-  run().await
+  let mut cli = Cli::parse();
+  let command = cli.command.take();
+
+  let config = match Config::from_cli(cli) {
+    Ok(config) => config,
+    Err(err) => {
+      eprintln!("{}", err);
+      exit(1)
+    }
+  };
+
+  logging::init(&config.log);
+  match command {
+    Some(Command::Mirror { concurrency }) => download_mirror(config, concurrency).await,
+    None => run(config).await,
+  }
+}
+
+/// Download or refresh the offline Pokeapi mirror
+async fn download_mirror(config: Config, concurrency: usize) {
+  let path = match &config.mirror.path {
+    Some(path) => path,
+    None => {
+      error!("`mirror.path` must be set to download a mirror");
+      exit(1)
+    }
+  };
+
+  match mirror::download(&API::from_config(&config), path, concurrency).await {
+    Ok(report) if report.failed == 0 => info!(path = %path.display(), downloaded = report.downloaded, "downloaded mirror"),
+    Ok(report) => {
+      error!(path = %path.display(), downloaded = report.downloaded, failed = report.failed, "failed to download some species");
+      exit(1)
+    },
+    Err(err) => {
+      error!(path = %path.display(), error = %err, "failed to download mirror");
+      exit(1)
+    }
+  }
 }
 
-pub async fn run() {
-  // Cache size set at 1000, as there are just under that many pokemon, with many significantly more popular than others.
-  // Additional testing would be required to determine optimal memory/latency settings.
-  let cache: MokaCache<(String, TranslationType), PokemonResponse> = MokaCache(Cache::new(1_000));
-  let api = API::new();
-  let poke_client = api.clone();
-  let translation_client = api.clone();
-
-  println!("Starting server on port 8080");
-  warp::serve(router(poke_client, translation_client, cache))
-    .run(([0, 0, 0, 0], 8080))
-    .await;
+pub async fn run(config: Config) {
+  let untranslated = moka(&config.cache.untranslated());
+  let translated = moka(&config.cache.translated);
+
+  match (config.cache.backend, &config.cache.path, &config.cache.redis_address) {
+    (CacheBackend::Disk, Some(path), _) => {
+      // Records are only restored while they would still be cached in memory
+      let (untranslated_ttl, translated_ttl) = (config.cache.untranslated().time_to_live(), config.cache.translated.time_to_live());
+      let options = DiskOptions::default().time_to_live(move |key: &CacheKey| match key.translation_type {
+        TranslationType::None => untranslated_ttl,
+        TranslationType::Yoda | TranslationType::Shakespeare => translated_ttl,
+      });
+      match DiskCache::open_with(path, PartitionedCache::new(untranslated, translated), options).await {
+        Ok(cache) => serve(&config, cache).await,
+        Err(err) => {
+          error!(path = %path.display(), error = %err, "failed to open disk cache");
+          exit(1)
+        }
+      }
+    },
+    (CacheBackend::Redis, _, Some(address)) => {
+      // Both partitions share one store, but expire entries in it by their own policy
+      let store = RedisStore::new(address.as_str());
+      let cache = PartitionedCache::new(
+        TieredCache::new(untranslated, store.clone()).time_to_live(config.cache.untranslated().time_to_live()),
+        TieredCache::new(translated, store).time_to_live(config.cache.translated.time_to_live()),
+      );
+      serve(&config, cache).await
+    },
+    _ => serve(&config, PartitionedCache::new(untranslated, translated)).await,
+  }
+}
+
+/// Build an in-memory cache following the given policy
+fn moka(policy: &CachePolicy) -> ExpiringCache<CacheKey, PokemonResponse> {
+  let mut builder = Cache::builder().max_capacity(policy.capacity);
+  if let Some(ttl) = policy.time_to_live() {
+    builder = builder.time_to_live(ttl);
+  }
+  if let Some(tti) = policy.time_to_idle() {
+    builder = builder.time_to_idle(tti);
+  }
+
+  ExpiringCache::new(builder.build())
+    .soft_ttl(policy.soft_ttl())
+    .hard_ttl(policy.hard_ttl())
+}
+
+async fn serve<C: CacheWrapper<CacheKey, PokemonResponse>>(config: &Config, cache: C) {
+  let api = API::from_config(config);
+  let translators = config.translation.registry(api.clone());
+
+  match &config.mirror.path {
+    Some(path) => match Mirror::open(path).await {
+      Ok(mirror) => serve_with(config, mirror, translators, cache).await,
+      Err(err) => {
+        error!(path = %path.display(), error = %err, "failed to open pokeapi mirror");
+        exit(1)
+      }
+    },
+    None => serve_with(config, api, translators, cache).await,
+  }
+}
+
+async fn serve_with<C: CacheWrapper<CacheKey, PokemonResponse>>(
+  config: &Config,
+  poke_client: impl PokeClient,
+  translation_client: impl TranslationClient,
+  cache: C,
+) {
+  // Shared by the router and the prewarmer, so species fetched by either serve both
+  let species = SpeciesCache::from_policy(&config.cache.untranslated());
+  let options = RouterOptions {
+    admin_token: config.admin.token.clone(),
+    languages: config.language.fallback.clone(),
+    species: species.clone(),
+  };
+
+  let routes = router_with_options(poke_client.clone(), translation_client, cache.clone(), options);
+  let (addr, server) = match shutdown::serve(routes, config.server.bind, config.server.drain_timeout(), shutdown::signal()) {
+    Ok(server) => server,
+    Err(err) => {
+      error!(bind = %config.server.bind, error = %err, "failed to bind server");
+      exit(1)
+    }
+  };
+
+  info!(bind = %addr, "starting server");
+
+  if config.prewarm.enabled {
+    let prewarmer = config.prewarm.prewarmer().languages(config.language.fallback.clone());
+    tokio::spawn(async move {
+      prewarmer.run(poke_client, species, cache).await.ok();
+    });
+  }
+
+  server.await;
 }
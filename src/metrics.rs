@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use hyper::StatusCode;
+use lazy_static::lazy_static;
+use prometheus::{
+  core::Collector, Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::util::{CacheKey, PokError, TranslationType};
+
+lazy_static! {
+  /// Registry of every metric exported on the `/metrics` route
+  pub static ref REGISTRY: Registry = Registry::new();
+
+  static ref HTTP_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+    Opts::new("http_requests_total", "HTTP requests served, by route and status"),
+    &["route", "status"],
+  ).unwrap());
+  static ref HTTP_LATENCY: HistogramVec = register(HistogramVec::new(
+    HistogramOpts::new("http_request_duration_seconds", "HTTP request latency, by route and status"),
+    &["route", "status"],
+  ).unwrap());
+  static ref UPSTREAM_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+    Opts::new("upstream_requests_total", "Requests made to upstream APIs, counting a retried request once"),
+    &["upstream"],
+  ).unwrap());
+  static ref UPSTREAM_ERRORS: IntCounterVec = register(IntCounterVec::new(
+    Opts::new("upstream_errors_total", "Failed requests to upstream APIs, by kind of failure"),
+    &["upstream", "kind"],
+  ).unwrap());
+  static ref UPSTREAM_LATENCY: HistogramVec = register(HistogramVec::new(
+    HistogramOpts::new("upstream_request_duration_seconds", "Upstream API latency, including retries"),
+    &["upstream"],
+  ).unwrap());
+  static ref CACHE_OPERATIONS: IntCounterVec = register(IntCounterVec::new(
+    Opts::new("cache_operations_total", "Cache hits, misses and inserts, by translation type"),
+    &["translation_type", "operation"],
+  ).unwrap());
+  static ref TRANSLATIONS: IntCounterVec = register(IntCounterVec::new(
+    Opts::new("translations_total", "Translation attempts, by backend, translation type and outcome"),
+    &["backend", "translation_type", "outcome"],
+  ).unwrap());
+  static ref PREWARM_SPECIES: IntCounterVec = register(IntCounterVec::new(
+    Opts::new("prewarm_species_total", "Species handled by the startup prewarm crawl, by outcome"),
+    &["outcome"],
+  ).unwrap());
+}
+
+fn register<T: Collector + Clone + 'static>(metric: T) -> T {
+  REGISTRY.register(Box::new(metric.clone())).expect("Register metric");
+  metric
+}
+
+/// A cache operation worth counting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheOperation {
+  Hit,
+  Miss,
+  Insert,
+}
+
+impl CacheOperation {
+  fn label(&self) -> &'static str {
+    match self {
+      CacheOperation::Hit => "hit",
+      CacheOperation::Miss => "miss",
+      CacheOperation::Insert => "insert",
+    }
+  }
+}
+
+/// What became of a species visited by the prewarm crawl
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrewarmOutcome {
+  Warmed,
+  Skipped,
+  Failed,
+}
+
+impl PrewarmOutcome {
+  fn label(&self) -> &'static str {
+    match self {
+      PrewarmOutcome::Warmed => "warmed",
+      PrewarmOutcome::Skipped => "skipped",
+      PrewarmOutcome::Failed => "failed",
+    }
+  }
+}
+
+/// Cache keys that can be attributed to a translation type in cache metrics
+pub trait CacheLabel {
+  fn translation_type(&self) -> TranslationType;
+}
+
+impl CacheLabel for CacheKey {
+  fn translation_type(&self) -> TranslationType {
+    self.translation_type
+  }
+}
+
+/// Record a request served by the public API.
+///
+/// `route` should be the route template, not the requested path, so that the
+/// number of label values stays bounded.
+pub fn record_http(route: &str, status: StatusCode, elapsed: Duration) {
+  let status = status.as_str();
+
+  HTTP_REQUESTS.with_label_values(&[route, status]).inc();
+  HTTP_LATENCY.with_label_values(&[route, status]).observe(elapsed.as_secs_f64());
+}
+
+/// Record the outcome of a (possibly retried) request to an upstream API.
+pub fn record_upstream(upstream: &str, elapsed: Duration, error: Option<&PokError>) {
+  UPSTREAM_REQUESTS.with_label_values(&[upstream]).inc();
+  UPSTREAM_LATENCY.with_label_values(&[upstream]).observe(elapsed.as_secs_f64());
+
+  if let Some(error) = error {
+    let kind = match error {
+      PokError::Unavailable(status) if status.is_client_error() => "client_error",
+      PokError::Unavailable(_) => "server_error",
+      PokError::Timeout => "timeout",
+      PokError::Hyper(_) | PokError::Http(_) => "connection",
+      PokError::Parse(_) => "parse",
+      _ => "other",
+    };
+    UPSTREAM_ERRORS.with_label_values(&[upstream, kind]).inc();
+  }
+}
+
+/// Record a cache operation against the given key.
+pub fn record_cache(key: &impl CacheLabel, operation: CacheOperation) {
+  CACHE_OPERATIONS
+    .with_label_values(&[key.translation_type().label(), operation.label()])
+    .inc();
+}
+
+/// Record an attempt to translate with the named backend.
+pub fn record_translation(backend: &str, translate_to: TranslationType, succeeded: bool) {
+  let outcome = if succeeded { "success" } else { "failure" };
+  TRANSLATIONS.with_label_values(&[backend, translate_to.label(), outcome]).inc();
+}
+
+/// Record what became of a species visited by the prewarm crawl.
+pub fn record_prewarm(outcome: PrewarmOutcome) {
+  PREWARM_SPECIES.with_label_values(&[outcome.label()]).inc();
+}
+
+/// Render every registered metric in the Prometheus text exposition format.
+pub fn gather() -> String {
+  let mut buffer = Vec::new();
+  TextEncoder::new()
+    .encode(&REGISTRY.gather(), &mut buffer)
+    .expect("Encode metrics");
+
+  String::from_utf8(buffer).expect("Metrics are valid utf-8")
+}
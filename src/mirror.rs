@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::from_slice;
+use tokio::fs;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+
+use crate::api::API;
+use crate::models::poke_models::{NamedAPIResourceList, PokemonSpecies};
+use crate::util::{PokeClient, PokError};
+
+/// Just enough of a species to index it by
+#[derive(Deserialize)]
+struct SpeciesId {
+  id: u64,
+  name: String,
+}
+
+/// A PokeClient serving species from a local mirror of Pokeapi
+///
+/// The mirror is a directory holding one `.json` file per species, each the
+/// unmodified response of `/api/v2/pokemon-species/{name}` - the same shape
+/// as `tests/assets/raw_*.json`. Files are indexed by the species they hold,
+/// whatever they are named, though `download` names them `{name}.json` when
+/// creating or refreshing one.
+///
+/// Pokemon are looked up by name, ignoring case, or by their numeric id. Only
+/// species found when the mirror was opened are served, and anything else is
+/// reported as `PokError::NotFound`. No network access is made at all.
+#[derive(Clone)]
+pub struct Mirror {
+  dir: PathBuf,
+  /// Species names by id, ordered by id
+  species: Arc<Vec<(u64, String)>>,
+  /// The file holding each species, by name
+  paths: Arc<HashMap<String, PathBuf>>,
+}
+
+impl Mirror {
+  /// Open the mirror in `dir`, indexing the species it holds.
+  ///
+  /// Files that cannot be read as a species are skipped with a warning.
+  pub async fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+    let dir = dir.as_ref().to_path_buf();
+
+    let mut species = Vec::new();
+    let mut paths = HashMap::new();
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+      let path = entry.path();
+      if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+        continue
+      }
+
+      let bytes = match fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+          warn!(path = %path.display(), error = %err, "skipping unreadable mirror file");
+          continue
+        }
+      };
+      match from_slice::<SpeciesId>(&bytes) {
+        Ok(SpeciesId { id, name }) => {
+          let name = name.to_lowercase();
+          species.push((id, name.clone()));
+          paths.insert(name, path);
+        },
+        Err(err) => warn!(path = %path.display(), error = %err, "skipping unreadable mirror file"),
+      }
+    }
+    species.sort();
+
+    info!(dir = %dir.display(), species = species.len(), "opened pokeapi mirror");
+
+    Ok(Self {
+      dir,
+      species: Arc::new(species),
+      paths: Arc::new(paths),
+    })
+  }
+
+  /// Get the number of species held.
+  pub fn len(&self) -> usize {
+    self.species.len()
+  }
+
+  /// Get whether the mirror holds no species at all.
+  pub fn is_empty(&self) -> bool {
+    self.species.is_empty()
+  }
+
+  /// Find the name of a species, and the file holding it, given either its 
+  /// name or id
+  fn resolve(&self, pokemon: &str) -> Option<(&str, &Path)> {
+    let pokemon = pokemon.to_lowercase();
+    let name = match pokemon.parse::<u64>() {
+      Ok(id) => self.species
+        .binary_search_by_key(&id, |(id, _)| *id)
+        .ok()
+        .map(|index| self.species[index].1.as_str())?,
+      Err(_) => pokemon.as_str(),
+    };
+    self.paths.get_key_value(name).map(|(name, path)| (name.as_str(), path.as_path()))
+  }
+}
+
+#[async_trait]
+impl PokeClient for Mirror {
+  const POKEAPI: &'static str = "";
+
+  /// The mirror's directory, standing in for a host
+  fn get_pokeapi_url(&self) -> String {
+    self.dir.display().to_string()
+  }
+
+  async fn get_pokemon(&self, pokemon: String) -> Result<PokemonSpecies, PokError> {
+    let (name, path) = match self.resolve(&pokemon) {
+      Some(resolved) => resolved,
+      None => {
+        debug!(pokemon = %pokemon, "pokemon not in mirror");
+        return Err(PokError::NotFound)
+      }
+    };
+
+    let bytes = match fs::read(path).await {
+      Ok(bytes) => bytes,
+      // Removed since the mirror was opened
+      Err(err) if err.kind() == ErrorKind::NotFound => return Err(PokError::NotFound),
+      Err(err) => {
+        warn!(pokemon = %name, error = %err, "failed to read mirror file");
+        return Err(err.into())
+      }
+    };
+
+    Ok(from_slice::<PokemonSpecies>(&bytes)?)
+  }
+
+  async fn list_species(&self, offset: u64, limit: u64) -> Result<NamedAPIResourceList, PokError> {
+    let count = self.species.len() as u64;
+    let names = self.species.iter()
+      .skip(offset as usize)
+      .take(limit as usize)
+      .map(|(_, name)| name.clone())
+      .collect();
+    let next = if offset.saturating_add(limit) < count { Some(String::from("next")) } else { None };
+
+    Ok(NamedAPIResourceList::new(count, next, names))
+  }
+
+  /// Check that the mirror's directory is still there
+  async fn ping(&self) -> Result<(), PokError> {
+    match fs::metadata(&self.dir).await? {
+      metadata if metadata.is_dir() => Ok(()),
+      _ => Err(io::Error::new(ErrorKind::InvalidInput, "mirror is not a directory").into()),
+    }
+  }
+}
+
+/// Tally of a mirror download
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadReport {
+  /// Species written to the mirror
+  pub downloaded: u64,
+  /// Species that could not be fetched or written
+  pub failed: u64,
+}
+
+/// Names requested per page of the species list while downloading
+const DOWNLOAD_PAGE_SIZE: u64 = 100;
+
+/// Download every species from Pokeapi into the mirror in `dir`, creating it
+/// if need be
+///
+/// Failing to fetch or write a single species is counted and carried on past,
+/// but failing to list a page of species, or to create `dir`, ends the
+/// download.
+///
+/// Existing files are replaced, refreshing the mirror. Each file is written in
+/// full before being moved into place, so a running server never reads one
+/// part way through. Up to `concurrency` species are fetched at once.
+pub async fn download(api: &API, dir: impl AsRef<Path>, concurrency: usize) -> Result<DownloadReport, PokError> {
+  let dir = dir.as_ref().to_path_buf();
+  fs::create_dir_all(&dir).await?;
+
+  let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+  let mut report = DownloadReport::default();
+  let mut offset = 0;
+
+  loop {
+    let page = api.list_species(offset, DOWNLOAD_PAGE_SIZE).await?;
+
+    let tasks: Vec<_> = page.results().iter().map(|species| {
+      let name = species.name().to_owned();
+      let (permits, api, dir) = (permits.clone(), api.clone(), dir.clone());
+      tokio::spawn(async move {
+        let _permit = permits.acquire_owned().await;
+        download_one(&api, &dir, &name).await
+      })
+    }).collect();
+
+    for task in tasks {
+      match task.await {
+        Ok(true) => report.downloaded += 1,
+        _ => report.failed += 1,
+      }
+    }
+    info!(downloaded = report.downloaded, failed = report.failed, total = page.count(), "mirror download progress");
+
+    if !page.has_next() || page.results().is_empty() {
+      break
+    }
+    offset += DOWNLOAD_PAGE_SIZE;
+  }
+
+  Ok(report)
+}
+
+/// Download a single species into the mirror, returning whether it succeeded
+async fn download_one(api: &API, dir: &Path, name: &str) -> bool {
+  let bytes = match api.get_species_json(name).await {
+    Ok(bytes) => bytes,
+    Err(err) => {
+      warn!(pokemon = %name, error = %err, "failed to download species");
+      return false
+    }
+  };
+
+  let path = dir.join(format!("{}.json", name));
+  let temp = path.with_extension("downloading");
+  let res = match fs::write(&temp, &bytes).await {
+    Ok(()) => fs::rename(&temp, &path).await,
+    Err(err) => Err(err),
+  };
+
+  match res {
+    Ok(()) => true,
+    Err(err) => {
+      warn!(path = %path.display(), error = %err, "failed to write mirror file");
+      false
+    }
+  }
+}
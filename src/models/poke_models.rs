@@ -1,7 +1,11 @@
+use std::str::FromStr;
+
 use regex::{Regex, RegexBuilder};
 use serde::{Serialize, Deserialize};
 use lazy_static::lazy_static;
+use thiserror::Error;
 
+use crate::language::{self, DEFAULT_LANGUAGE};
 use crate::util::PokError;
 
 lazy_static! {
@@ -15,6 +19,10 @@ lazy_static! {
 /// 
 /// The habitat field can be null in some cases, such as Arceus. In these cases 
 /// the returned string is just "null".
+/// 
+/// The fields beyond those of the basic response are only served by the full 
+/// response, see `SpeciesDetails`, and so may be missing without failing any 
+/// other route.
 #[derive(Deserialize)]
 pub struct PokemonSpecies {
   name: String,
@@ -23,6 +31,16 @@ pub struct PokemonSpecies {
   /// Undocumented aspect of Pokeapi is that habitat may be null - example, Arceus
   habitat: Option<NamedAPIResource>,
   is_legendary: bool,
+  id: Option<u32>,
+  #[serde(default)]
+  genera: Vec<Genus>,
+  generation: Option<NamedAPIResource>,
+  is_mythical: Option<bool>,
+  color: Option<NamedAPIResource>,
+  /// Null for some pokemon added in later generations
+  shape: Option<NamedAPIResource>,
+  capture_rate: Option<u8>,
+  evolution_chain: Option<APIResource>,
 }
 
 impl PokemonSpecies {
@@ -43,8 +61,100 @@ impl PokemonSpecies {
   pub fn get_first_description(&self, key: &str) -> Option<String> {
     self.descriptions.iter()
       .find(|flavour| flavour.language().name() == key)
-      .and_then(|flavour| Some(flavour.flavor_text()))
-      .and_then(|flavor| Some(REMOVE_ESCAPED.replace_all(flavor, " ").to_string()))
+      .map(FlavourText::description)
+  }
+
+  /// Get the first description in the most preferred of the given languages, 
+  /// along with the language it is in, as named by Pokeapi.
+  ///
+  /// Languages are matched ignoring case, and regional languages such as 
+  /// "en-GB" fall back to their primary language. Returns None if there is 
+  /// no description in any of the languages.
+  pub fn get_preferred_description(&self, languages: &[String]) -> Option<(String, String)> {
+    self.select_description(languages, &VersionSelection::First)
+      .map(|flavour| (flavour.language().name().to_owned(), flavour.description()))
+  }
+
+  /// Choose a description in the most preferred of the given languages, by 
+  /// the game version `selection` asks for.
+  ///
+  /// Languages are matched as in `get_preferred_description`, and only 
+  /// languages with a description selected by `selection` are considered - so 
+  /// a version missing in one language may still be found in the next. 
+  /// Returns None if no language has such a description.
+  pub fn select_description(&self, languages: &[String], selection: &VersionSelection) -> Option<&FlavourText> {
+    languages.iter().find_map(|wanted| {
+      let mut candidates: Vec<&FlavourText> = self.descriptions.iter()
+        .filter(|flavour| flavour.language().name().eq_ignore_ascii_case(wanted))
+        .collect();
+      if candidates.is_empty() {
+        candidates = self.descriptions.iter()
+          .filter(|flavour| language::matches(wanted, flavour.language().name()))
+          .collect();
+      }
+
+      selection.select(&self.name, &candidates)
+    })
+  }
+
+  /// Get every distinct description of the pokemon, each with the versions 
+  /// and languages it appeared in, in the order Pokeapi lists them.
+  ///
+  /// Descriptions are compared once normalised - with newlines and form 
+  /// feeds replaced and runs of whitespace collapsed - as many differ between 
+  /// games only in how they were wrapped.
+  pub fn distinct_descriptions(&self) -> Vec<DistinctDescription> {
+    let mut distinct: Vec<DistinctDescription> = Vec::new();
+
+    for flavour in &self.descriptions {
+      let description = flavour.description().split_whitespace().collect::<Vec<_>>().join(" ");
+      let index = match distinct.iter().position(|seen| seen.description == description) {
+        Some(index) => index,
+        None => {
+          distinct.push(DistinctDescription {
+            description,
+            versions: Vec::new(),
+            languages: Vec::new(),
+          });
+          distinct.len() - 1
+        },
+      };
+
+      let entry = &mut distinct[index];
+      push_unique(&mut entry.versions, flavour.version().name());
+      push_unique(&mut entry.languages, flavour.language().name());
+    }
+
+    distinct
+  }
+
+  /// Get the pokemon's national pokedex number, if Pokeapi gave one.
+  pub fn id(&self) -> Option<u32> {
+    self.id
+  }
+
+  /// Get the pokemon's genus, such as "Mouse Pokémon", in the given language 
+  /// as named by Pokeapi, or failing that in English.
+  pub fn genus(&self, language: &str) -> Option<&str> {
+    self.genera.iter()
+      .find(|genus| genus.language().name() == language)
+      .or_else(|| self.genera.iter().find(|genus| genus.language().name() == DEFAULT_LANGUAGE))
+      .map(Genus::genus)
+  }
+
+  /// Get the details served by the full response, with the genus in the 
+  /// given language, see `genus`.
+  pub fn details(&self, language: &str) -> SpeciesDetails {
+    SpeciesDetails {
+      id: self.id,
+      genus: self.genus(language).map(str::to_owned),
+      generation: self.generation.as_ref().map(|generation| generation.name().to_owned()),
+      is_mythical: self.is_mythical,
+      color: self.color.as_ref().map(|color| color.name().to_owned()),
+      shape: self.shape.as_ref().map(|shape| shape.name().to_owned()),
+      capture_rate: self.capture_rate,
+      evolution_chain: self.evolution_chain.as_ref().and_then(APIResource::id),
+    }
   }
 
   /// Get a reference to the pokemon species's habitat.
@@ -78,15 +188,111 @@ impl NamedAPIResource {
   }
 }
 
+/// An API Resource, referring to another part of the API by url alone
+#[derive(Deserialize)]
+pub struct APIResource {
+  url: String,
+}
+
+impl APIResource {
+  /// Get the id of the referred resource, the last segment of its url.
+  pub fn id(&self) -> Option<u32> {
+    self.url.trim_end_matches('/').rsplit('/').next()?.parse().ok()
+  }
+}
+
+/// A genus as returned by Pokeapi, such as "Mouse Pokémon", and the language 
+/// it is in
+#[derive(Deserialize)]
+pub struct Genus {
+  genus: String,
+  language: NamedAPIResource,
+}
+
+impl Genus {
+  /// Get a reference to the genus itself.
+  pub fn genus(&self) -> &str {
+    self.genus.as_ref()
+  }
+
+  /// Get a reference to the genus's language.
+  pub fn language(&self) -> &NamedAPIResource {
+    &self.language
+  }
+}
+
+/// Details of a pokemon beyond those of the basic response, served only by 
+/// the full response
+/// 
+/// Resources are given by name, such as "generation-i" or "yellow", apart 
+/// from the evolution chain, which is given by its Pokeapi id as it has none.
+/// Any missing from Pokeapi's response are None.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SpeciesDetails {
+  pub id: Option<u32>,
+  pub genus: Option<String>,
+  pub generation: Option<String>,
+  pub is_mythical: Option<bool>,
+  pub color: Option<String>,
+  pub shape: Option<String>,
+  pub capture_rate: Option<u8>,
+  pub evolution_chain: Option<u32>,
+}
+
+/// Fields a pokemon response can be projected onto with a `fields` query 
+/// parameter, those of the full response
+pub const FIELDS: &[&str] = &[
+  "name", "description", "habitat", "is_legendary",
+  "id", "genus", "generation", "is_mythical", "color", "shape", "capture_rate", "evolution_chain",
+  "language", "version",
+];
+
+/// A page of a Pokeapi resource listing, such as `/api/v2/pokemon-species`
+/// 
+/// `count` is the number of resources across all pages, and `next` the url of 
+/// the following page, if there is one.
+#[derive(Deserialize)]
+pub struct NamedAPIResourceList {
+  count: u64,
+  next: Option<String>,
+  results: Vec<NamedAPIResource>,
+}
+
+impl NamedAPIResourceList {
+  pub fn new(count: u64, next: Option<String>, names: Vec<String>) -> Self {
+    Self {
+      count,
+      next,
+      results: names.into_iter().map(|name| NamedAPIResource { name }).collect(),
+    }
+  }
+
+  /// Get the total number of resources listed across all pages.
+  pub fn count(&self) -> u64 {
+    self.count
+  }
+
+  /// Get whether there is a page following this one.
+  pub fn has_next(&self) -> bool {
+    self.next.is_some()
+  }
+
+  /// Get the resources listed on this page.
+  pub fn results(&self) -> &[NamedAPIResource] {
+    &self.results
+  }
+}
+
 /// A flavor text as returned by Pokeapi
 /// 
 /// Contains a flavor text (which may include a wide range of unicode, including
-/// newlines and form feeds) and a Named API Resource representing the language 
-/// the flavor text is in.
+/// newlines and form feeds) and Named API Resources representing the language 
+/// the flavor text is in and the game version it appeared in.
 #[derive(Deserialize)]
 pub struct FlavourText {
   flavor_text: String,
-  language: NamedAPIResource
+  language: NamedAPIResource,
+  version: NamedAPIResource,
 }
 
 impl FlavourText {
@@ -99,6 +305,105 @@ impl FlavourText {
   pub fn language(&self) -> &NamedAPIResource {
     &self.language
   }
+
+  /// Get a reference to the game version the flavour text appeared in.
+  pub fn version(&self) -> &NamedAPIResource {
+    &self.version
+  }
+
+  /// Get the flavour text with its newlines and form feeds replaced by spaces.
+  pub fn description(&self) -> String {
+    REMOVE_ESCAPED.replace_all(self.flavor_text(), " ").to_string()
+  }
+}
+
+/// A description shared by one or more flavour texts, along with the game 
+/// versions and languages, as named by Pokeapi, of those flavour texts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DistinctDescription {
+  pub description: String,
+  pub versions: Vec<String>,
+  pub languages: Vec<String>,
+}
+
+fn push_unique(values: &mut Vec<String>, value: &str) {
+  if !values.iter().any(|seen| seen == value) {
+    values.push(value.to_owned());
+  }
+}
+
+/// How to choose between the descriptions a pokemon has in a language, one 
+/// for each game version it appeared in
+///
+/// Parsed from the labels "first", "latest" and "random", or otherwise the 
+/// name of a version, such as "sword".
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionSelection {
+  /// The first description, as listed by Pokeapi
+  #[default]
+  First,
+  /// The last description - Pokeapi lists versions in release order, so this 
+  /// is from the most recent game
+  Latest,
+  /// A description picked at random, but always the same one for the same 
+  /// pokemon, so that responses can be cached
+  Random,
+  /// The description from the named version
+  Version(String),
+}
+
+impl VersionSelection {
+  pub fn is_first(&self) -> bool {
+    matches!(self, VersionSelection::First)
+  }
+
+  /// Choose one of `candidates`, all descriptions of the pokemon `name`.
+  fn select<'a>(&self, name: &str, candidates: &[&'a FlavourText]) -> Option<&'a FlavourText> {
+    match self {
+      VersionSelection::First => candidates.first().copied(),
+      VersionSelection::Latest => candidates.last().copied(),
+      VersionSelection::Random if candidates.is_empty() => None,
+      VersionSelection::Random => Some(candidates[(stable_hash(name) % candidates.len() as u64) as usize]),
+      VersionSelection::Version(version) => candidates.iter()
+        .find(|flavour| flavour.version().name() == version)
+        .copied(),
+    }
+  }
+}
+
+/// A malformed game version
+#[derive(Error, Debug)]
+#[error("Invalid game version: {0}")]
+pub struct InvalidVersion(pub String);
+
+/// Parses the `version` query parameter, ignoring case
+impl FromStr for VersionSelection {
+  type Err = InvalidVersion;
+
+  fn from_str(label: &str) -> Result<Self, Self::Err> {
+    let label = label.trim().to_lowercase();
+    match label.as_str() {
+      "first" => Ok(VersionSelection::First),
+      "latest" => Ok(VersionSelection::Latest),
+      "random" => Ok(VersionSelection::Random),
+      _ if is_valid_version(&label) => Ok(VersionSelection::Version(label)),
+      _ => Err(InvalidVersion(label)),
+    }
+  }
+}
+
+/// Whether a version is named as Pokeapi names them, such as "omega-ruby"
+fn is_valid_version(version: &str) -> bool {
+  !version.is_empty()
+    && version.len() <= 32
+    && version.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// FNV-1a, which unlike the standard library's hasher is fixed across 
+/// releases, keeping random selections stable between deployments
+fn stable_hash(text: &str) -> u64 {
+  text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
 }
 
 /// The response this API will return following a successful request.
@@ -109,12 +414,26 @@ impl FlavourText {
 /// 
 /// The use of a separate type ensures that the returned object is always 
 /// explicitly a response type, and not just a passed on API response.
-#[derive(Serialize, Clone)]
+/// 
+/// Deserialize is implemented so that responses can be restored from 
+/// persistent caches. Responses cached before languages were recorded are 
+/// restored as English, and those cached before versions were recorded 
+/// without a version. Details of the species are not held in responses, but 
+/// alongside the species, see `crate::species::SpeciesCache`.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PokemonResponse {
   name: String,
   description: String,
   habitat: String,
-  is_legendary: bool
+  is_legendary: bool,
+  #[serde(default = "default_language")]
+  language: String,
+  #[serde(default)]
+  version: Option<String>,
+}
+
+fn default_language() -> String {
+  DEFAULT_LANGUAGE.to_owned()
 }
 
 impl PokemonResponse {
@@ -138,20 +457,50 @@ impl PokemonResponse {
     self.is_legendary
   }
 
+  /// Get the language of the pokemon's description, as named by Pokeapi.
+  pub fn language(&self) -> &str {
+    self.language.as_ref()
+  }
+
+  /// Get the game version the pokemon's description appeared in, if known.
+  pub fn version(&self) -> Option<&str> {
+    self.version.as_deref()
+  }
+
   pub fn set_description(&mut self, translated: String) {
     self.description = translated;
   }
-}
 
-impl TryFrom<PokemonSpecies> for PokemonResponse {
-  type Error = PokError;
+  /// Create a response describing the pokemon in the most preferred of the 
+  /// given languages, from the version chosen by `selection`, see 
+  /// `PokemonSpecies::select_description`.
+  ///
+  /// Fails with `PokError::VersionNotFound` should the pokemon have no 
+  /// description from a version asked for by name.
+  pub fn from_species(species: &PokemonSpecies, languages: &[String], selection: &VersionSelection) -> Result<Self, PokError> {
+    let flavour = match (species.select_description(languages, selection), selection) {
+      (Some(flavour), _) => flavour,
+      (None, VersionSelection::Version(version)) => return Err(PokError::VersionNotFound(version.clone())),
+      (None, _) => return Err(PokError::NoDescription),
+    };
 
-  fn try_from(species: PokemonSpecies) -> Result<Self, Self::Error> {
+    let language = flavour.language().name();
     Ok(Self {
       name: species.name().to_owned(),
-      description: species.get_first_description("en").ok_or_else(|| PokError::NoDescription)?,
+      description: flavour.description(),
       habitat: species.habitat().to_owned(),
-      is_legendary: species.is_legendary()
+      is_legendary: species.is_legendary(),
+      language: language.to_owned(),
+      version: Some(flavour.version().name().to_owned()),
     })
   }
 }
+
+impl TryFrom<PokemonSpecies> for PokemonResponse {
+  type Error = PokError;
+
+  /// Describes the pokemon in English, from the first version listed
+  fn try_from(species: PokemonSpecies) -> Result<Self, Self::Error> {
+    Self::from_species(&species, &[default_language()], &VersionSelection::First)
+  }
+}
@@ -17,10 +17,14 @@ impl TranslationUnit {
   }
 }
 
-/// The actual translated string, as wrapped in a JSON object
+/// The actual translated string, as wrapped in a JSON object, alongside the 
+/// text it was translated from - which is empty should funtranslations leave 
+/// it out
 #[derive(Deserialize)]
 pub struct Contents {
-  translated: String
+  translated: String,
+  #[serde(default)]
+  text: String
 }
 
 impl Contents {
@@ -28,4 +32,9 @@ impl Contents {
   pub fn translated(&self) -> &str {
     self.translated.as_ref()
   }
+
+  /// Get a reference to the contents's original, untranslated string.
+  pub fn text(&self) -> &str {
+    self.text.as_ref()
+  }
 }
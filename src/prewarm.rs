@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tracing::{debug, info, instrument, warn};
+
+use crate::language::DEFAULT_LANGUAGE;
+use crate::metrics::{self, PrewarmOutcome};
+use crate::models::poke_models::PokemonResponse;
+use crate::species::SpeciesCache;
+use crate::util::{PokeClient, TranslationType, CacheKey, PokError, SharedError, Freshness, CacheWrapper};
+
+/// Tally of a pre-warm crawl
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrewarmReport {
+  /// Species listed by Pokeapi
+  pub listed: u64,
+  /// Species fetched and cached
+  pub warmed: u64,
+  /// Species already freshly cached, and so left alone
+  pub skipped: u64,
+  /// Species that could not be fetched
+  pub failed: u64,
+}
+
+impl PrewarmReport {
+  fn record(&mut self, outcome: PrewarmOutcome) {
+    metrics::record_prewarm(outcome);
+    match outcome {
+      PrewarmOutcome::Warmed => self.warmed += 1,
+      PrewarmOutcome::Skipped => self.skipped += 1,
+      PrewarmOutcome::Failed => self.failed += 1,
+    }
+  }
+}
+
+/// Crawls the full list of pokemon species, caching each untranslated
+///
+/// Pokeapi's species list is paged through `page_size` names at a time, with
+/// up to `concurrency` species fetched at once. Species already freshly cached,
+/// such as those restored by the disk backend, are skipped. Translations are
+/// never requested, so no translation quota is spent. Descriptions are cached 
+/// in the first of `languages` each species has one in, as the server does 
+/// for requests not asking for a language.
+///
+/// Species are fetched through the server's `SpeciesCache`, so those held in
+/// memory cost no Pokeapi request, and those fetched are held for the server
+/// to describe in other languages or versions.
+///
+/// Progress is logged after every page, and each species' outcome counted in
+/// the exported prewarm metrics.
+#[derive(Clone, Debug)]
+pub struct Prewarmer {
+  page_size: u64,
+  concurrency: usize,
+  languages: Vec<String>,
+}
+
+impl Default for Prewarmer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Prewarmer {
+  /// Create a crawler fetching pages of 100 names, 4 species at a time, 
+  /// described in English.
+  pub fn new() -> Self {
+    Self {
+      page_size: 100,
+      concurrency: 4,
+      languages: vec![DEFAULT_LANGUAGE.to_owned()],
+    }
+  }
+
+  /// Set the number of names requested per page of the species list.
+  pub fn page_size(mut self, page_size: u64) -> Self {
+    self.page_size = page_size.max(1);
+    self
+  }
+
+  /// Set the maximum number of species fetched at once.
+  pub fn concurrency(mut self, concurrency: usize) -> Self {
+    self.concurrency = concurrency.max(1);
+    self
+  }
+
+  /// Set the languages descriptions are chosen from, most preferred first.
+  pub fn languages(mut self, languages: Vec<String>) -> Self {
+    self.languages = languages;
+    self
+  }
+
+  /// Crawl every species, returning a tally of what was done.
+  ///
+  /// Failing to fetch a single species is counted and carried on past, but
+  /// failing to list a page of species ends the crawl.
+  #[instrument(name = "prewarm", skip_all)]
+  pub async fn run(
+    &self,
+    poke_client: impl PokeClient,
+    species: SpeciesCache,
+    cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  ) -> Result<PrewarmReport, PokError> {
+    let permits = Arc::new(Semaphore::new(self.concurrency));
+    let mut report = PrewarmReport::default();
+    let mut offset = 0;
+
+    loop {
+      let page = match poke_client.list_species(offset, self.page_size).await {
+        Ok(page) => page,
+        Err(err) => {
+          warn!(offset, error = %err, "failed to list species, abandoning prewarm");
+          return Err(err)
+        }
+      };
+
+      let tasks: Vec<_> = page.results().iter().map(|listed| {
+        let key = CacheKey::new(listed.name(), TranslationType::None).languages(self.languages.clone());
+        let (permits, poke_client, species, cache) = (permits.clone(), poke_client.clone(), species.clone(), cache.clone());
+        tokio::spawn(async move {
+          let _permit = permits.acquire_owned().await;
+          warm(key, poke_client, species, cache).await
+        })
+      }).collect();
+
+      report.listed += tasks.len() as u64;
+      for task in tasks {
+        report.record(task.await.unwrap_or(PrewarmOutcome::Failed));
+      }
+
+      info!(
+        listed = report.listed,
+        total = page.count(),
+        warmed = report.warmed,
+        skipped = report.skipped,
+        failed = report.failed,
+        "prewarm progress"
+      );
+
+      if !page.has_next() || page.results().is_empty() {
+        break
+      }
+      offset += self.page_size;
+    }
+
+    info!(listed = report.listed, warmed = report.warmed, skipped = report.skipped, failed = report.failed, "prewarm complete");
+    Ok(report)
+  }
+}
+
+/// Fetch and cache a single species, unless it is already freshly cached
+async fn warm(
+  key: CacheKey,
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> PrewarmOutcome {
+  if let Some((_, Freshness::Fresh)) = cache.lookup(&key).await {
+    return PrewarmOutcome::Skipped
+  }
+
+  let res = match species.get(&key.name, &poke_client).await {
+    Ok(species) => PokemonResponse::from_species(&species, &key.languages, &key.version).map_err(SharedError::from),
+    Err(err) => Err(err),
+  };
+
+  match res {
+    Ok(response) => {
+      cache.insert(key, response).await;
+      PrewarmOutcome::Warmed
+    },
+    Err(err) => {
+      debug!(pokemon = %key.name, error = %err, "failed to prewarm species");
+      PrewarmOutcome::Failed
+    },
+  }
+}
@@ -0,0 +1,133 @@
+use std::{io, time::{Duration, SystemTime}};
+
+use hyper::{Method, StatusCode, header::HeaderValue};
+use rand::Rng;
+
+use crate::util::{PokError, io_error_kind};
+
+/// When, and how often, a failed upstream request should be retried
+///
+/// Delays grow exponentially from `base_delay`, capped at `max_delay`. `jitter`
+/// is the fraction of each delay (between 0 and 1) that is randomised, so that
+/// many clients failing at once do not all retry in lockstep.
+///
+/// Only idempotent requests are ever retried, and a 4xx other than 429 is never
+/// retried even if listed in `retryable_statuses` - the request itself is at
+/// fault, and sending it again will not help.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+  pub max_attempts: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+  pub jitter: f64,
+  pub retryable_statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 3,
+      base_delay: Duration::from_millis(100),
+      max_delay: Duration::from_secs(5),
+      jitter: 0.5,
+      retryable_statuses: vec![
+        StatusCode::TOO_MANY_REQUESTS,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        StatusCode::BAD_GATEWAY,
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::GATEWAY_TIMEOUT,
+      ],
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// A policy that makes a single attempt and never retries
+  pub fn none() -> Self {
+    Self {
+      max_attempts: 1,
+      ..Default::default()
+    }
+  }
+
+  /// Whether a response with the given status may be retried
+  pub fn is_retryable_status(&self, status: StatusCode) -> bool {
+    let client_error = status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS;
+
+    !client_error && self.retryable_statuses.contains(&status)
+  }
+
+  /// Whether a request that failed with the given error may be retried
+  ///
+  /// Upstream statuses are checked against the retryable set, timed out
+  /// attempts and dropped connections are always considered transient, and
+  /// anything else (such as a malformed response) is not.
+  pub fn is_retryable(&self, method: &Method, error: &PokError) -> bool {
+    if !method.is_idempotent() {
+      return false
+    }
+
+    match error {
+      PokError::Unavailable(status) => self.is_retryable_status(*status),
+      PokError::Timeout => true,
+      PokError::Hyper(err) => {
+        err.is_connect() || err.is_incomplete_message() || matches!(
+          io_error_kind(err),
+          Some(io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe)
+        )
+      },
+      _ => false,
+    }
+  }
+
+  /// The exponential backoff before the given retry, with jitter applied
+  ///
+  /// `attempt` is the number of attempts made so far, starting at 1.
+  pub fn backoff(&self, attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(31);
+    let delay = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+    let jitter = self.jitter.clamp(0.0, 1.0);
+
+    if jitter > 0.0 {
+      delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+    } else {
+      delay
+    }
+  }
+
+  /// How long to wait before retrying a request that has failed `attempt`
+  /// times, or None if it should not be retried.
+  ///
+  /// A `Retry-After` given by the upstream takes the place of the backoff. If
+  /// it asks for a longer wait than `max_delay`, we give up rather than hold
+  /// our own client open.
+  pub fn next_delay(
+    &self,
+    method: &Method,
+    attempt: u32,
+    error: &PokError,
+    retry_after: Option<Duration>,
+  ) -> Option<Duration> {
+    if attempt >= self.max_attempts || !self.is_retryable(method, error) {
+      return None
+    }
+
+    match retry_after {
+      Some(delay) if delay > self.max_delay => None,
+      Some(delay) => Some(delay),
+      None => Some(self.backoff(attempt)),
+    }
+  }
+}
+
+/// Parse a `Retry-After` header, given either as delay seconds or an HTTP date
+pub fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+  let value = value.to_str().ok()?.trim();
+
+  if let Ok(secs) = value.parse::<u64>() {
+    return Some(Duration::from_secs(secs))
+  }
+
+  let date = httpdate::parse_http_date(value).ok()?;
+  Some(date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+}
@@ -1,9 +1,71 @@
 use std::convert::Infallible;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
 
-use crate::util::{PokeClient, TranslationClient, TranslationType, handle_reject, CacheWrapper};
-use crate::models::poke_models::PokemonResponse;
+use crate::admin;
+use crate::breaker::BreakerStatus;
+use crate::language::{self, DEFAULT_LANGUAGE};
+use crate::metrics;
+use crate::singleflight::SingleFlight;
+use crate::species::SpeciesCache;
+use crate::util::{PokeClient, TranslationClient, TranslationType, CacheKey, PokError, SharedError, Freshness, handle_reject, CacheWrapper};
+use crate::models::poke_models::{DistinctDescription, PokemonResponse, SpeciesDetails, VersionSelection, FIELDS};
 
-use warp::{Reply, Filter, reject, Rejection, reply::json, path};
+use serde::{Serialize, Serializer, Deserialize, ser::SerializeMap};
+use serde_json::{Map, Value};
+use tracing::{debug, field, info, instrument, warn, Instrument, Span};
+use warp::{Reply, Filter, reject, Rejection, reply::{json, with_header, with_status, Response}, path, http::{StatusCode, HeaderValue, header::{CONTENT_LANGUAGE, CONTENT_TYPE}}};
+
+/// Header marking a response served from stale cached data
+/// 
+/// Absent from fresh responses, otherwise set to the label of a Staleness.
+pub const STALE_HEADER: &str = "x-cache-stale";
+
+/// Header naming the game version a response's description appeared in
+pub const VERSION_HEADER: &str = "x-game-version";
+
+/// Fields of `FIELDS` taken from the details of a pokemon's species, see 
+/// `SpeciesDetails`
+const DETAIL_FIELDS: &[&str] = &[
+  "id", "genus", "generation", "is_mythical", "color", "shape", "capture_rate", "evolution_chain",
+];
+
+/// Why a response was served from stale cached data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Staleness {
+  /// Past its soft expiry, and being refreshed in the background
+  Revalidating,
+  /// Past its hard expiry, and served because refreshing it failed
+  UpstreamError,
+}
+
+impl Staleness {
+  /// Value of the staleness header for this reason
+  pub fn label(&self) -> &'static str {
+    match self {
+      Staleness::Revalidating => "revalidating",
+      Staleness::UpstreamError => "upstream-error",
+    }
+  }
+}
+
+/// A pokemon to respond with, and whether it is stale
+#[derive(Clone)]
+pub struct Served {
+  pub pokemon: PokemonResponse,
+  pub stale: Option<Staleness>,
+}
+
+impl Served {
+  fn fresh(pokemon: PokemonResponse) -> Self {
+    Self { pokemon, stale: None }
+  }
+
+  fn stale(pokemon: PokemonResponse, staleness: Staleness) -> Self {
+    Self { pokemon, stale: Some(staleness) }
+  }
+}
 
 /// Filter for "basic" non-translation API requests
 /// 
@@ -11,23 +73,115 @@ use warp::{Reply, Filter, reject, Rejection, reply::json, path};
 /// species description for the given pokemon from Pokeapi. If a response is 
 /// received successfully from Pokeapi, a response object of our own is created,
 /// cached, then returned.
+/// 
+/// Stale cached responses are served while being refreshed in the background. 
+/// Expired ones are refreshed first, and only served should that fail.
+/// 
+/// Concurrent misses for the same pokemon are coalesced into a single Pokeapi 
+/// request, the result of which is shared between them.
+/// 
+/// The description is in the first of `languages` the pokemon has one in, 
+/// from the game version chosen by `version`, and is cached separately for 
+/// each list of languages and way of choosing a version.
+#[instrument(skip_all, fields(pokemon = %pokemon, translation_type = ?TranslationType::None))]
 pub async fn basic_handler(
   pokemon: String,
+  languages: Vec<String>,
+  version: VersionSelection,
   poke_client: impl PokeClient,
-  cache: impl CacheWrapper<(String, TranslationType), PokemonResponse>,
-) -> Result<PokemonResponse, Rejection> {
-  if let Some(cached_pokemon) = cache.get(&(pokemon.clone(), TranslationType::None)) {
-    Ok(cached_pokemon)
-  } else {
-    let species = poke_client
-      .get_pokemon(pokemon.clone())
-      .await
-      .map_err(|err| reject::custom(err))?;
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<Served, Rejection> {
+  let key = CacheKey::new(pokemon, TranslationType::None).languages(languages).version(version);
+
+  match cache.lookup(&key).await {
+    Some((cached_pokemon, Freshness::Fresh)) => {
+      debug!(cache = "hit", "serving cached pokemon");
+      Ok(Served::fresh(cached_pokemon))
+    },
+    Some((cached_pokemon, Freshness::Stale)) => {
+      debug!(cache = "stale", "serving stale pokemon, refreshing in background");
+      tokio::spawn(async move {
+        if let Err(err) = fetch_pokemon(key, Fetch::Refresh, poke_client, species, cache, flights).await {
+          warn!(error = %err, "background refresh failed");
+        }
+      }.in_current_span());
+      Ok(Served::stale(cached_pokemon, Staleness::Revalidating))
+    },
+    Some((cached_pokemon, Freshness::Expired)) => {
+      debug!(cache = "expired", "refreshing expired pokemon");
+      match fetch_pokemon(key, Fetch::Refresh, poke_client, species, cache, flights).await {
+        Ok(response) => Ok(Served::fresh(response)),
+        Err(err) => {
+          warn!(error = %err, "refresh failed, serving expired pokemon");
+          Ok(Served::stale(cached_pokemon, Staleness::UpstreamError))
+        }
+      }
+    },
+    None => {
+      debug!(cache = "miss", "requesting pokemon from pokeapi");
+      fetch_pokemon(key, Fetch::Miss, poke_client, species, cache, flights).await
+        .map(Served::fresh)
+        .map_err(reject::custom)
+    },
+  }
+}
 
-    let response = PokemonResponse::try_from(species).map_err(|err| reject::custom(err))?;
-    cache.insert((pokemon, TranslationType::None), response.clone()).await;
+/// Why an entry is being fetched from upstream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Fetch {
+  /// Nothing was cached, so an entry cached by another flight in the meantime 
+  /// is served instead
+  Miss,
+  /// The cached entry is to be replaced, however fresh it is
+  Refresh,
+}
+
+/// Describe a pokemon from its species and cache the result, joining any 
+/// request for it already in flight
+/// 
+/// Refreshing requests the species from Pokeapi afresh, while filling a miss 
+/// only requests it should it not be held in `species`.
+pub(crate) async fn fetch_pokemon(
+  key: CacheKey,
+  fetch: Fetch,
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<PokemonResponse, SharedError> {
+  flights.run_shared(key.clone(), || async move {
+    if let Some(pokemon) = cached_fresh(&key, fetch, &cache).await {
+      return Ok(pokemon)
+    }
+
+    let held = match fetch {
+      Fetch::Miss => species.get(&key.name, &poke_client).await?,
+      Fetch::Refresh => species.fetch(&key.name, &poke_client).await?,
+    };
+
+    let response = PokemonResponse::from_species(&held, &key.languages, &key.version)?;
+    cache.insert(key, response.clone()).await;
 
     Ok(response)
+  }).await
+}
+
+/// Look for a fresh entry cached under `key`, for flights filling a miss to 
+/// check before going upstream - a flight that finished between a request 
+/// missing the cache and starting its own will have filled it.
+async fn cached_fresh(key: &CacheKey, fetch: Fetch, cache: &impl CacheWrapper<CacheKey, PokemonResponse>) -> Option<PokemonResponse> {
+  if fetch == Fetch::Refresh {
+    return None
+  }
+
+  match cache.lookup(key).await {
+    Some((pokemon, Freshness::Fresh)) => {
+      debug!(cache = "hit", "served by a flight that has since landed");
+      Some(pokemon)
+    },
+    _ => None,
   }
 }
 
@@ -40,101 +194,696 @@ pub async fn basic_handler(
 /// to the funtranslations API for a translation of the given Pokemon's 
 /// description. If a successful response is received, the given reponse has 
 /// it's description replaced with the translation, is cached, then returned.
+/// 
+/// Stale and expired translations are handled as in the basic handler. As 
+/// with the basic handler, concurrent misses share a single translation 
+/// request - and so a single unit of translation quota.
+/// 
+/// Translations are only made from English, so descriptions in any other 
+/// language are served untranslated. Translations are cached by the language 
+/// and game the description is from, rather than those asked for, so that 
+/// asking for it in different ways spends no further quota.
+#[instrument(skip_all, fields(pokemon = %served.pokemon.name(), translation_type = field::Empty))]
 pub async fn advanced_handler(
-  mut pokemon: PokemonResponse,
+  served: Served,
   translation_client: impl TranslationClient,
-  cache: impl CacheWrapper<(String, TranslationType), PokemonResponse>,
-) -> Result<PokemonResponse, Rejection> {
-  let translate_to = if pokemon.is_legendary() || pokemon.habitat() == "cave" {
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<Served, Rejection> {
+  let pokemon = &served.pokemon;
+  if pokemon.language() != DEFAULT_LANGUAGE {
+    debug!(language = pokemon.language(), "not translating description from another language");
+    return Ok(served)
+  }
+
+  let translate_to = translation_type(pokemon);
+  Span::current().record("translation_type", &field::debug(translate_to));
+
+  let key = translation_key(pokemon, translate_to);
+
+  match cache.lookup(&key).await {
+    Some((cached_translated, Freshness::Fresh)) => {
+      debug!(cache = "hit", "serving cached translation");
+      Ok(Served::fresh(cached_translated))
+    },
+    Some((cached_translated, Freshness::Stale)) => {
+      debug!(cache = "stale", "serving stale translation, refreshing in background");
+      tokio::spawn(async move {
+        if let Err(err) = translate_pokemon(key, Fetch::Refresh, served.pokemon, translation_client, cache, flights).await {
+          warn!(error = %err, "background translation refresh failed");
+        }
+      }.in_current_span());
+      Ok(Served::stale(cached_translated, Staleness::Revalidating))
+    },
+    Some((cached_translated, Freshness::Expired)) => {
+      debug!(cache = "expired", "refreshing expired translation");
+      match translate_pokemon(key, Fetch::Refresh, served.pokemon, translation_client, cache, flights).await {
+        Ok(translated_pokemon) => Ok(Served::fresh(translated_pokemon)),
+        Err(err) => {
+          warn!(error = %err, "translation refresh failed, serving expired translation");
+          Ok(Served::stale(cached_translated, Staleness::UpstreamError))
+        }
+      }
+    },
+    None => {
+      debug!(cache = "miss", "requesting translation");
+      match translate_pokemon(key, Fetch::Miss, served.pokemon.clone(), translation_client, cache, flights).await {
+        Ok(translated_pokemon) => Ok(Served::fresh(translated_pokemon)),
+        Err(err) => {
+          warn!(error = %err, "translation failed, serving untranslated description");
+          Ok(served)
+        }
+      }
+    },
+  }
+}
+
+/// Filter listing every distinct description of a pokemon
+/// 
+/// Listed from the species held for the "pokemon" route, and so sharing its 
+/// Pokeapi requests, whichever languages the pokemon has descriptions in.
+#[instrument(skip_all, fields(pokemon = %pokemon))]
+pub async fn descriptions_handler(
+  pokemon: String,
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+) -> Result<Response, Rejection> {
+  let held = species.get(&pokemon, &poke_client).await
+    .map_err(reject::custom)?;
+  let descriptions = species.descriptions(&pokemon, &poke_client).await
+    .map_err(reject::custom)?;
+
+  Ok(json(&DescriptionsReply {
+    name: held.name(),
+    descriptions: &descriptions,
+  }).into_response())
+}
+
+/// Filter for the full response, the basic response along with every detail 
+/// of the pokemon
+/// 
+/// Shares the cache entries of the "pokemon" route, in the same languages and 
+/// version, by fetching the pokemon through the basic handler. Details are 
+/// taken from the species held for it, rather than cached in every response.
+#[instrument(skip_all, fields(pokemon = %pokemon))]
+pub async fn full_handler(
+  pokemon: String,
+  languages: Vec<String>,
+  version: VersionSelection,
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<Response, Rejection> {
+  let served = basic_handler(pokemon, languages, version, poke_client.clone(), species.clone(), cache, flights).await?;
+  let details = species_details(&served.pokemon, &poke_client, &species).await?;
+
+  Ok(format_full(served, &details))
+}
+
+/// Choose how to translate a pokemon's description
+/// 
+/// Legendary pokemon and those living in caves are translated to Yoda speak, 
+/// all others to Shakespearean English.
+pub(crate) fn translation_type(pokemon: &PokemonResponse) -> TranslationType {
+  if pokemon.is_legendary() || pokemon.habitat() == "cave" {
     TranslationType::Yoda
   } else {
     TranslationType::Shakespeare
+  }
+}
+
+/// The key a translation of a pokemon's description is cached under, that of 
+/// the language and game the description is from
+/// 
+/// Entries cached before the game was recorded were all from the first listed.
+pub(crate) fn translation_key(pokemon: &PokemonResponse, translate_to: TranslationType) -> CacheKey {
+  let version = match pokemon.version() {
+    Some(version) => VersionSelection::Version(version.to_owned()),
+    None => VersionSelection::First,
   };
 
-  if let Some(cached_translated) = cache.get(&(pokemon.name().to_owned(), translate_to)) {
-    return Ok(cached_translated)
+  CacheKey::new(pokemon.name(), translate_to)
+    .languages(vec![pokemon.language().to_owned()])
+    .version(version)
+}
+
+/// Translate a pokemon's description and cache the result, joining any 
+/// translation of it already in flight
+pub(crate) async fn translate_pokemon(
+  key: CacheKey,
+  fetch: Fetch,
+  mut pokemon: PokemonResponse,
+  translation_client: impl TranslationClient,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> Result<PokemonResponse, SharedError> {
+  flights.run(key.clone(), || async move {
+    if let Some(translated) = cached_fresh(&key, fetch, &cache).await {
+      return Ok(translated)
+    }
+
+    let translated = translation_client
+      .translate(&pokemon, key.translation_type)
+      .await?;
+
+    info!("translated description");
+    pokemon.set_description(translated);
+
+    cache.insert(key, pokemon.clone()).await;
+    Ok(pokemon)
+  }).await
+}
+
+/// The public JSON form of a PokemonResponse
+#[derive(Serialize)]
+struct PokemonReply<'a> {
+  name: &'a str,
+  description: &'a str,
+  habitat: &'a str,
+  is_legendary: bool,
+}
+
+#[derive(Serialize)]
+struct DescriptionsReply<'a> {
+  name: &'a str,
+  descriptions: &'a [DistinctDescription],
+}
+
+/// The JSON form of a PokemonResponse served by the full route
+#[derive(Serialize)]
+struct FullPokemonReply<'a> {
+  #[serde(flatten)]
+  basic: PokemonReply<'a>,
+  #[serde(flatten)]
+  details: Option<&'a SpeciesDetails>,
+  language: &'a str,
+  version: Option<&'a str>,
+}
+
+impl<'a> PokemonReply<'a> {
+  fn new(pokemon: &'a PokemonResponse) -> Self {
+    Self {
+      name: pokemon.name(),
+      description: pokemon.description(),
+      habitat: pokemon.habitat(),
+      is_legendary: pokemon.is_legendary(),
+    }
   }
+}
+
+impl<'a> FullPokemonReply<'a> {
+  fn new(pokemon: &'a PokemonResponse, details: Option<&'a SpeciesDetails>) -> Self {
+    Self {
+      basic: PokemonReply::new(pokemon),
+      details,
+      language: pokemon.language(),
+      version: pokemon.version(),
+    }
+  }
+}
 
-  let res = {
-    translation_client
-      .translate(&pokemon, translate_to)
-      .await
+/// Filter to format a Served PokemonResponse into a warp Json type, marking 
+/// stale responses with the staleness header
+/// 
+/// Given `fields`, the full response is projected onto them, otherwise the 
+/// public four field form is served. The cached response is left complete, 
+/// and the species' details only looked up when a field of them is asked for.
+/// 
+/// The language the description is in is given by the Content-Language header, 
+/// and the game version it appeared in by the version header.
+async fn format(
+  served: Served,
+  fields: Option<Vec<String>>,
+  poke_client: impl PokeClient,
+  species: SpeciesCache,
+) -> Result<Response, Rejection> {
+  let res = match fields {
+    Some(fields) => {
+      let details = if fields.iter().any(|field| DETAIL_FIELDS.contains(&field.as_str())) {
+        Some(species_details(&served.pokemon, &poke_client, &species).await?)
+      } else {
+        None
+      };
+      json(&project(&served.pokemon, details.as_ref(), &fields)).into_response()
+    },
+    None => json(&PokemonReply::new(&served.pokemon)).into_response(),
   };
+  Ok(with_served_headers(res, &served))
+}
 
-  match res {
-    Ok(translated) => {
-      pokemon.set_description(translated);
+/// Get the details of a pokemon's species, with the genus in the language of 
+/// its description
+async fn species_details(
+  pokemon: &PokemonResponse,
+  poke_client: &impl PokeClient,
+  species: &SpeciesCache,
+) -> Result<SpeciesDetails, Rejection> {
+  let held = species.get(pokemon.name(), poke_client).await
+    .map_err(reject::custom)?;
+  Ok(held.details(pokemon.language()))
+}
 
-      cache.insert((pokemon.name().to_owned(), translate_to), pokemon.clone()).await;
-      Ok(pokemon)
-    },
-    Err(_err) => {
-      Ok(pokemon)
+/// A pokemon projected onto the fields asked for, serialized in the order they 
+/// were asked for
+struct Projection(Vec<(String, Value)>);
+
+impl Serialize for Projection {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(self.0.len()))?;
+    for (field, value) in &self.0 {
+      map.serialize_entry(field, value)?;
     }
+    map.end()
   }
 }
 
-/// Filter to format a plain PokemonResponse into a warp Json type, which implements Reply
-fn format(
-  pokemon: PokemonResponse
+/// Project the full form of a pokemon onto `fields`, each one of `FIELDS`, 
+/// keeping the first of any asked for more than once
+/// 
+/// Fields that aren't known, such as the version of responses cached before 
+/// it was recorded or details not given, are null.
+fn project(pokemon: &PokemonResponse, details: Option<&SpeciesDetails>, fields: &[String]) -> Projection {
+  let full = match serde_json::to_value(FullPokemonReply::new(pokemon, details)) {
+    Ok(Value::Object(full)) => full,
+    _ => Map::new(),
+  };
+
+  let mut projected: Vec<(String, Value)> = Vec::with_capacity(fields.len());
+  for field in fields {
+    if !projected.iter().any(|(seen, _)| seen == field) {
+      projected.push((field.clone(), full.get(field).cloned().unwrap_or(Value::Null)));
+    }
+  }
+  Projection(projected)
+}
+
+/// Format a Served PokemonResponse as the full response, with the same 
+/// headers as `format`
+fn format_full(
+  served: Served,
+  details: &SpeciesDetails,
+) -> Response {
+  let res = json(&FullPokemonReply::new(&served.pokemon, Some(details))).into_response();
+  with_served_headers(res, &served)
+}
+
+/// Set the language, version and staleness headers of a served pokemon
+fn with_served_headers(mut res: Response, served: &Served) -> Response {
+  let pokemon = &served.pokemon;
+  if let Ok(language) = HeaderValue::from_str(pokemon.language()) {
+    res.headers_mut().insert(CONTENT_LANGUAGE, language);
+  }
+  if let Some(Ok(version)) = pokemon.version().map(HeaderValue::from_str) {
+    res.headers_mut().insert(VERSION_HEADER, version);
+  }
+  if let Some(staleness) = served.stale {
+    res.headers_mut().insert(STALE_HEADER, HeaderValue::from_static(staleness.label()));
+  }
+
+  res
+}
+
+#[derive(Serialize)]
+struct StatusReply {
+  translation_breaker: Option<BreakerStatus>,
+}
+
+/// Report the state of the translation client's circuit breaker, if it has one
+fn status(
+  translation_client: impl TranslationClient,
 ) -> impl Reply {
-  json(&pokemon)
+  json(&StatusReply {
+    translation_breaker: translation_client.breaker_status(),
+  })
+}
+
+#[derive(Serialize)]
+struct HealthReply {
+  status: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReadinessReply {
+  status: &'static str,
+  dependencies: Dependencies,
+  cache: CacheReport,
+}
+
+#[derive(Serialize)]
+struct Dependencies {
+  pokeapi: DependencyReport,
+  funtranslations: DependencyReport,
+}
+
+#[derive(Serialize)]
+struct DependencyReport {
+  status: &'static str,
+  latency_ms: u64,
+  error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CacheReport {
+  entries: u64,
+}
+
+/// Time a dependency probe and report its outcome
+async fn probe(ping: impl Future<Output = Result<(), PokError>>) -> DependencyReport {
+  let started = Instant::now();
+  let res = ping.await;
+
+  DependencyReport {
+    status: if res.is_ok() { "up" } else { "down" },
+    latency_ms: started.elapsed().as_millis() as u64,
+    error: res.err().map(|err| err.to_string()),
+  }
+}
+
+/// Liveness check - if the server can answer at all, it is alive
+fn liveness() -> impl Reply {
+  json(&HealthReply { status: "ok" })
+}
+
+/// Readiness check, probing each upstream dependency concurrently
+/// 
+/// The service cannot answer anything without Pokeapi, so it is only ready if 
+/// Pokeapi is reachable. Without funtranslations it still serves untranslated 
+/// descriptions, so an unreachable translation API only marks it as degraded.
+pub async fn readiness_handler(
+  poke_client: impl PokeClient,
+  translation_client: impl TranslationClient,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> Result<impl Reply, Rejection> {
+  let (pokeapi, funtranslations) = tokio::join!(
+    probe(poke_client.ping()),
+    probe(translation_client.ping()),
+  );
+
+  let (status, code) = match (pokeapi.error.is_none(), funtranslations.error.is_none()) {
+    (false, _) => ("unavailable", StatusCode::SERVICE_UNAVAILABLE),
+    (true, false) => ("degraded", StatusCode::OK),
+    (true, true) => ("ready", StatusCode::OK),
+  };
+
+  let reply = ReadinessReply {
+    status,
+    dependencies: Dependencies { pokeapi, funtranslations },
+    cache: CacheReport { entries: cache.entry_count() },
+  };
+
+  Ok(with_status(json(&reply), code))
+}
+
+/// Render the metrics registry for scraping by Prometheus
+fn export_metrics() -> impl Reply {
+  with_header(metrics::gather(), CONTENT_TYPE, prometheus::TEXT_FORMAT)
+}
+
+/// Map a requested path back to the route template that served it, for use 
+/// as a metrics label
+fn route_label(path: &str) -> &'static str {
+  let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+  match segments.as_slice() {
+    ["pokemon", "translated", _] => "/pokemon/translated/{name}",
+    ["pokemon", _] => "/pokemon/{name}",
+    ["pokemon", _, "descriptions"] => "/pokemon/{name}/descriptions",
+    ["pokemon", _, "full"] => "/pokemon/{name}/full",
+    ["status"] => "/status",
+    ["metrics"] => "/metrics",
+    ["healthz"] => "/healthz",
+    ["readyz"] => "/readyz",
+    ["admin", "cache"] => "/admin/cache",
+    ["admin", "cache", "warm"] => "/admin/cache/warm",
+    ["admin", "cache", _, _] => "/admin/cache/{type}/{name}",
+    _ => "unmatched",
+  }
+}
+
+/// Query parameters accepted by the pokemon routes
+#[derive(Deserialize)]
+struct PokemonQuery {
+  lang: Option<String>,
+  version: Option<String>,
+  fields: Option<String>,
+}
+
+/// Extract the languages a request asks for descriptions in, followed by the 
+/// `fallback` languages, see `language::negotiate`
+pub(crate) fn with_languages(
+  fallback: Arc<Vec<String>>,
+) -> impl Filter<Extract = (Vec<String>,), Error = Rejection> + Clone {
+  warp::query::<PokemonQuery>()
+    .and(warp::header::optional::<String>("accept-language"))
+    .and_then(move |query: PokemonQuery, accept_language: Option<String>| {
+      let fallback = fallback.clone();
+      async move {
+        language::negotiate(query.lang.as_deref(), accept_language.as_deref(), &fallback)
+          .map_err(reject::custom)
+      }
+    })
+}
+
+/// Extract how a request asks for the game version of descriptions to be 
+/// chosen, the first listed unless a `version` query parameter is given
+pub(crate) fn with_version() -> impl Filter<Extract = (VersionSelection,), Error = Rejection> + Clone {
+  warp::query::<PokemonQuery>()
+    .and_then(|query: PokemonQuery| async move {
+      match query.version {
+        Some(version) => version.parse::<VersionSelection>()
+          .map_err(|err| reject::custom(PokError::InvalidVersion(err.0))),
+        None => Ok(VersionSelection::First),
+      }
+    })
+}
+
+/// Inject the languages descriptions fall back to
+pub(crate) fn with_fallback_languages(
+  languages: Arc<Vec<String>>,
+) -> impl Filter<Extract = (Arc<Vec<String>>,), Error = Infallible> + Clone {
+  warp::any().map(move || languages.clone())
+}
+
+/// Extract the fields a request asks for responses to be projected onto, 
+/// given by a comma separated `fields` query parameter
+/// 
+/// Fails with `PokError::UnknownField` should any not be one of `FIELDS`.
+pub(crate) fn with_fields() -> impl Filter<Extract = (Option<Vec<String>>,), Error = Rejection> + Clone {
+  warp::query::<PokemonQuery>()
+    .and_then(|query: PokemonQuery| async move {
+      let fields = match query.fields {
+        Some(fields) => fields,
+        None => return Ok(None),
+      };
+
+      let fields: Vec<String> = fields.split(',').map(|field| field.trim().to_owned()).collect();
+      match fields.iter().find(|field| !FIELDS.contains(&field.as_str())) {
+        Some(unknown) => Err(reject::custom(PokError::UnknownField(unknown.clone()))),
+        None => Ok(Some(fields)),
+      }
+    })
+}
+
+/// Reject requests asking for fields not in `FIELDS`, see `with_fields`
+/// 
+/// Chained after the path of each route taking fields, so that a request for 
+/// no route is not found, and ahead of its handler, so that a bad request 
+/// spends no upstream request.
+fn known_fields() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+  with_fields().map(|_| ()).untuple_one()
 }
 
 /// Inject PokeClient implementor for handlers to make requests with
-fn with_poke_client(
+pub(crate) fn with_poke_client(
   poke_client: impl PokeClient,
 ) -> impl Filter<Extract = (impl PokeClient,), Error = Infallible> + Clone {
   warp::any().map(move || poke_client.clone())
 }
 
 /// Inject TranslationClient implementor for handlers to make requests with
-fn with_translation_client(
+pub(crate) fn with_translation_client(
   translation_client: impl TranslationClient,
 ) -> impl Filter<Extract = (impl TranslationClient,), Error = Infallible> + Clone {
   warp::any().map(move || translation_client.clone())
 }
 
 /// Inject cache for handlers to insert and retrieve from
-fn with_cache(
-  cache: impl CacheWrapper<(String, TranslationType), PokemonResponse>,
-) -> impl Filter<Extract = (impl CacheWrapper<(String, TranslationType), PokemonResponse>,), Error = Infallible> + Clone {
+pub(crate) fn with_cache(
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> impl Filter<Extract = (impl CacheWrapper<CacheKey, PokemonResponse>,), Error = Infallible> + Clone {
   warp::any().map(move || cache.clone())
 }
 
+/// Inject the species held in memory for handlers to describe pokemon from
+pub(crate) fn with_species(
+  species: SpeciesCache,
+) -> impl Filter<Extract = (SpeciesCache,), Error = Infallible> + Clone {
+  warp::any().map(move || species.clone())
+}
+
+/// Inject the set of in-flight upstream requests for handlers to join
+pub(crate) fn with_flights(
+  flights: SingleFlight<CacheKey, PokemonResponse>,
+) -> impl Filter<Extract = (SingleFlight<CacheKey, PokemonResponse>,), Error = Infallible> + Clone {
+  warp::any().map(move || flights.clone())
+}
+
+/// Optional behaviour of the router
+#[derive(Clone, Debug)]
+pub struct RouterOptions {
+  /// Bearer token required by the admin routes, which are disabled without one
+  pub admin_token: Option<String>,
+  /// Languages descriptions fall back to, in order, after those a request 
+  /// asks for
+  pub languages: Vec<String>,
+  /// Species held in memory to describe pokemon from, see 
+  /// `SpeciesCache::from_policy` to hold them no longer than cached entries
+  pub species: SpeciesCache,
+}
+
+impl Default for RouterOptions {
+  fn default() -> Self {
+    Self {
+      admin_token: None,
+      languages: vec![DEFAULT_LANGUAGE.to_owned()],
+      species: SpeciesCache::new(),
+    }
+  }
+}
+
 /// Full router of available public API endpoints
 /// 
 /// For each path, the necessary client dependencies are injected, followed by 
 /// injection of a cache reference (Moka caches are wrapped in an atomic 
 /// reference count).
 /// 
-/// The "pokemon" route is simple - the PokeClient and cache are injected then 
-/// the handler is invoked.
+/// The "pokemon" route is simple - the requested languages are negotiated and 
+/// game version selection parsed, the PokeClient and cache are injected, then the handler is invoked.
 /// The "pokemon/translated" handler effectively is an extension of the 
 /// "pokemon" route - the same injection and handling as the "pokemon" route is 
 /// performed, then the response handed off to be injected into the advanced 
 /// handler along with a TranslationClient implementor and the cache again. In 
 /// this way, the advanced handler does not need to duplicate the code to 
 /// contact Pokeapi itself, and effectively reuses the basic handler to do so.
+/// Both routes share one set of in-flight upstream requests, so concurrent 
+/// cache misses on either are coalesced. Both may also be asked for only some 
+/// fields, which are checked ahead of either handler, see `format`.
+/// 
+/// The "pokemon/{name}/descriptions" route lists every distinct description 
+/// of a pokemon, and the "pokemon/{name}/full" route serves the basic response 
+/// along with every detail of the pokemon, both reusing the basic handler in 
+/// the same way.
+/// 
+/// The "status" route reports the state of the translation circuit breaker, 
+/// and the "metrics" route exports Prometheus metrics. The "healthz" and 
+/// "readyz" routes serve liveness and readiness probes respectively.
+/// 
+/// Every request is traced in a span carrying its method and path, and 
+/// counted in the HTTP metrics by route and status.
+/// 
+/// The admin routes are disabled, see `router_with_options` to enable them.
 pub fn router(
   poke_client: impl PokeClient,
   translation_client: impl TranslationClient,
-  cache: impl CacheWrapper<(String, TranslationType), PokemonResponse>,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+  router_with_options(poke_client, translation_client, cache, RouterOptions::default())
+}
+
+/// Full router, as in `router`, with optional behaviour enabled by `options`
+/// 
+/// Given an admin token, the "admin/cache" routes are served to requests 
+/// bearing it, see `admin::routes`.
+pub fn router_with_options(
+  poke_client: impl PokeClient,
+  translation_client: impl TranslationClient,
+  cache: impl CacheWrapper<CacheKey, PokemonResponse>,
+  options: RouterOptions,
 ) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
-  path!("pokemon" / String)
+  let flights = SingleFlight::new();
+  let species = options.species;
+  let languages = Arc::new(options.languages);
+
+  let admin_routes = admin::routes(
+    options.admin_token,
+    languages.clone(),
+    poke_client.clone(),
+    translation_client.clone(),
+    species.clone(),
+    cache.clone(),
+    flights.clone(),
+  );
+
+  let pokemon_routes = path!("pokemon" / String)
+    .and(known_fields())
+    .and(with_languages(languages.clone()))
+    .and(with_version())
     .and(with_poke_client(poke_client.clone()))
+    .and(with_species(species.clone()))
     .and(with_cache(cache.clone()))
+    .and(with_flights(flights.clone()))
     .and_then(basic_handler)
     .or(
       path!("pokemon" / "translated" / String)
+        .and(known_fields())
+        .and(with_languages(languages.clone()))
+        .and(with_version())
         .and(with_poke_client(poke_client.clone()))
+        .and(with_species(species.clone()))
         .and(with_cache(cache.clone()))
+        .and(with_flights(flights.clone()))
         .and_then(basic_handler)
         .and(with_translation_client(translation_client.clone()))
         .and(with_cache(cache.clone()))
+        .and(with_flights(flights.clone()))
         .and_then(advanced_handler)
     )
     .unify()
-    .map(|p| format(p))
+    .and(with_fields())
+    .and(with_poke_client(poke_client.clone()))
+    .and(with_species(species.clone()))
+    .and_then(format);
+
+  let full_route = path!("pokemon" / String / "full")
+    .and(with_languages(languages))
+    .and(with_version())
+    .and(with_poke_client(poke_client.clone()))
+    .and(with_species(species.clone()))
+    .and(with_cache(cache.clone()))
+    .and(with_flights(flights.clone()))
+    .and_then(full_handler);
+
+  let descriptions_route = path!("pokemon" / String / "descriptions")
+    .and(with_poke_client(poke_client.clone()))
+    .and(with_species(species))
+    .and_then(descriptions_handler);
+
+  let status_route = path!("status")
+    .and(with_translation_client(translation_client.clone()))
+    .map(status);
+
+  let healthz_route = path!("healthz")
+    .map(liveness);
+
+  let readyz_route = path!("readyz")
+    .and(with_poke_client(poke_client))
+    .and(with_translation_client(translation_client))
+    .and(with_cache(cache))
+    .and_then(readiness_handler);
+
+  let metrics_route = path!("metrics")
+    .map(export_metrics);
+
+  pokemon_routes
+    .or(full_route)
+    .or(descriptions_route)
+    .or(status_route)
+    .or(metrics_route)
+    .or(healthz_route)
+    .or(readyz_route)
+    .or(admin_routes)
     .recover(handle_reject)
+    .with(warp::log::custom(|info| metrics::record_http(route_label(info.path()), info.status(), info.elapsed())))
+    .with(warp::trace::request())
 }
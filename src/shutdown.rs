@@ -0,0 +1,166 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use hyper::{Body, Request, Server, service::{make_service_fn, service_fn, Service}};
+use tokio::{sync::oneshot, time::timeout};
+use tracing::{info, warn};
+use warp::{Filter, Reply};
+
+/// Counts of requests in flight, and of requests completed
+///
+/// Clones share counts, so the tracker can be cloned into each connection.
+#[derive(Clone, Default)]
+pub struct InFlight {
+  active: Arc<AtomicUsize>,
+  completed: Arc<AtomicUsize>,
+}
+
+impl InFlight {
+  /// Get the number of requests currently being handled.
+  pub fn active(&self) -> usize {
+    self.active.load(Ordering::SeqCst)
+  }
+
+  /// Get the number of requests handled to completion (or abandoned by the
+  /// client) so far.
+  pub fn completed(&self) -> usize {
+    self.completed.load(Ordering::SeqCst)
+  }
+
+  fn begin(&self) -> InFlightGuard {
+    self.active.fetch_add(1, Ordering::SeqCst);
+    InFlightGuard(self.clone())
+  }
+}
+
+/// Marks a request as in flight until dropped
+struct InFlightGuard(InFlight);
+
+impl Drop for InFlightGuard {
+  fn drop(&mut self) {
+    self.0.active.fetch_sub(1, Ordering::SeqCst);
+    self.0.completed.fetch_add(1, Ordering::SeqCst);
+  }
+}
+
+/// What happened to in-flight requests during a graceful shutdown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrainSummary {
+  /// Requests being handled when the shutdown signal was received
+  pub in_flight: usize,
+  /// Requests completed after the shutdown signal was received
+  pub drained: usize,
+  /// Requests still being handled when the drain deadline expired
+  pub abandoned: usize,
+}
+
+/// Serve the given routes until `signal` resolves, then shut down gracefully
+///
+/// Once signalled, the server stops accepting new connections and waits up to
+/// `drain_timeout` for in-flight requests to complete before giving up on
+/// them and aborting the server. A summary of the drain is logged and returned.
+///
+/// Binding happens immediately, so that the bound address (useful when binding
+/// to port 0) can be returned alongside the future that runs the server.
+pub fn serve<F>(
+  routes: F,
+  bind: SocketAddr,
+  drain_timeout: Duration,
+  signal: impl Future<Output = ()> + Send + 'static,
+) -> Result<(SocketAddr, impl Future<Output = DrainSummary>), hyper::Error>
+where
+  F: Filter<Error = Infallible> + Clone + Send + Sync + 'static,
+  F::Extract: Reply,
+{
+  let tracker = InFlight::default();
+  let service = warp::service(routes);
+
+  let make_service = {
+    let tracker = tracker.clone();
+    make_service_fn(move |_| {
+      let tracker = tracker.clone();
+      let service = service.clone();
+
+      async move {
+        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+          let guard = tracker.begin();
+          let res = service.clone().call(req);
+
+          async move {
+            let res = res.await;
+            drop(guard);
+            res
+          }
+        }))
+      }
+    })
+  };
+
+  let (stop_tx, stop_rx) = oneshot::channel::<()>();
+  let server = Server::try_bind(&bind)?.serve(make_service);
+  let addr = server.local_addr();
+  let server = server.with_graceful_shutdown(async {
+    stop_rx.await.ok();
+  });
+
+  let run = async move {
+    let mut server = tokio::spawn(server);
+
+    signal.await;
+    let in_flight = tracker.active();
+    let completed = tracker.completed();
+    info!(in_flight, drain_timeout_ms = drain_timeout.as_millis() as u64, "shutting down, draining in-flight requests");
+
+    stop_tx.send(()).ok();
+    let timed_out = timeout(drain_timeout, &mut server).await.is_err();
+
+    let summary = DrainSummary {
+      in_flight,
+      drained: tracker.completed() - completed,
+      abandoned: tracker.active(),
+    };
+
+    // Past the deadline the server is stopped outright, rather than left 
+    // running in the background
+    if timed_out {
+      server.abort();
+    }
+
+    if timed_out {
+      warn!(in_flight = summary.in_flight, drained = summary.drained, abandoned = summary.abandoned, "drain deadline expired, abandoning requests");
+    } else {
+      info!(in_flight = summary.in_flight, drained = summary.drained, abandoned = summary.abandoned, "drained in-flight requests");
+    }
+
+    summary
+  };
+
+  Ok((addr, run))
+}
+
+/// Resolve once the process receives SIGINT or (on unix) SIGTERM
+pub async fn signal() {
+  let interrupt = async {
+    tokio::signal::ctrl_c().await.expect("Install SIGINT handler");
+  };
+
+  #[cfg(unix)]
+  let terminate = async {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+      .expect("Install SIGTERM handler")
+      .recv()
+      .await;
+  };
+
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = interrupt => info!("received SIGINT"),
+    _ = terminate => info!("received SIGTERM"),
+  }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast::{self, Sender};
+use tracing::debug;
+
+use crate::util::{PokError, SharedError};
+
+type Flights<K, V> = Mutex<HashMap<K, Sender<Result<V, SharedError>>>>;
+
+/// Coalesces concurrent work for the same key into a single flight
+///
+/// The first caller for a key leads, running its work, while any caller
+/// arriving with the same key before the leader finishes waits for, and
+/// shares, the leader's result. Once the leader finishes the key is free
+/// again, so results are never reused - pair this with a cache, filled by the
+/// leader, for that.
+///
+/// Should a leader be cancelled before finishing (for example, because its
+/// client hung up), one of its followers takes over.
+///
+/// Clones share flights.
+pub struct SingleFlight<K, V> {
+  flights: Arc<Flights<K, V>>,
+}
+
+impl<K, V> Clone for SingleFlight<K, V> {
+  fn clone(&self) -> Self {
+    Self {
+      flights: self.flights.clone(),
+    }
+  }
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+  fn default() -> Self {
+    Self {
+      flights: Default::default(),
+    }
+  }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Run `work` for `key`, unless a flight for `key` is already under way, in
+  /// which case wait for and return its result instead.
+  pub async fn run<F, Fut>(&self, key: K, work: F) -> Result<V, SharedError>
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<V, PokError>>,
+  {
+    self.run_shared(key, || async move {
+      work().await.map_err(SharedError::from)
+    }).await
+  }
+
+  /// Run `work` for `key` as in `run`, for work that may fail with the shared
+  /// error of another flight it joins.
+  pub async fn run_shared<F, Fut>(&self, key: K, work: F) -> Result<V, SharedError>
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<V, SharedError>>,
+  {
+    loop {
+      let mut receiver = {
+        let mut flights = self.flights.lock().unwrap();
+        match flights.get(&key) {
+          Some(sender) => sender.subscribe(),
+          None => {
+            let (sender, _) = broadcast::channel(1);
+            flights.insert(key.clone(), sender);
+            break
+          }
+        }
+      };
+
+      debug!("joining in-flight request");
+      match receiver.recv().await {
+        Ok(res) => return res,
+        // The leader was cancelled, so try to take over
+        Err(_) => continue,
+      }
+    }
+
+    let flight = Flight { flights: &self.flights, key: Some(key) };
+    let res = work().await;
+    if let Some(sender) = flight.land() {
+      // Nobody waiting is not an error
+      sender.send(res.clone()).ok();
+    }
+
+    res
+  }
+}
+
+/// A led flight, removed from the in-flight set when landed or dropped
+struct Flight<'a, K: Hash + Eq, V> {
+  flights: &'a Flights<K, V>,
+  key: Option<K>,
+}
+
+impl<K: Hash + Eq, V> Flight<'_, K, V> {
+  fn land(mut self) -> Option<Sender<Result<V, SharedError>>> {
+    let key = self.key.take()?;
+    self.flights.lock().unwrap().remove(&key)
+  }
+}
+
+impl<K: Hash + Eq, V> Drop for Flight<'_, K, V> {
+  fn drop(&mut self) {
+    // Dropping the sender wakes followers, who then find the key free
+    if let (Some(key), Ok(mut flights)) = (self.key.take(), self.flights.lock()) {
+      flights.remove(&key);
+    }
+  }
+}
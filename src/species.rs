@@ -0,0 +1,145 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use moka::future::Cache;
+use tracing::debug;
+
+use crate::config::CachePolicy;
+use crate::models::poke_models::{DistinctDescription, PokemonSpecies};
+use crate::singleflight::SingleFlight;
+use crate::util::{PokeClient, PokError, SharedError};
+
+/// Maximum number of species held at once, unless created from a policy
+const SPECIES_CAPACITY: u64 = 1_000;
+
+/// How long a species is held for before it is fetched from Pokeapi again, 
+/// unless created from a policy that expires entries sooner
+const SPECIES_TIME_TO_LIVE: Duration = Duration::from_secs(60 * 60);
+
+/// Species fetched from Pokeapi, held in memory by name
+///
+/// Responses are cached for each way of asking for a pokemon - each list of
+/// languages and way of choosing a game version - but are all described from
+/// the same species. Holding the species as well means asking for a pokemon
+/// in a new way costs no further Pokeapi request, and neither does asking for
+/// its distinct descriptions or details. Concurrent requests for a species
+/// not held share a single Pokeapi request.
+///
+/// The distinct descriptions of a species are held alongside it once listed,
+/// rather than in every response described from it.
+///
+/// Clones share species and flights.
+#[derive(Clone)]
+pub struct SpeciesCache {
+  species: Cache<String, Arc<PokemonSpecies>>,
+  descriptions: Cache<String, Arc<Vec<DistinctDescription>>>,
+  flights: SingleFlight<String, Arc<PokemonSpecies>>,
+}
+
+impl Default for SpeciesCache {
+  fn default() -> Self {
+    Self::with_expiry(SPECIES_CAPACITY, SPECIES_TIME_TO_LIVE)
+  }
+}
+
+impl Debug for SpeciesCache {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SpeciesCache")
+      .field("species", &self.species.entry_count())
+      .finish()
+  }
+}
+
+impl SpeciesCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Create a cache holding up to `capacity` species, each for `time_to_live`.
+  pub fn with_expiry(capacity: u64, time_to_live: Duration) -> Self {
+    Self {
+      species: Cache::builder()
+        .max_capacity(capacity)
+        .time_to_live(time_to_live)
+        .build(),
+      descriptions: Cache::builder()
+        .max_capacity(capacity)
+        .time_to_live(time_to_live)
+        .build(),
+      flights: SingleFlight::new(),
+    }
+  }
+
+  /// Create a cache for the species of untranslated entries cached under 
+  /// `policy`, holding as many species as there are entries
+  /// 
+  /// Species are held for no longer than the policy's time to live or idle, so 
+  /// that an entry rebuilt once evicted is no older than the policy allows.
+  pub fn from_policy(policy: &CachePolicy) -> Self {
+    let time_to_live = [policy.time_to_live(), policy.time_to_idle()].into_iter()
+      .flatten()
+      .min()
+      .unwrap_or(SPECIES_TIME_TO_LIVE)
+      .min(SPECIES_TIME_TO_LIVE);
+    Self::with_expiry(policy.capacity, time_to_live)
+  }
+
+  /// Get a species, requesting it from Pokeapi only should it not be held.
+  pub async fn get(&self, name: &str, poke_client: &impl PokeClient) -> Result<Arc<PokemonSpecies>, SharedError> {
+    let name = name.to_owned();
+    if let Some(species) = self.species.get(&name) {
+      debug!(species = "hit", "describing pokemon from held species");
+      return Ok(species)
+    }
+
+    self.flights.run(name.clone(), || async move {
+      // Another flight may have landed since the species was missed
+      if let Some(species) = self.species.get(&name) {
+        return Ok(species)
+      }
+
+      self.request(name, poke_client).await
+    }).await
+  }
+
+  /// Get the distinct descriptions of a species, see 
+  /// `PokemonSpecies::distinct_descriptions`, listing them only should they 
+  /// not be held.
+  pub async fn descriptions(&self, name: &str, poke_client: &impl PokeClient) -> Result<Arc<Vec<DistinctDescription>>, SharedError> {
+    if let Some(descriptions) = self.descriptions.get(&name.to_owned()) {
+      return Ok(descriptions)
+    }
+
+    let descriptions = Arc::new(self.get(name, poke_client).await?.distinct_descriptions());
+    self.descriptions.insert(name.to_owned(), descriptions.clone()).await;
+    Ok(descriptions)
+  }
+
+  /// Request a species from Pokeapi, replacing any held, joining any request
+  /// for it already in flight.
+  pub async fn fetch(&self, name: &str, poke_client: &impl PokeClient) -> Result<Arc<PokemonSpecies>, SharedError> {
+    let name = name.to_owned();
+    self.flights.run(name.clone(), || self.request(name, poke_client)).await
+  }
+
+  /// Forget a species, so that it is next requested from Pokeapi.
+  pub async fn invalidate(&self, name: &str) {
+    self.species.invalidate(&name.to_owned()).await;
+    self.descriptions.invalidate(&name.to_owned()).await;
+  }
+
+  /// Forget every species.
+  pub fn invalidate_all(&self) {
+    self.species.invalidate_all();
+    self.descriptions.invalidate_all();
+  }
+
+  async fn request(&self, name: String, poke_client: &impl PokeClient) -> Result<Arc<PokemonSpecies>, PokError> {
+    let species = Arc::new(poke_client.get_pokemon(name.clone()).await?);
+    self.species.insert(name.clone(), species.clone()).await;
+    // Listed again from the new species when next asked for
+    self.descriptions.invalidate(&name).await;
+    Ok(species)
+  }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, info};
+
+use crate::breaker::BreakerStatus;
+use crate::metrics;
+use crate::models::poke_models::PokemonResponse;
+use crate::util::{TranslationClient, TranslationType, PokError};
+
+pub mod local;
+
+/// An object safe view of a TranslationClient, as held by a registry
+///
+/// Implemented for every TranslationClient, so that backends of different
+/// types can be chained together.
+#[async_trait]
+pub trait TranslationBackend: Send + Sync + 'static {
+  fn name(&self) -> &'static str;
+
+  async fn translate(&self, pokemon: &PokemonResponse, translate_to: TranslationType) -> Result<String, PokError>;
+
+  fn breaker_status(&self) -> Option<BreakerStatus>;
+
+  async fn ping(&self) -> Result<(), PokError>;
+}
+
+#[async_trait]
+impl<T: TranslationClient> TranslationBackend for T {
+  fn name(&self) -> &'static str {
+    self.backend_name()
+  }
+
+  async fn translate(&self, pokemon: &PokemonResponse, translate_to: TranslationType) -> Result<String, PokError> {
+    TranslationClient::translate(self, pokemon, translate_to).await
+  }
+
+  fn breaker_status(&self) -> Option<BreakerStatus> {
+    TranslationClient::breaker_status(self)
+  }
+
+  async fn ping(&self) -> Result<(), PokError> {
+    TranslationClient::ping(self).await
+  }
+}
+
+type Chain = Vec<Arc<dyn TranslationBackend>>;
+
+/// A TranslationClient choosing translation backends by translation type
+///
+/// Each translation type is given a chain of backends, tried in order until
+/// one succeeds - so that, for example, a rate limited funtranslations can
+/// fall back to the local translator. Only should every backend in the chain
+/// fail is the last failure returned. Translation types without a chain fail
+/// with `PokError::NoTranslator`.
+///
+/// Every attempt is counted in the exported translation metrics, by backend.
+///
+/// Clones share backends.
+#[derive(Clone, Default)]
+pub struct TranslationRegistry {
+  chains: Arc<HashMap<TranslationType, Chain>>,
+}
+
+impl TranslationRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set the backends tried, in order, for `translate_to`.
+  pub fn chain(mut self, translate_to: TranslationType, backends: impl IntoIterator<Item = Arc<dyn TranslationBackend>>) -> Self {
+    Arc::make_mut(&mut self.chains).insert(translate_to, backends.into_iter().collect());
+    self
+  }
+
+  /// Get the names of the backends tried for `translate_to`, in order.
+  pub fn backend_names(&self, translate_to: TranslationType) -> Vec<&'static str> {
+    self.chains.get(&translate_to)
+      .map(|chain| chain.iter().map(|backend| backend.name()).collect())
+      .unwrap_or_default()
+  }
+}
+
+#[async_trait]
+impl TranslationClient for TranslationRegistry {
+  const TRANSLATION_API: &'static str = "registry";
+
+  /// Registries hold no single url - see each backend for its own
+  fn get_translation_url(&self) -> String {
+    String::new()
+  }
+
+  async fn translate(&self, pokemon: &PokemonResponse, translate_to: TranslationType) -> Result<String, PokError> {
+    let chain = match self.chains.get(&translate_to) {
+      Some(chain) if !chain.is_empty() => chain,
+      _ => return Err(PokError::NoTranslator),
+    };
+
+    let mut last_error = None;
+    for (position, backend) in chain.iter().enumerate() {
+      let res = backend.translate(pokemon, translate_to).await;
+      metrics::record_translation(backend.name(), translate_to, res.is_ok());
+
+      match res {
+        Ok(translated) => {
+          if position > 0 {
+            info!(backend = backend.name(), "translated by fallback backend");
+          }
+          return Ok(translated)
+        },
+        Err(err) => {
+          debug!(backend = backend.name(), error = %err, "translation backend failed");
+          last_error = Some(err);
+        },
+      }
+    }
+
+    Err(last_error.unwrap_or(PokError::NoTranslator))
+  }
+
+  /// The breaker of the first backend with one
+  fn breaker_status(&self) -> Option<BreakerStatus> {
+    self.chains.values()
+      .flatten()
+      .find_map(|backend| backend.breaker_status())
+  }
+
+  /// Reachable if every translation type has a backend that can be reached
+  async fn ping(&self) -> Result<(), PokError> {
+    for chain in self.chains.values() {
+      let mut last_error = None;
+      for backend in chain {
+        match backend.ping().await {
+          Ok(()) => {
+            last_error = None;
+            break
+          },
+          Err(err) => last_error = Some(err),
+        }
+      }
+
+      if let Some(err) = last_error {
+        return Err(err)
+      }
+    }
+
+    Ok(())
+  }
+}
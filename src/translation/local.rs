@@ -0,0 +1,355 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::models::poke_models::PokemonResponse;
+use crate::util::{TranslationClient, TranslationType, PokError};
+
+/// Shakespearean replacements for common modern words
+const SHAKESPEARE_LEXICON: &[(&str, &str)] = &[
+  ("you", "thee"),
+  ("your", "thy"),
+  ("yours", "thine"),
+  ("yourself", "thyself"),
+  ("are", "art"),
+  ("has", "hath"),
+  ("does", "doth"),
+  ("could", "couldst"),
+  ("would", "wouldst"),
+  ("should", "shouldst"),
+  ("will", "wilt"),
+  ("build", "buildeth"),
+  ("when", "at which hour"),
+  ("before", "ere"),
+  ("often", "oft"),
+  ("here", "hither"),
+  ("there", "thither"),
+  ("why", "wherefore"),
+  ("perhaps", "haply"),
+  ("maybe", "mayhap"),
+  ("between", "betwixt"),
+  ("among", "amongst"),
+  ("until", "till"),
+  ("nothing", "naught"),
+  ("anything", "aught"),
+  ("soon", "anon"),
+  ("over", "o'er"),
+  ("never", "ne'er"),
+  ("ever", "e'er"),
+  ("even", "e'en"),
+  ("it's", "'tis"),
+  ("yes", "aye"),
+  ("hello", "good morrow"),
+];
+
+/// Verbs after which Yoda moves the rest of a clause to the front
+const YODA_AUXILIARIES: &[&str] = &[
+  "is", "isn't", "was", "wasn't", "are", "aren't", "were", "weren't",
+  "can", "can't", "cannot", "could", "couldn't", "will", "won't", "would", "wouldn't",
+  "may", "might", "must", "should", "shouldn't", "has", "have", "had",
+];
+
+/// Words that join the auxiliary before them in a verb group, as in "can't be"
+const VERB_GROUP_CONTINUATIONS: &[&str] = &["be", "been", "being", "not"];
+
+/// Pronouns that may lead a sentence as its whole subject
+const PRONOUNS: &[&str] = &["it", "he", "she", "they", "we", "i", "you"];
+
+/// Words that may lead a sentence's subject, followed by a single noun
+const DETERMINERS: &[&str] = &["the", "this", "that", "these", "those", "its", "their", "a", "an"];
+
+/// Adverbs that may separate a subject from its verb, as in "it sometimes appears"
+const ADVERBS: &[&str] = &["sometimes", "often", "always", "never", "usually", "also", "still", "even"];
+
+/// The rules followed by the local translator
+///
+/// Shakespeare replaces single words by those in a lexicon, keeping their case.
+///
+/// Yoda moves the object of each sentence in front of its subject and verb,
+/// turning "This pokemon is rarely seen." into "Rarely seen, this pokemon is."
+/// The verb is the first of the auxiliaries - such as "is" or "can't be" - or
+/// failing that, the verb following a leading pronoun or determiner and noun,
+/// as in "It sometimes appears". Only the object up to the first comma is
+/// moved, anything before the subject is kept in place, and sentences where no
+/// verb is found are left as they are.
+///
+/// `Rules::default` gives the built in rules, which can be added to.
+#[derive(Clone, Debug)]
+pub struct Rules {
+  /// Archaic words by lowercase modern word
+  lexicon: HashMap<String, String>,
+  /// Lowercase auxiliary verbs
+  auxiliaries: HashSet<String>,
+}
+
+impl Default for Rules {
+  fn default() -> Self {
+    Self::empty()
+      .words(SHAKESPEARE_LEXICON.iter().copied())
+      .auxiliaries(YODA_AUXILIARIES.iter().copied())
+  }
+}
+
+impl Rules {
+  /// Create rules with an empty lexicon and no auxiliaries, which leave text
+  /// much as it is.
+  pub fn empty() -> Self {
+    Self {
+      lexicon: HashMap::new(),
+      auxiliaries: HashSet::new(),
+    }
+  }
+
+  /// Have Shakespeare replace `modern` with `archaic`, whatever its case.
+  pub fn word(mut self, modern: &str, archaic: &str) -> Self {
+    self.lexicon.insert(normalise(modern), archaic.to_owned());
+    self
+  }
+
+  /// Have Shakespeare replace every modern word with its archaic pair.
+  pub fn words<'a>(self, words: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+    words.into_iter().fold(self, |rules, (modern, archaic)| rules.word(modern, archaic))
+  }
+
+  /// Have Yoda treat `verb` as an auxiliary.
+  pub fn auxiliary(mut self, verb: &str) -> Self {
+    self.auxiliaries.insert(normalise(verb));
+    self
+  }
+
+  /// Have Yoda treat every verb as an auxiliary.
+  pub fn auxiliaries<'a>(self, verbs: impl IntoIterator<Item = &'a str>) -> Self {
+    verbs.into_iter().fold(self, Self::auxiliary)
+  }
+
+  /// Translate `text` by these rules.
+  pub fn translate(&self, text: &str, translate_to: TranslationType) -> String {
+    match translate_to {
+      TranslationType::Yoda => self.yoda(text),
+      TranslationType::Shakespeare => self.shakespeare(text),
+      TranslationType::None => text.to_owned(),
+    }
+  }
+
+  /// Replace every word found in the lexicon.
+  pub fn shakespeare(&self, text: &str) -> String {
+    let mut translated = String::with_capacity(text.len());
+    let mut word = String::new();
+
+    for c in text.chars().map(Some).chain(std::iter::once(None)) {
+      match c {
+        Some(c) if c.is_alphabetic() || c == '\'' || c == '’' => {
+          word.push(c);
+          continue
+        },
+        _ => (),
+      }
+
+      if !word.is_empty() {
+        match self.lexicon.get(&normalise(&word)) {
+          Some(archaic) => translated.push_str(&match_case(&word, archaic)),
+          None => translated.push_str(&word),
+        }
+        word.clear();
+      }
+      translated.extend(c);
+    }
+
+    translated
+  }
+
+  /// Reorder every sentence around its verb.
+  pub fn yoda(&self, text: &str) -> String {
+    sentences(text)
+      .into_iter()
+      .map(|sentence| self.yoda_sentence(sentence))
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+
+  fn yoda_sentence(&self, sentence: &str) -> String {
+    let (body, terminator) = match sentence.char_indices().last() {
+      Some((index, '.' | '!' | '?')) => (&sentence[..index], &sentence[index..]),
+      _ => (sentence, ""),
+    };
+    let words: Vec<&str> = body.split_whitespace().collect();
+
+    let (verb_start, verb_end) = match self.find_verb(&words) {
+      Some(verb) => verb,
+      None => return sentence.to_owned(),
+    };
+
+    // Keep any leading clause, such as "When it rains,", in front of the subject
+    let subject_start = words[..verb_start].iter()
+      .rposition(|word| word.ends_with(','))
+      .map_or(0, |index| index + 1);
+    let object_end = words[verb_end..].iter()
+      .position(|word| word.ends_with(','))
+      .map_or(words.len(), |index| verb_end + index + 1);
+    if subject_start == verb_start || object_end == verb_end {
+      return sentence.to_owned()
+    }
+
+    let object = words[verb_end..object_end].join(" ");
+    let object = object.trim_end_matches(',');
+    let subject = words[subject_start..verb_end].join(" ");
+
+    let mut translated = String::new();
+    if subject_start > 0 {
+      translated.push_str(&words[..subject_start].join(" "));
+      translated.push(' ');
+      translated.push_str(object);
+      translated.push_str(", ");
+      translated.push_str(&subject);
+    } else {
+      translated.push_str(&capitalise(object));
+      translated.push_str(", ");
+      translated.push_str(&lowercase_first(&subject));
+    }
+    if object_end < words.len() {
+      translated.push_str(", ");
+      translated.push_str(&words[object_end..].join(" "));
+    }
+    translated.push_str(terminator);
+
+    translated
+  }
+
+  /// Find the first and one past the last index of a sentence's verb group
+  fn find_verb(&self, words: &[&str]) -> Option<(usize, usize)> {
+    let is = |word: &str, list: &[&str]| list.contains(&normalise(word).as_str());
+
+    if let Some(start) = words.iter().skip(1).position(|word| self.auxiliaries.contains(&normalise(word))) {
+      let start = start + 1;
+      let continuations = words[start + 1..].iter()
+        .take_while(|word| is(word, VERB_GROUP_CONTINUATIONS))
+        .count();
+      return Some((start, start + 1 + continuations))
+    }
+
+    let first = *words.first()?;
+    let subject_end = if is(first, PRONOUNS) {
+      1
+    } else if is(first, DETERMINERS) {
+      2
+    } else {
+      return None
+    };
+
+    let verb = subject_end + words.get(subject_end..)?.iter()
+      .take_while(|word| is(word, ADVERBS))
+      .count();
+    match words.get(verb) {
+      // After a noun, only take a verb that agrees with it, such as "controls"
+      Some(word) if subject_end == 1 || word.ends_with('s') => Some((verb, verb + 1)),
+      _ => None,
+    }
+  }
+}
+
+/// A TranslationClient translating entirely locally, by fixed rules
+///
+/// Translations are deterministic and never fail, so the local translator is
+/// best used as the last backend of a `TranslationRegistry` chain, standing in
+/// for funtranslations once it is rate limited. See `Rules` for how each
+/// translation type is translated.
+///
+/// Clones share rules.
+#[derive(Clone, Debug, Default)]
+pub struct LocalTranslator {
+  rules: Arc<Rules>,
+}
+
+impl LocalTranslator {
+  /// Create a translator following the built in rules.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Create a translator following the given rules.
+  pub fn with_rules(rules: Rules) -> Self {
+    Self {
+      rules: Arc::new(rules),
+    }
+  }
+}
+
+#[async_trait]
+impl TranslationClient for LocalTranslator {
+  const TRANSLATION_API: &'static str = "local";
+
+  /// Local translations contact no host
+  fn get_translation_url(&self) -> String {
+    String::new()
+  }
+
+  async fn translate(&self, pokemon: &PokemonResponse, translate_to: TranslationType) -> Result<String, PokError> {
+    Ok(self.rules.translate(pokemon.description(), translate_to))
+  }
+}
+
+/// Lowercase a word for lookup, straightening any curly apostrophes
+fn normalise(word: &str) -> String {
+  word.to_lowercase().replace('’', "'")
+}
+
+/// Split text into sentences, each keeping its terminating punctuation
+fn sentences(text: &str) -> Vec<&str> {
+  let mut sentences = Vec::new();
+  let mut start = 0;
+  let mut chars = text.char_indices().peekable();
+
+  while let Some((index, c)) = chars.next() {
+    let ends_sentence = matches!(c, '.' | '!' | '?')
+      && matches!(chars.peek(), None | Some((_, ' ' | '\n')));
+    if ends_sentence {
+      let end = index + c.len_utf8();
+      sentences.push(text[start..end].trim());
+      start = end;
+    }
+  }
+  if !text[start..].trim().is_empty() {
+    sentences.push(text[start..].trim());
+  }
+
+  sentences
+}
+
+/// Give `replacement` the case of `original` - lower, capitalised or upper
+fn match_case(original: &str, replacement: &str) -> String {
+  if is_capitals(original) {
+    replacement.to_uppercase()
+  } else if original.starts_with(char::is_uppercase) {
+    capitalise(replacement)
+  } else {
+    replacement.to_owned()
+  }
+}
+
+/// Whether a word is written in capitals, such as "YOU" or "REGICE’s"
+fn is_capitals(word: &str) -> bool {
+  let stem = word.split(['\'', '’']).next().unwrap_or_default();
+  stem.chars().filter(|c| c.is_alphabetic()).count() > 1 && !stem.chars().any(char::is_lowercase)
+}
+
+fn capitalise(text: &str) -> String {
+  let mut chars = text.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
+
+/// Lowercase the first letter, unless the first word is written in capitals
+fn lowercase_first(text: &str) -> String {
+  if is_capitals(text.split(' ').next().unwrap_or_default()) {
+    return text.to_owned()
+  }
+
+  let mut chars = text.chars();
+  match chars.next() {
+    Some(first) => first.to_lowercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
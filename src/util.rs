@@ -1,14 +1,24 @@
+use std::borrow::Cow;
 use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
 use core::hash::Hash;
 
 use hyper::StatusCode;
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 use thiserror::Error;
 use async_trait::async_trait;
-use warp::{Reply, Rejection, reject::{Reject, MethodNotAllowed}, reply, body::BodyDeserializeError};
-use moka::future::Cache;
+use warp::{Reply, Rejection, reject::{Reject, InvalidQuery, MethodNotAllowed, PayloadTooLarge}, reply, body::BodyDeserializeError};
+use moka::future::{Cache, ConcurrentCacheExt};
+use tracing::{debug, warn};
 
-use crate::models::poke_models::{PokemonSpecies, PokemonResponse};
+use crate::breaker::BreakerStatus;
+use crate::language::DEFAULT_LANGUAGE;
+use crate::metrics::{self, CacheLabel, CacheOperation};
+use crate::models::poke_models::{NamedAPIResourceList, PokemonSpecies, PokemonResponse, VersionSelection, FIELDS};
 
 /// Trait defining the functions an API object needs to contact Pokeapi
 /// 
@@ -16,6 +26,17 @@ use crate::models::poke_models::{PokemonSpecies, PokemonResponse};
 /// 
 /// `get_pokemonapi_url` is included as a test helper, allowing test functions 
 /// to modify what url an API under test contacts.
+/// 
+/// `list_species` returns a page of the names of every pokemon species, 
+/// starting `offset` species in and holding at most `limit` names.
+/// 
+/// `ping` is used by the readiness endpoint to check that Pokeapi can be 
+/// reached. Implementors that don't contact a remote service can rely on the 
+/// default, which always succeeds.
+/// 
+/// `forget_not_found` and `forget_all_not_found` clear pokemon remembered as 
+/// unknown to Pokeapi, so that admin invalidations reach them too. 
+/// Implementors that remember nothing can rely on the defaults.
 #[async_trait]
 pub trait PokeClient: Send + Sync + Clone + 'static {
   /// API host address - aka: authority
@@ -24,6 +45,16 @@ pub trait PokeClient: Send + Sync + Clone + 'static {
   fn get_pokeapi_url(&self) -> String;
 
   async fn get_pokemon(&self, pokemon: String) -> Result<PokemonSpecies, PokError>;
+
+  async fn list_species(&self, offset: u64, limit: u64) -> Result<NamedAPIResourceList, PokError>;
+
+  async fn ping(&self) -> Result<(), PokError> {
+    Ok(())
+  }
+
+  async fn forget_not_found(&self, _pokemon: &str) {}
+
+  async fn forget_all_not_found(&self) {}
 }
 
 /// Trait defining the methods an API object needs to contact funtranslations
@@ -32,6 +63,16 @@ pub trait PokeClient: Send + Sync + Clone + 'static {
 /// 
 /// `get_translation_url` is included as a test helper, allowing test functions 
 /// to modify what url an API under test contacts.
+/// 
+/// `backend_name` names the implementor in logs and metrics, and in the 
+/// translation section of the configuration. It defaults to `TRANSLATION_API`.
+/// 
+/// `breaker_status` reports the state of any circuit breaker guarding the 
+/// translation API - implementors without one can rely on the default.
+/// 
+/// `ping` is used by the readiness endpoint to check that the translation API 
+/// can be reached, and must not spend translation quota to do so. Implementors 
+/// that don't contact a remote service can rely on the default.
 #[async_trait]
 pub trait TranslationClient: Send + Sync + Clone + 'static {
   /// API host address - aka: authority
@@ -40,44 +81,114 @@ pub trait TranslationClient: Send + Sync + Clone + 'static {
   fn get_translation_url(&self) -> String;
 
   async fn translate(&self, pokemon: &PokemonResponse, translate_to: TranslationType) -> Result<String, PokError>;
+
+  fn backend_name(&self) -> &'static str {
+    Self::TRANSLATION_API
+  }
+
+  fn breaker_status(&self) -> Option<BreakerStatus> {
+    None
+  }
+
+  async fn ping(&self) -> Result<(), PokError> {
+    Ok(())
+  }
 }
 
 /// Trait defining cache insertion and get functions
 /// 
-/// This trait exists solely to improve testing ergonomics - when not testing 
-/// this just re-exports the existing cache functions. However, under test, 
-/// additional instrumentation can be attached allowing for inspection of cache 
-/// utilisation.
+/// This trait exists to improve testing ergonomics - under test, additional 
+/// instrumentation can be attached allowing for inspection of cache 
+/// utilisation - and to allow alternative cache backends, such as the disk 
+/// backed cache in `cache::disk`, to be swapped in.
+/// 
+/// `get` is async so that backends may read through to remote stores.
+/// 
+/// `lookup` additionally reports how fresh a value is, for backends that 
+/// support soft and hard expiry. Backends that don't can rely on the default, 
+/// which treats every value as fresh. `get` only returns values that are usable 
+/// without first trying to refresh them - fresh or stale, but not expired.
 #[async_trait]
 pub trait CacheWrapper<K, V>: Send + Sync + Clone + 'static
 where
   K: Hash + Eq + Send + Sync + 'static,
   V: Clone + Send + Sync + 'static,
 {
-  fn get(&self, key: &K) -> Option<V>;
+  async fn get(&self, key: &K) -> Option<V>;
+
+  async fn lookup(&self, key: &K) -> Option<(V, Freshness)> {
+    self.get(key).await.map(|value| (value, Freshness::Fresh))
+  }
 
   async fn insert(&self, key: K, value: V);
+
+  /// Remove the value held under `key`, if there is one
+  async fn invalidate(&self, key: &K);
+
+  /// Remove every value
+  async fn invalidate_all(&self);
+
+  /// The keys of every value currently held
+  fn keys(&self) -> Vec<K>;
+
+  /// The number of entries currently held, which may be approximate
+  fn entry_count(&self) -> u64;
+}
+
+/// How fresh a cached value is
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Freshness {
+  /// Within its soft time to live, and served as is
+  Fresh,
+  /// Past its soft time to live - served, but refreshed in the background
+  Stale,
+  /// Past its hard time to live - refreshed before serving, and only served 
+  /// should that fail
+  Expired,
 }
 
 /// Non-test implementation of the CacheWrapper trait.
 /// 
-/// Utilises the moka cache library.
+/// Utilises the moka cache library. Hits, misses and inserts are counted in 
+/// the exported cache metrics.
 #[derive(Clone)]
 pub struct MokaCache<K: Hash + Eq + Send + Sync + 'static, V: Clone + Send + Sync + 'static>(pub Cache<K, V>);
 
 #[async_trait]
 impl<K, V> CacheWrapper<K, V> for MokaCache<K, V> 
 where
-  K: Clone + Hash + Eq + Send + Sync + 'static,
+  K: CacheLabel + Clone + Hash + Eq + Send + Sync + 'static,
   V: Clone + Send + Sync + 'static,
 {
-  fn get(&self, key: &K) -> Option<V> {
-    self.0.get(key)
+  async fn get(&self, key: &K) -> Option<V> {
+    let value = self.0.get(key);
+    metrics::record_cache(key, if value.is_some() { CacheOperation::Hit } else { CacheOperation::Miss });
+    value
   }
 
   async fn insert(&self, key: K, value: V) {
+    metrics::record_cache(&key, CacheOperation::Insert);
     self.0.insert(key, value).await
   }
+
+  async fn invalidate(&self, key: &K) {
+    self.0.invalidate(key).await
+  }
+
+  async fn invalidate_all(&self) {
+    self.0.invalidate_all();
+  }
+
+  fn keys(&self) -> Vec<K> {
+    self.0.iter().map(|(key, _)| K::clone(&key)).collect()
+  }
+
+  fn entry_count(&self) -> u64 {
+    // Moka applies writes lazily - flush them so the count is current
+    self.0.sync();
+    self.0.entry_count()
+  }
 }
 
 /// The type of translation that is being requested
@@ -90,22 +201,139 @@ where
 /// This is utilised for caching purposes - by keying on not only the pokemon 
 /// name but also the translation type, both the untranslated and translated 
 /// pokemon objects can be cached simultaneously.
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum TranslationType {
   Yoda,
   Shakespeare,
   None
 }
 
-/// The to_string function is only used when generating the api path
+impl TranslationType {
+  /// Label used for this translation type in metrics and reports
+  /// 
+  /// Unlike `to_string`, this is defined for None.
+  pub fn label(&self) -> &'static str {
+    match self {
+      TranslationType::Yoda => "yoda",
+      TranslationType::Shakespeare => "shakespeare",
+      TranslationType::None => "none",
+    }
+  }
+}
+
+/// Parses the labels given by `label`, such as in admin request paths
+impl FromStr for TranslationType {
+  type Err = UnknownTranslationType;
+
+  fn from_str(label: &str) -> Result<Self, Self::Err> {
+    match label {
+      "yoda" => Ok(TranslationType::Yoda),
+      "shakespeare" => Ok(TranslationType::Shakespeare),
+      "none" => Ok(TranslationType::None),
+      _ => Err(UnknownTranslationType(label.to_owned())),
+    }
+  }
+}
+
+/// Key of a cached pokemon response
+/// 
+/// Alongside the pokemon's name and translation type, responses are keyed on 
+/// the languages their description was chosen from, most preferred first - 
+/// the same pokemon asked for in Japanese and in English is cached twice. 
+/// Likewise, they are keyed on how the game version of the description was 
+/// chosen. Keys described in the default language from the first version are
+/// serialized as the name and translation type alone, as they were before
+/// either was keyed on, so that persistent caches keep their earlier keys.
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
+#[serde(from = "SerializedKey", into = "SerializedKey")]
+pub struct CacheKey {
+  pub name: String,
+  pub translation_type: TranslationType,
+  pub languages: Vec<String>,
+  pub version: VersionSelection,
+}
+
+/// The forms a `CacheKey` is serialized in
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SerializedKey {
+  /// A pokemon described in the default language from the first version
+  Default(String, TranslationType),
+  Described {
+    name: String,
+    translation_type: TranslationType,
+    languages: Vec<String>,
+    #[serde(default, skip_serializing_if = "VersionSelection::is_first")]
+    version: VersionSelection,
+  },
+}
+
+impl From<SerializedKey> for CacheKey {
+  fn from(key: SerializedKey) -> Self {
+    match key {
+      SerializedKey::Default(name, translation_type) => CacheKey::new(name, translation_type),
+      SerializedKey::Described { name, translation_type, languages, version } => CacheKey { name, translation_type, languages, version },
+    }
+  }
+}
+
+impl From<CacheKey> for SerializedKey {
+  fn from(key: CacheKey) -> Self {
+    if key.version.is_first() && key.languages.len() == 1 && key.languages[0] == DEFAULT_LANGUAGE {
+      SerializedKey::Default(key.name, key.translation_type)
+    } else {
+      let CacheKey { name, translation_type, languages, version } = key;
+      SerializedKey::Described { name, translation_type, languages, version }
+    }
+  }
+}
+
+impl CacheKey {
+  /// Create a key for a pokemon described in the default language.
+  pub fn new(name: impl Into<String>, translation_type: TranslationType) -> Self {
+    Self {
+      name: name.into(),
+      translation_type,
+      languages: vec![DEFAULT_LANGUAGE.to_owned()],
+      version: VersionSelection::First,
+    }
+  }
+
+  /// Set the languages the description is chosen from, most preferred first.
+  pub fn languages(mut self, languages: Vec<String>) -> Self {
+    self.languages = languages;
+    self
+  }
+
+  /// Set how the game version of the description is chosen.
+  pub fn version(mut self, version: VersionSelection) -> Self {
+    self.version = version;
+    self
+  }
+
+  /// Get the key of the same pokemon and languages, translated another way.
+  pub fn with_translation_type(&self, translation_type: TranslationType) -> Self {
+    Self {
+      translation_type,
+      ..self.clone()
+    }
+  }
+}
+
+/// A label that names no translation type
+#[derive(Error, Debug)]
+#[error("Unknown translation type: {0}")]
+pub struct UnknownTranslationType(pub String);
+
+/// The Display implementation is only used when generating the api path
 /// 
 /// Panics if called on None, as asking for a translation to, effectively, no 
 /// language or scheme, is undefined.
-impl ToString for TranslationType {
-  fn to_string(&self) -> String {
+impl Display for TranslationType {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match self {
-      TranslationType::Yoda => String::from("yoda"),
-      TranslationType::Shakespeare => String::from("shakespeare"),
+      TranslationType::Yoda => write!(f, "yoda"),
+      TranslationType::Shakespeare => write!(f, "shakespeare"),
       TranslationType::None => unreachable!()
     }
   }
@@ -127,7 +355,42 @@ pub enum PokError {
   #[error("An error ocurred within warp")]
   Warp(#[from] warp::Error),
   #[error("No description for pokemon returned from pokeapi")]
-  NoDescription
+  NoDescription,
+  #[error("Pokemon not found by pokeapi")]
+  NotFound,
+  #[error("Upstream request timed out")]
+  Timeout,
+  #[error("Circuit breaker is open, upstream request skipped")]
+  CircuitOpen,
+  #[error("Local rate limit reached, upstream request skipped")]
+  RateLimited,
+  #[error("Missing or incorrect admin token")]
+  Unauthorized,
+  #[error("Failed to read or write local data")]
+  Io(#[from] io::Error),
+  #[error("No translation backend is configured for this translation type")]
+  NoTranslator,
+  #[error("Invalid language tag: {0}")]
+  InvalidLanguage(String),
+  #[error("Invalid game version: {0}")]
+  InvalidVersion(String),
+  #[error("No description for pokemon from game version: {0}")]
+  VersionNotFound(String),
+  #[error("Unknown field {0:?}, expected a comma separated list of: {}", FIELDS.join(", "))]
+  UnknownField(String),
+  #[error("Too many pokemon to warm at once: {0}")]
+  TooManyNames(usize),
+}
+
+/// A PokError shared between requests coalesced into a single upstream request
+#[derive(Error, Debug, Clone)]
+#[error(transparent)]
+pub struct SharedError(pub Arc<PokError>);
+
+impl From<PokError> for SharedError {
+  fn from(err: PokError) -> Self {
+    SharedError(Arc::new(err))
+  }
 }
 
 impl From<serde_json::Error> for PokError {
@@ -136,38 +399,78 @@ impl From<serde_json::Error> for PokError {
   }
 }
 
+/// Find the kind of the io error underlying a hyper error, if there is one
+/// 
+/// Hyper wraps io errors (such as timeouts and connection resets) in opaque 
+/// errors of its own, so the source chain has to be walked to find them.
+pub(crate) fn io_error_kind(err: &hyper::Error) -> Option<io::ErrorKind> {
+  let mut source = err.source();
+  while let Some(inner) = source {
+    if let Some(io) = inner.downcast_ref::<io::Error>() {
+      return Some(io.kind())
+    }
+    source = inner.source();
+  }
+
+  None
+}
+
 #[derive(Serialize)]
-struct ErrorReply {
-  message: String
+pub(crate) struct ErrorReply {
+  pub(crate) message: String
 }
 
 impl Reject for PokError {}
 
+impl Reject for SharedError {}
+
 /// Handle errors raised at runtime and generate appropriate HTTP error responses
 /// 
 /// Some of the returned status codes are only approximate, and would ideally 
 /// have greater inspection of the actual error.
 pub async fn handle_reject(err: Rejection) -> Result<impl Reply, Infallible> {
-  let (code, message) = if err.is_not_found() {
-    (StatusCode::NOT_FOUND, "Not Found")
-  } else if let Some(_) = err.find::<BodyDeserializeError>() {
-    (StatusCode::BAD_REQUEST, "Bad Request")
-  } else if let Some(_) = err.find::<MethodNotAllowed>() {
-    (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
-  } else if let Some(error) = err.find::<PokError>() {
+  let (code, message): (StatusCode, Cow<str>) = if err.is_not_found() {
+    (StatusCode::NOT_FOUND, "Not Found".into())
+  } else if err.find::<BodyDeserializeError>().is_some() {
+    (StatusCode::BAD_REQUEST, "Bad Request".into())
+  } else if err.find::<InvalidQuery>().is_some() {
+    // Such as a parameter given more than once, as in "?lang=en&lang=ja"
+    (StatusCode::BAD_REQUEST, "Invalid query string, expected each parameter at most once".into())
+  } else if err.find::<PayloadTooLarge>().is_some() {
+    (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large".into())
+  } else if err.find::<MethodNotAllowed>().is_some() {
+    (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed".into())
+  } else if let Some(error) = err.find::<PokError>().or_else(|| err.find::<SharedError>().map(|shared| &*shared.0)) {
     match error {
-      PokError::Hyper(_) | PokError::Warp(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
-      PokError::Parse(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to parse JSON response from API"),
-      PokError::Http(_) | PokError::Unavailable(_) => (StatusCode::BAD_GATEWAY, "Failed to connect to upstream service"),
-      PokError::NoDescription => (StatusCode::BAD_GATEWAY, "Pokeapi did not return a description for this pokemon")
+      PokError::Hyper(_) | PokError::Warp(_) | PokError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".into()),
+      PokError::Parse(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to parse JSON response from API".into()),
+      PokError::Http(_) | PokError::Unavailable(_) => (StatusCode::BAD_GATEWAY, "Failed to connect to upstream service".into()),
+      PokError::NoDescription => (StatusCode::BAD_GATEWAY, "Pokeapi did not return a description for this pokemon".into()),
+      PokError::NotFound => (StatusCode::NOT_FOUND, "Pokemon not found".into()),
+      PokError::Timeout => (StatusCode::GATEWAY_TIMEOUT, "Upstream service took too long to respond".into()),
+      PokError::CircuitOpen => (StatusCode::SERVICE_UNAVAILABLE, "Upstream service is failing, try again later".into()),
+      PokError::NoTranslator => (StatusCode::SERVICE_UNAVAILABLE, "Translation is unavailable".into()),
+      PokError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, "Upstream request quota exhausted, try again later".into()),
+      PokError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".into()),
+      PokError::InvalidLanguage(_) => (StatusCode::BAD_REQUEST, "Invalid language, expected a tag such as en or ja-Hrkt".into()),
+      PokError::InvalidVersion(_) => (StatusCode::BAD_REQUEST, "Invalid version, expected first, latest, random or a game such as sword".into()),
+      PokError::VersionNotFound(_) => (StatusCode::NOT_FOUND, "No description for this pokemon from that game version".into()),
+      PokError::UnknownField(_) => (StatusCode::BAD_REQUEST, error.to_string().into()),
+      PokError::TooManyNames(_) => (StatusCode::BAD_REQUEST, "Too many pokemon to warm at once".into()),
     }
   } else {
-    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".into())
   };
 
+  if code.is_server_error() {
+    warn!(status = code.as_u16(), rejection = ?err, "{}", message);
+  } else {
+    debug!(status = code.as_u16(), rejection = ?err, "{}", message);
+  }
+
   Ok(reply::with_status(
     reply::json(&ErrorReply {
-      message: message.to_owned()
+      message: message.into_owned()
     }),
     code
   ))
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use httpmock::{MockServer, Method::GET};
+use serde_json::{from_slice, from_value, json, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  models::poke_models::PokemonResponse,
+  util::{CacheWrapper, CacheKey, TranslationType},
+  server::{router, router_with_options, RouterOptions},
+};
+
+mod mock_impl;
+use mock_impl::{api, MockPokeAPI, MockTranslationAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+const TOKEN: &str = "Bearer hunter2";
+
+fn options() -> RouterOptions {
+  RouterOptions {
+    admin_token: Some(String::from("hunter2")),
+    ..RouterOptions::default()
+  }
+}
+
+#[tokio::test]
+async fn requires_admin_token() {
+  let admin = router_with_options(MockPokeAPI, MockTranslationAPI, MockCache::new(), options());
+
+  let missing = request().path("/admin/cache").reply(&admin).await;
+  assert_eq!(missing.status(), 401);
+
+  let wrong = request().method("DELETE").path("/admin/cache").header("authorization", "Bearer hunter3").reply(&admin).await;
+  assert_eq!(wrong.status(), 401);
+
+  let authorized = request().path("/admin/cache").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(authorized.status(), 200);
+
+  // Without a token the admin routes don't exist
+  let disabled = router(MockPokeAPI, MockTranslationAPI, MockCache::new());
+  let res = request().path("/admin/cache").header("authorization", TOKEN).reply(&disabled).await;
+  assert_eq!(res.status(), 404);
+}
+
+#[tokio::test]
+async fn inspects_entries() {
+  let cache = MockCache::new();
+  let admin = router_with_options(MockPokeAPI, MockTranslationAPI, cache, options());
+  request().path("/pokemon/translated/pikachu").reply(&admin).await;
+
+  let res = request().path("/admin/cache").header("authorization", TOKEN).reply(&admin).await;
+  let summary: Value = from_slice(res.body()).unwrap();
+  assert_eq!(summary, json!({ "entries": { "none": 1, "shakespeare": 1, "yoda": 0 }, "total": 2 }));
+
+  let res = request().path("/admin/cache/shakespeare/pikachu").header("authorization", TOKEN).reply(&admin).await;
+  let entry: Value = from_slice(res.body()).unwrap();
+  assert_eq!(entry["freshness"], "fresh");
+  assert_eq!(entry["translation_type"], "shakespeare");
+  assert_eq!(entry["value"]["name"], "pikachu");
+
+  // Translations are cached by the game served, which may also be named
+  let res = request().path("/admin/cache/shakespeare/pikachu?version=red").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(res.status(), 200);
+
+  let res = request().path("/admin/cache/yoda/pikachu").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(res.status(), 404);
+  let res = request().path("/admin/cache/klingon/pikachu").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(res.status(), 404);
+}
+
+#[tokio::test]
+async fn invalidates_entries() {
+  let cache = MockCache::new();
+  let admin = router_with_options(MockPokeAPI, MockTranslationAPI, cache.clone(), options());
+  request().path("/pokemon/translated/pikachu").reply(&admin).await;
+  request().path("/pokemon/diglett").reply(&admin).await;
+
+  let res = request().method("DELETE").path("/admin/cache/shakespeare/pikachu").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(res.status(), 204);
+  assert!(cache.get(&CacheKey::new("pikachu", TranslationType::Shakespeare)).await.is_none());
+  assert!(cache.get(&CacheKey::new("pikachu", TranslationType::None)).await.is_some());
+
+  let res = request().method("DELETE").path("/admin/cache").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(res.status(), 204);
+  assert_eq!(cache.entry_count(), 0);
+}
+
+#[tokio::test]
+async fn warms_requested_pokemon() {
+  let cache = MockCache::new();
+  let admin = router_with_options(MockPokeAPI, MockTranslationAPI, cache.clone(), options());
+
+  let res = request().method("POST").path("/admin/cache/warm")
+    .header("authorization", TOKEN)
+    .json(&json!({ "names": ["pikachu", "regice"], "translated": true }))
+    .reply(&admin).await;
+  let report: Value = from_slice(res.body()).unwrap();
+
+  assert_eq!(report, json!({ "warmed": ["pikachu", "regice"], "failed": [] }));
+  for path in ["/admin/cache/shakespeare/pikachu", "/admin/cache/yoda/regice"] {
+    let res = request().path(path).header("authorization", TOKEN).reply(&admin).await;
+    assert_eq!(res.status(), 200);
+  }
+  assert_eq!(cache.entry_count(), 4);
+}
+
+#[tokio::test]
+async fn warming_replaces_fresh_entries() {
+  let cache = MockCache::new();
+  let key = CacheKey::new("pikachu", TranslationType::None);
+  let cached: PokemonResponse = from_value(json!({ "name": "pikachu", "description": "Cached.", "habitat": "forest", "is_legendary": false }))
+    .expect("Build pokemon");
+  cache.insert(key.clone(), cached).await;
+  let admin = router_with_options(MockPokeAPI, MockTranslationAPI, cache.clone(), options());
+
+  request().method("POST").path("/admin/cache/warm")
+    .header("authorization", TOKEN)
+    .json(&json!({ "names": ["pikachu"] }))
+    .reply(&admin).await;
+
+  assert_ne!(cache.get(&key).await.unwrap().description(), "Cached.");
+}
+
+#[tokio::test]
+async fn reports_failures_to_warm() {
+  // Nothing is mocked, so pokeapi answers every request with a 404
+  let server = MockServer::start_async().await;
+  let api = api(&server);
+  let cache = MockCache::new();
+  let admin = router_with_options(api.clone(), api, cache.clone(), options());
+
+  let res = request().method("POST").path("/admin/cache/warm")
+    .header("authorization", TOKEN)
+    .json(&json!({ "names": ["notapokemon"] }))
+    .reply(&admin).await;
+  let report: Value = from_slice(res.body()).unwrap();
+
+  assert_eq!(report["warmed"], json!([]));
+  assert_eq!(report["failed"][0]["name"], "notapokemon");
+  assert_eq!(cache.entry_count(), 0);
+}
+
+#[tokio::test]
+async fn forgets_pokemon_not_found() {
+  let server = MockServer::start_async().await;
+  let missing = server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(404);
+  }).await;
+  let api = api(&server).cache_not_found(Duration::from_secs(60));
+  let admin = router_with_options(api.clone(), api, MockCache::new(), options());
+
+  assert_eq!(request().path("/pokemon/pikachu").reply(&admin).await.status(), 404);
+
+  // Pokeapi learns of the pokemon, but it is still remembered as not found
+  missing.delete_async().await;
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+  assert_eq!(request().path("/pokemon/pikachu").reply(&admin).await.status(), 404);
+
+  let res = request().method("DELETE").path("/admin/cache/none/pikachu").header("authorization", TOKEN).reply(&admin).await;
+  assert_eq!(res.status(), 204);
+  assert_eq!(request().path("/pokemon/pikachu").reply(&admin).await.status(), 200);
+}
+
+#[tokio::test]
+async fn limits_warm_requests() {
+  let cache = MockCache::new();
+  let admin = router_with_options(MockPokeAPI, MockTranslationAPI, cache.clone(), options());
+
+  let names: Vec<String> = (0..201).map(|n| format!("pokemon{}", n)).collect();
+  let res = request().method("POST").path("/admin/cache/warm")
+    .header("authorization", TOKEN)
+    .json(&json!({ "names": names }))
+    .reply(&admin).await;
+  assert_eq!(res.status(), 400);
+
+  let res = request().method("POST").path("/admin/cache/warm")
+    .header("authorization", TOKEN)
+    .json(&json!({ "names": ["pikachu".repeat(4096)] }))
+    .reply(&admin).await;
+  assert_eq!(res.status(), 413);
+  assert_eq!(cache.entry_count(), 0);
+}
@@ -0,0 +1,125 @@
+use std::fs::read;
+use std::time::Duration;
+
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use serde_json::{from_slice, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  breaker::{BreakerState, CircuitBreaker},
+  models::poke_models::PokemonResponse,
+  util::{CacheKey, MokaCache},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::MockPokeAPI;
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[test]
+fn breaker_transitions() {
+  let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+
+  breaker.try_acquire().unwrap().failure();
+  assert_eq!(breaker.status().state, BreakerState::Closed);
+
+  breaker.try_acquire().unwrap().failure();
+  assert_eq!(breaker.status().state, BreakerState::Open);
+  assert!(breaker.try_acquire().is_none());
+
+  std::thread::sleep(Duration::from_millis(30));
+
+  // Only a single trial is allowed through once half-open
+  let trial = breaker.try_acquire().unwrap();
+  assert_eq!(breaker.status().state, BreakerState::HalfOpen);
+  assert!(breaker.try_acquire().is_none());
+
+  trial.failure();
+  assert_eq!(breaker.status().state, BreakerState::Open);
+
+  std::thread::sleep(Duration::from_millis(30));
+
+  breaker.try_acquire().unwrap().success();
+  assert_eq!(breaker.status().state, BreakerState::Closed);
+  assert_eq!(breaker.status().consecutive_failures, 0);
+}
+
+#[test]
+fn dropped_trial_reopens_breaker() {
+  let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+  breaker.try_acquire().unwrap().failure();
+
+  std::thread::sleep(Duration::from_millis(30));
+
+  // A trial abandoned without an outcome, as by a cancelled request
+  drop(breaker.try_acquire().unwrap());
+  assert_eq!(breaker.status().state, BreakerState::Open);
+
+  std::thread::sleep(Duration::from_millis(30));
+  assert!(breaker.try_acquire().is_some());
+}
+
+#[tokio::test]
+async fn malformed_translation_counts_as_failure() {
+  let mock_server = MockServer::start_async().await;
+
+  mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(200)
+      .body("not json");
+  }).await;
+
+  let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+  let translation_client = API::new()
+    .override_uri(mock_server.address().to_string())
+    .disable_https()
+    .translation_breaker(breaker.clone());
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(MockPokeAPI, translation_client, cache);
+  request().path("/pokemon/translated/pikachu").reply(&router).await;
+
+  assert_eq!(breaker.status().state, BreakerState::Open);
+}
+
+#[tokio::test]
+async fn open_breaker_skips_translation() {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(429);
+  }).await;
+
+  let translation_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https()
+    .translation_breaker(CircuitBreaker::new(2, Duration::from_secs(60)));
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(MockPokeAPI, translation_client, cache);
+
+  for _ in 0..4 {
+    let res = request().path("/pokemon/translated/pikachu").reply(&router).await;
+
+    assert!(res.status().is_success());
+    assert_eq!(
+      res.body().to_vec(),
+      read(format!("{}/tests/assets/expected_pikachu.json", ROOT)).expect("Read test data")
+    );
+  }
+
+  assert_eq!(mock.hits_async().await, 2);
+
+  let res = request().path("/status").reply(&router).await;
+  let status = from_slice::<Value>(res.body()).expect("Parse json");
+
+  assert!(res.status().is_success());
+  assert_eq!(status["translation_breaker"]["state"], "open");
+  assert_eq!(status["translation_breaker"]["consecutive_failures"], 2);
+}
@@ -0,0 +1,150 @@
+use std::{env::temp_dir, fs::write, net::SocketAddr, path::PathBuf, process};
+
+use clap::Parser;
+
+use truelayer_coding_challenge::config::{Cli, Command, Config, ConfigError, LogFormat};
+
+fn cli(args: &[&str]) -> Cli {
+  Cli::try_parse_from(["truelayer_coding_challenge"].iter().chain(args)).expect("Parse flags")
+}
+
+#[test]
+fn empty_file_is_default() {
+  let config: Config = "".parse().expect("Parse config");
+
+  assert_eq!(config, Config::default());
+  assert_eq!(config.server.bind, SocketAddr::from(([0, 0, 0, 0], 8080)));
+  assert_eq!(config.cache.capacity, 1_000);
+  assert!(config.validate().is_ok());
+}
+
+#[test]
+fn flags_override_file() {
+  let path = temp_dir().join(format!("config_tests_{}.toml", process::id()));
+  write(&path, r#"
+    [server]
+    bind = "127.0.0.1:9000"
+
+    [cache]
+    capacity = 50
+    time_to_live_secs = 60
+
+    [pokeapi]
+    host = "localhost:8000"
+    https = false
+  "#).expect("Write config file");
+
+  let config = Config::from_cli(cli(&[
+    "--config", path.to_str().unwrap(),
+    "--cache-capacity", "10",
+    "--funtranslations-host", "localhost:8001",
+  ])).expect("Load config");
+
+  assert_eq!(config.server.bind, SocketAddr::from(([127, 0, 0, 1], 9000)));
+  assert_eq!(config.cache.capacity, 10);
+  assert_eq!(config.cache.time_to_live_secs, Some(60));
+  assert_eq!(config.pokeapi.host.as_deref(), Some("localhost:8000"));
+  assert!(!config.pokeapi.https);
+  assert_eq!(config.funtranslations.host.as_deref(), Some("localhost:8001"));
+  assert!(config.funtranslations.https);
+}
+
+#[test]
+fn rejects_unknown_fields() {
+  let res = "[cache]\ncapacty = 10".parse::<Config>();
+
+  assert!(matches!(res, Err(ConfigError::Parse(_))));
+}
+
+#[test]
+fn rejects_invalid_values() {
+  let zero_capacity = Config::from_cli(cli(&["--cache-capacity", "0"]));
+  assert!(matches!(zero_capacity, Err(ConfigError::Invalid { field: "cache.capacity", .. })));
+
+  let disk_without_path = Config::from_cli(cli(&["--cache-backend", "disk"]));
+  assert!(matches!(disk_without_path, Err(ConfigError::Invalid { field: "cache.path", .. })));
+
+  let redis_without_address = Config::from_cli(cli(&["--cache-backend", "redis"]));
+  assert!(matches!(redis_without_address, Err(ConfigError::Invalid { field: "cache.redis_address", .. })));
+
+  let bad_host = Config::from_cli(cli(&["--pokeapi-host", "https://pokeapi.co/"]));
+  assert!(matches!(bad_host, Err(ConfigError::Invalid { field: "pokeapi.host", .. })));
+
+  let short_deadline = Config::from_cli(cli(&["--read-timeout-ms", "500", "--deadline-ms", "100"]));
+  assert!(matches!(short_deadline, Err(ConfigError::Invalid { field: "timeouts.deadline_ms", .. })));
+}
+
+#[test]
+fn log_settings() {
+  let config = Config::from_cli(cli(&["--log-format", "json", "--log-level", "truelayer_coding_challenge=debug"]))
+    .expect("Load config");
+
+  assert_eq!(config.log.format, LogFormat::Json);
+  assert_eq!(config.log.level, "truelayer_coding_challenge=debug");
+
+  let bad_level = Config::from_cli(cli(&["--log-level", "loud=[[["]));
+  assert!(matches!(bad_level, Err(ConfigError::Invalid { field: "log.level", .. })));
+}
+
+#[test]
+fn separate_translated_cache_policy() {
+  let config = Config::from_cli(cli(&["--cache-ttl-secs", "60", "--translated-cache-capacity", "5000"]))
+    .expect("Load config");
+
+  assert_eq!(config.cache.untranslated().time_to_live_secs, Some(60));
+  assert_eq!(config.cache.translated.capacity, 5_000);
+  assert_eq!(config.cache.translated.time_to_live_secs, None);
+
+  let config: Config = "[cache.translated]\ntime_to_idle_secs = 600".parse().expect("Parse config");
+  assert_eq!(config.cache.translated.time_to_idle_secs, Some(600));
+  assert_eq!(config.cache.time_to_idle_secs, None);
+
+  let zero_capacity = Config::from_cli(cli(&["--translated-cache-capacity", "0"]));
+  assert!(matches!(zero_capacity, Err(ConfigError::Invalid { field: "cache.translated.capacity", .. })));
+
+  let soft_after_hard = Config::from_cli(cli(&["--translated-cache-soft-ttl-secs", "600", "--translated-cache-hard-ttl-secs", "60"]));
+  assert!(matches!(soft_after_hard, Err(ConfigError::Invalid { field: "cache.translated.soft_ttl_secs", .. })));
+}
+
+#[test]
+fn admin_token() {
+  assert_eq!(Config::from_cli(cli(&[])).expect("Load config").admin.token, None);
+
+  let config = Config::from_cli(cli(&["--admin-token", "hunter2"])).expect("Load config");
+  assert_eq!(config.admin.token.as_deref(), Some("hunter2"));
+
+  let empty = Config::from_cli(cli(&["--admin-token", " "]));
+  assert!(matches!(empty, Err(ConfigError::Invalid { field: "admin.token", .. })));
+}
+
+#[test]
+fn prewarm_settings() {
+  let config = Config::from_cli(cli(&["--prewarm", "true", "--prewarm-concurrency", "8"])).expect("Load config");
+  assert!(config.prewarm.enabled);
+  assert_eq!(config.prewarm.concurrency, 8);
+  assert_eq!(config.prewarm.page_size, 100);
+
+  let zero_concurrency = Config::from_cli(cli(&["--prewarm-concurrency", "0"]));
+  assert!(matches!(zero_concurrency, Err(ConfigError::Invalid { field: "prewarm.concurrency", .. })));
+}
+
+#[test]
+fn mirror_subcommand() {
+  let parsed = cli(&["--mirror-path", "data/pokeapi", "mirror", "--concurrency", "2"]);
+  assert!(matches!(parsed.command, Some(Command::Mirror { concurrency: 2 })));
+
+  let config = Config::from_cli(parsed).expect("Load config");
+  assert_eq!(config.mirror.path, Some(PathBuf::from("data/pokeapi")));
+  assert!(cli(&[]).command.is_none());
+}
+
+#[test]
+fn language_settings() {
+  assert_eq!(Config::default().language.fallback, ["en"]);
+
+  let config = Config::from_cli(cli(&["--fallback-languages", "fr,en"])).expect("Load config");
+  assert_eq!(config.language.fallback, ["fr", "en"]);
+
+  let bad_tag = Config::from_cli(cli(&["--fallback-languages", "en_GB"]));
+  assert!(matches!(bad_tag, Err(ConfigError::Invalid { field: "language.fallback", .. })));
+}
@@ -0,0 +1,83 @@
+use httpmock::{MockServer, Method::GET};
+use serde_json::{from_slice, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::server::{router, router_with_options, RouterOptions};
+
+mod mock_impl;
+use mock_impl::{api, species, MockPokeAPI, MockTranslationAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[test]
+fn deduplicates_descriptions() {
+  let distinct = species("pikachu").distinct_descriptions();
+  assert_eq!(distinct.len(), 117);
+
+  // Red and Blue share a description, wrapped differently
+  assert_eq!(distinct[0].description, "When several of these POKéMON gather, their electricity could build and cause lightning storms.");
+  assert_eq!(distinct[0].versions, ["red", "blue"]);
+  assert_eq!(distinct[0].languages, ["en"]);
+
+  assert!(distinct.iter().all(|entry| !entry.description.contains(['\n', '\u{0c}']) && !entry.description.contains("  ")));
+}
+
+#[tokio::test]
+async fn lists_distinct_descriptions() {
+  let router = router(MockPokeAPI, MockTranslationAPI, MockCache::new());
+
+  let res = request().path("/pokemon/regice/descriptions").reply(&router).await;
+  assert_eq!(res.status(), 200);
+
+  let body = from_slice::<Value>(res.body()).unwrap();
+  assert_eq!(body["name"], "regice");
+  assert_eq!(body["descriptions"].as_array().unwrap().len(), 56);
+  assert!(body["descriptions"][0]["versions"].is_array());
+
+  let res = request().path("/pokemon/missingno/descriptions").reply(&router).await;
+  assert_eq!(res.status(), 404);
+}
+
+#[tokio::test]
+async fn shares_species_with_basic_route() {
+  let mock_server = MockServer::start_async().await;
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+
+  let cache = MockCache::new();
+  let router = router(api(&mock_server), MockTranslationAPI, cache.clone());
+
+  request().path("/pokemon/pikachu").reply(&router).await;
+  for _ in 0..2 {
+    let res = request().path("/pokemon/pikachu/descriptions").reply(&router).await;
+    assert_eq!(res.status(), 200);
+  }
+
+  mock.assert_hits_async(1).await;
+  // Descriptions are held with the species, not in the cached response
+  assert_eq!(*cache.insert_count(), 1);
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert_eq!(from_slice::<Value>(res.body()).unwrap().as_object().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn lists_descriptions_whatever_the_fallback_languages() {
+  let options = RouterOptions {
+    languages: vec![String::from("xx")],
+    ..RouterOptions::default()
+  };
+  let cache = MockCache::new();
+  let router = router_with_options(MockPokeAPI, MockTranslationAPI, cache.clone(), options);
+
+  let res = request().path("/pokemon/pikachu/descriptions").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  let body = from_slice::<Value>(res.body()).unwrap();
+  assert_eq!(body["name"], "pikachu");
+  assert_eq!(body["descriptions"].as_array().unwrap().len(), 117);
+  assert_eq!(*cache.insert_count(), 0);
+}
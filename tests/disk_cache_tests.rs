@@ -0,0 +1,193 @@
+use std::fs::{create_dir_all, read_to_string, remove_dir, remove_file, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::from_slice;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  cache::disk::{DiskCache, DiskOptions},
+  models::poke_models::PokemonResponse,
+  util::{CacheWrapper, CacheKey, TranslationType},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{translated_key, MockPokeAPI, MockTranslationAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+/// A fresh cache file path, unique to the test and process
+fn cache_path(test: &str) -> PathBuf {
+  let path = std::env::temp_dir().join(format!("pokedex-{}-{}.jsonl", test, std::process::id()));
+  remove_file(&path).ok();
+  path
+}
+
+fn expected(file: &str) -> PokemonResponse {
+  from_slice(&std::fs::read(format!("{}/tests/assets/{}.json", ROOT, file)).expect("Read test data")).expect("Parse test data")
+}
+
+#[tokio::test]
+async fn translations_survive_restart() {
+  let path = cache_path("survive");
+
+  let cache = DiskCache::open(&path, MockCache::new()).await.expect("Open cache");
+  let first_run = router(MockPokeAPI, MockTranslationAPI, cache);
+  let res = request().path("/pokemon/translated/pikachu").reply(&first_run).await;
+  assert!(res.status().is_success());
+
+  let warmed = MockCache::new();
+  let restarted = DiskCache::open(&path, warmed.clone()).await.expect("Reopen cache");
+
+  assert_eq!(restarted.entry_count(), 2);
+  assert_eq!(*warmed.insert_count(), 2);
+
+  let translated = restarted.get(&translated_key("pikachu", TranslationType::Shakespeare)).await.expect("Translation restored");
+  assert_eq!(translated.description(), expected("expected_translated_pikachu").description());
+
+  // Served from the warmed cache, without contacting either API
+  let second_run = router(MockPokeAPI, MockTranslationAPI, restarted);
+  let res = request().path("/pokemon/translated/pikachu").reply(&second_run).await;
+  assert_eq!(from_slice::<PokemonResponse>(res.body()).unwrap().description(), translated.description());
+  assert_eq!(*warmed.insert_count(), 2);
+
+  remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn compacts_superseded_records() {
+  let path = cache_path("compact");
+  let key = CacheKey::new("pikachu", TranslationType::None);
+
+  let cache = DiskCache::open(&path, MockCache::new()).await.expect("Open cache");
+  cache.insert(key.clone(), expected("expected_pikachu")).await;
+  cache.insert(key.clone(), expected("expected_translated_pikachu")).await;
+  assert_eq!(read_to_string(&path).unwrap().lines().count(), 2);
+
+  let restarted = DiskCache::open(&path, MockCache::new()).await.expect("Reopen cache");
+
+  assert_eq!(read_to_string(&path).unwrap().lines().count(), 1);
+  assert_eq!(restarted.get(&key).await.unwrap().description(), expected("expected_translated_pikachu").description());
+
+  remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn skips_unreadable_records() {
+  let path = cache_path("corrupt");
+  let key = CacheKey::new("pikachu", TranslationType::None);
+
+  let cache = DiskCache::open(&path, MockCache::new()).await.expect("Open cache");
+  cache.insert(key.clone(), expected("expected_pikachu")).await;
+
+  // A write cut short part way through
+  OpenOptions::new().append(true).open(&path).unwrap()
+    .write_all(br#"{"key":["diglett","Yoda"],"val"#).unwrap();
+
+  let restarted = DiskCache::open(&path, MockCache::new()).await.expect("Reopen cache");
+
+  assert_eq!(restarted.entry_count(), 1);
+  assert!(restarted.get(&key).await.is_some());
+  assert_eq!(read_to_string(&path).unwrap().lines().count(), 1);
+
+  remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn restores_records_keyed_before_languages() {
+  let path = cache_path("legacy");
+  let value = serde_json::to_string(&expected("expected_pikachu")).unwrap();
+  std::fs::write(&path, format!("{{\"key\":[\"pikachu\",\"None\"],\"value\":{}}}\n", value)).unwrap();
+
+  let restarted = DiskCache::open(&path, MockCache::new()).await.expect("Reopen cache");
+
+  let key = CacheKey::new("pikachu", TranslationType::None);
+  assert_eq!(restarted.get(&key).await.unwrap().description(), expected("expected_pikachu").description());
+  // Keys in the default language from the first version keep their earlier form
+  assert!(read_to_string(&path).unwrap().starts_with(r#"{"key":["pikachu","None"],"#));
+
+  remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn rejects_unreadable_file() {
+  let path = cache_path("directory");
+  create_dir_all(&path).unwrap();
+
+  let res = DiskCache::<CacheKey, PokemonResponse, _>::open(&path, MockCache::new()).await;
+
+  assert!(res.is_err());
+
+  remove_dir(&path).ok();
+}
+
+#[tokio::test]
+async fn invalidations_survive_restart() {
+  let path = cache_path("invalidate");
+  let (pikachu, mewtwo) = (CacheKey::new("pikachu", TranslationType::None), CacheKey::new("mewtwo", TranslationType::None));
+
+  let cache = DiskCache::open(&path, MockCache::new()).await.expect("Open cache");
+  cache.insert(pikachu.clone(), expected("expected_pikachu")).await;
+  cache.insert(mewtwo.clone(), expected("expected_pikachu")).await;
+  cache.invalidate(&pikachu).await;
+  assert!(cache.get(&pikachu).await.is_none());
+
+  let restarted = DiskCache::open(&path, MockCache::new()).await.expect("Reopen cache");
+  assert!(restarted.get(&pikachu).await.is_none());
+  assert!(restarted.get(&mewtwo).await.is_some());
+  // The tombstone is compacted away along with the entry it buried
+  assert_eq!(read_to_string(&path).unwrap().lines().count(), 1);
+
+  restarted.invalidate_all().await;
+  let emptied = DiskCache::open(&path, MockCache::new()).await.expect("Reopen cache");
+  assert_eq!(emptied.entry_count(), 0);
+
+  remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn drops_expired_records() {
+  let path = cache_path("expire");
+  let (pikachu, translated) = (CacheKey::new("pikachu", TranslationType::None), CacheKey::new("pikachu", TranslationType::Yoda));
+
+  // One record written long ago, one written now
+  std::fs::write(&path, format!(
+    "{{\"key\":{},\"value\":{},\"written_at\":0}}\n",
+    serde_json::to_string(&pikachu).unwrap(),
+    serde_json::to_string(&expected("expected_pikachu")).unwrap(),
+  )).unwrap();
+  let cache = DiskCache::open(&path, MockCache::new()).await.expect("Open cache");
+  cache.insert(translated.clone(), expected("expected_translated_pikachu")).await;
+
+  let options = DiskOptions::default().time_to_live(|_: &CacheKey| Some(Duration::from_secs(60)));
+  let restarted = DiskCache::open_with(&path, MockCache::new(), options).await.expect("Reopen cache");
+
+  assert!(restarted.get(&pikachu).await.is_none());
+  assert!(restarted.get(&translated).await.is_some());
+  assert_eq!(read_to_string(&path).unwrap().lines().count(), 1);
+
+  remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn compacts_while_running() {
+  let path = cache_path("grow");
+  let key = CacheKey::new("pikachu", TranslationType::None);
+
+  let options = DiskOptions::default().compact_threshold(4 * 1024);
+  let cache = DiskCache::open_with(&path, MockCache::new(), options).await.expect("Open cache");
+  for _ in 0..100 {
+    cache.insert(key.clone(), expected("expected_pikachu")).await;
+  }
+
+  // Rewriting one key never leaves more than a few records behind
+  let lines = read_to_string(&path).unwrap().lines().count();
+  assert!(lines < 50, "{} lines", lines);
+
+  let restarted = DiskCache::open(&path, MockCache::new()).await.expect("Reopen cache");
+  assert_eq!(restarted.entry_count(), 1);
+
+  remove_file(&path).ok();
+}
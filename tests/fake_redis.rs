@@ -0,0 +1,156 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Stored values, with the time to live in milliseconds they were set with
+type Entries = HashMap<String, (Vec<u8>, Option<u64>)>;
+
+/// An in-process stand in for Redis, supporting GET, SET (with PX), DEL, SCAN
+/// (with MATCH on a prefix) and PING
+///
+/// Setting the server down closes every connection as soon as it sends a
+/// command, simulating an outage, and setting a delay holds back every reply.
+#[derive(Clone)]
+pub struct FakeRedis {
+  address: SocketAddr,
+  entries: Arc<Mutex<Entries>>,
+  down: Arc<AtomicBool>,
+  delay: Arc<Mutex<Duration>>,
+}
+
+impl FakeRedis {
+  pub async fn start() -> Self {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Bind fake redis");
+    let fake = Self {
+      address: listener.local_addr().unwrap(),
+      entries: Default::default(),
+      down: Default::default(),
+      delay: Default::default(),
+    };
+
+    let server = fake.clone();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(server.clone().serve(stream));
+      }
+    });
+
+    fake
+  }
+
+  pub fn address(&self) -> String {
+    self.address.to_string()
+  }
+
+  pub fn set_down(&self, down: bool) {
+    self.down.store(down, Ordering::SeqCst);
+  }
+
+  pub fn set_delay(&self, delay: Duration) {
+    *self.delay.lock().unwrap() = delay;
+  }
+
+  /// Get a stored value as a string
+  pub fn value(&self, key: &str) -> Option<String> {
+    self.entries.lock().unwrap().get(key).map(|(value, _)| String::from_utf8(value.clone()).unwrap())
+  }
+
+  /// Get the time to live, in milliseconds, a value was stored with
+  pub fn time_to_live(&self, key: &str) -> Option<u64> {
+    self.entries.lock().unwrap().get(key).and_then(|(_, ttl)| *ttl)
+  }
+
+  pub fn entry_count(&self) -> usize {
+    self.entries.lock().unwrap().len()
+  }
+
+  async fn serve(self, stream: TcpStream) {
+    let mut stream = BufStream::new(stream);
+
+    while let Some(args) = read_command(&mut stream).await {
+      if self.down.load(Ordering::SeqCst) {
+        return
+      }
+
+      let reply = match args[0].to_ascii_uppercase().as_slice() {
+        b"PING" => b"+PONG\r\n".to_vec(),
+        b"GET" => match self.entries.lock().unwrap().get(&String::from_utf8_lossy(&args[1]).into_owned()) {
+          Some((value, _)) => [format!("${}\r\n", value.len()).as_bytes(), value, b"\r\n"].concat(),
+          None => b"$-1\r\n".to_vec(),
+        },
+        b"SET" => {
+          let ttl = match args.get(3) {
+            Some(option) if option.eq_ignore_ascii_case(b"PX") => String::from_utf8_lossy(&args[4]).parse().ok(),
+            _ => None,
+          };
+          self.entries.lock().unwrap().insert(String::from_utf8_lossy(&args[1]).into_owned(), (args[2].clone(), ttl));
+          b"+OK\r\n".to_vec()
+        },
+        b"DEL" => {
+          let mut entries = self.entries.lock().unwrap();
+          let deleted = args[1..].iter()
+            .filter(|key| entries.remove(&String::from_utf8_lossy(key).into_owned()).is_some())
+            .count();
+          format!(":{}\r\n", deleted).into_bytes()
+        },
+        // Every match is returned in one batch, with the cursor finished
+        b"SCAN" => {
+          let pattern = match args.get(2) {
+            Some(option) if option.eq_ignore_ascii_case(b"MATCH") => String::from_utf8_lossy(&args[3]).into_owned(),
+            _ => String::from("*"),
+          };
+          let prefix = pattern.trim_end_matches('*').replace('\\', "");
+          let keys: Vec<String> = self.entries.lock().unwrap().keys()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+          let mut reply = format!("*2\r\n$1\r\n0\r\n*{}\r\n", keys.len()).into_bytes();
+          for key in keys {
+            reply.extend(format!("${}\r\n{}\r\n", key.len(), key).into_bytes());
+          }
+          reply
+        },
+        _ => b"-ERR unknown command\r\n".to_vec(),
+      };
+
+      let delay = *self.delay.lock().unwrap();
+      tokio::time::sleep(delay).await;
+
+      if stream.write_all(&reply).await.is_err() || stream.flush().await.is_err() {
+        return
+      }
+    }
+  }
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> Option<String> {
+  let mut line = String::new();
+  match stream.read_line(&mut line).await {
+    Ok(0) | Err(_) => None,
+    Ok(_) => Some(line.trim_end().to_owned()),
+  }
+}
+
+/// Read a command, sent as an array of bulk strings
+async fn read_command(stream: &mut BufStream<TcpStream>) -> Option<Vec<Vec<u8>>> {
+  let count: usize = read_line(stream).await?.strip_prefix('*')?.parse().ok()?;
+
+  let mut args = Vec::with_capacity(count);
+  for _ in 0..count {
+    let len: usize = read_line(stream).await?.strip_prefix('$')?.parse().ok()?;
+    let mut arg = vec![0; len + 2];
+    stream.read_exact(&mut arg).await.ok()?;
+    arg.truncate(len);
+    args.push(arg);
+  }
+
+  Some(args)
+}
@@ -0,0 +1,90 @@
+use serde_json::{from_slice, from_value, json, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  models::poke_models::PokemonResponse,
+  server::router,
+  util::{CacheKey, CacheWrapper, TranslationType},
+};
+
+mod mock_impl;
+use mock_impl::{MockPokeAPI, MockTranslationAPI, MockCache};
+
+fn fields(body: &[u8]) -> Vec<String> {
+  from_slice::<Value>(body).unwrap().as_object().unwrap().keys().cloned().collect()
+}
+
+#[tokio::test]
+async fn projects_responses() {
+  let router = router(MockPokeAPI, MockTranslationAPI, MockCache::new());
+
+  let res = request().path("/pokemon/pikachu?fields=name,description").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(fields(res.body()), ["description", "name"]);
+  assert_eq!(res.headers()["content-language"], "en");
+
+  let res = request().path("/pokemon/translated/pikachu?fields=description").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(from_slice::<Value>(res.body()).unwrap(), json!({ "description": "At which hour several of these pokémon gather,  their electricity couldst buildeth and cause lightning storms." }));
+
+  // Fields of the full response can be asked for too
+  let res = request().path("/pokemon/pikachu?fields=name,%20id,genus").reply(&router).await;
+  assert_eq!(from_slice::<Value>(res.body()).unwrap(), json!({ "name": "pikachu", "id": 25, "genus": "Mouse Pokémon" }));
+
+  // Fields asked for twice are served once
+  let res = request().path("/pokemon/pikachu?fields=name,name").reply(&router).await;
+  assert_eq!(from_slice::<Value>(res.body()).unwrap(), json!({ "name": "pikachu" }));
+
+  // Fields are served in the order asked for
+  let res = request().path("/pokemon/pikachu?fields=name,habitat,description,name").reply(&router).await;
+  let body = String::from_utf8(res.body().to_vec()).unwrap();
+  assert!(body.starts_with(r#"{"name":"pikachu","habitat":"forest","description":"#), "{}", body);
+}
+
+#[tokio::test]
+async fn rejects_unknown_fields() {
+  let router = router(MockPokeAPI, MockTranslationAPI, MockCache::new());
+
+  // Fields are listed in one parameter, so repeating it is a bad request too
+  for path in ["/pokemon/pikachu?fields=name,weight", "/pokemon/translated/pikachu?fields=weight", "/pokemon/pikachu?fields=", "/pokemon/pikachu?fields=name&fields=description"] {
+    let res = request().path(path).reply(&router).await;
+    assert_eq!(res.status(), 400, "{}", path);
+  }
+
+  let res = request().path("/pokemon/pikachu?fields=name,weight").reply(&router).await;
+  let message = from_slice::<Value>(res.body()).unwrap()["message"].as_str().unwrap().to_owned();
+  assert!(message.contains("\"weight\""), "{}", message);
+  assert!(message.contains("name, description, habitat, is_legendary"), "{}", message);
+
+  // Requests for no route are not found, whatever fields they ask for
+  for path in ["/nope?fields=weight", "/pokemon/pikachu/extra?fields=weight"] {
+    let res = request().path(path).reply(&router).await;
+    assert_eq!(res.status(), 404, "{}", path);
+  }
+}
+
+#[tokio::test]
+async fn caches_complete_responses() {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  request().path("/pokemon/pikachu?fields=name").reply(&router).await;
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert_eq!(fields(res.body()), ["description", "habitat", "is_legendary", "name"]);
+
+  // Projections share the unprojected cache entry
+  assert_eq!(*cache.insert_count(), 1);
+}
+
+#[tokio::test]
+async fn projects_details_of_old_entries() {
+  let cache = MockCache::new();
+  let legacy: PokemonResponse = from_value(json!({ "name": "pikachu", "description": "Cached.", "habitat": "forest", "is_legendary": false }))
+    .expect("Build pokemon");
+  cache.insert(CacheKey::new("pikachu", TranslationType::None), legacy).await;
+
+  let router = router(MockPokeAPI, MockTranslationAPI, cache);
+  // Details come from the species, but the version was never recorded
+  let res = request().path("/pokemon/pikachu?fields=description,id,version").reply(&router).await;
+  assert_eq!(from_slice::<Value>(res.body()).unwrap(), json!({ "description": "Cached.", "id": 25, "version": null }));
+}
@@ -0,0 +1,113 @@
+use serde_json::{from_slice, from_value, json, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  models::poke_models::{PokemonResponse, PokemonSpecies, SpeciesDetails, VersionSelection},
+  server::router,
+  util::{CacheKey, CacheWrapper, TranslationType},
+};
+
+mod mock_impl;
+use mock_impl::{species, MockPokeAPI, MockTranslationAPI, MockCache};
+
+#[test]
+fn extracts_species_details() {
+  assert_eq!(species("pikachu").details("en"), SpeciesDetails {
+    id: Some(25),
+    genus: Some(String::from("Mouse Pokémon")),
+    generation: Some(String::from("generation-i")),
+    is_mythical: Some(false),
+    color: Some(String::from("yellow")),
+    shape: Some(String::from("quadruped")),
+    capture_rate: Some(190),
+    evolution_chain: Some(10),
+  });
+
+  let arceus = species("arceus");
+  assert_eq!(arceus.details("en").is_mythical, Some(true));
+  assert_eq!(arceus.genus("ja"), Some("そうぞうポケモン"));
+  // Genera missing in a language fall back to English
+  assert_eq!(arceus.genus("xx"), Some("Alpha Pokémon"));
+}
+
+#[test]
+fn tolerates_species_without_details() {
+  let species: PokemonSpecies = from_value(json!({
+    "name": "pikachu",
+    "flavor_text_entries": [{ "flavor_text": "Sparks.", "language": { "name": "en" }, "version": { "name": "red" } }],
+    "habitat": null,
+    "is_legendary": false,
+  })).expect("Parse species");
+
+  let pokemon = PokemonResponse::from_species(&species, &[String::from("en")], &VersionSelection::First).expect("Describe species");
+  assert_eq!(pokemon.description(), "Sparks.");
+  assert_eq!(species.details("en"), SpeciesDetails {
+    id: None,
+    genus: None,
+    generation: None,
+    is_mythical: None,
+    color: None,
+    shape: None,
+    capture_rate: None,
+    evolution_chain: None,
+  });
+}
+
+#[tokio::test]
+async fn serves_full_response() {
+  let router = router(MockPokeAPI, MockTranslationAPI, MockCache::new());
+
+  let res = request().path("/pokemon/regice/full").reply(&router).await;
+  assert_eq!(res.status(), 200);
+
+  let body = from_slice::<Value>(res.body()).unwrap();
+  assert_eq!(body["name"], "regice");
+  assert_eq!(body["habitat"], "cave");
+  assert_eq!(body["id"], 378);
+  assert_eq!(body["genus"], "Iceberg Pokémon");
+  assert_eq!(body["generation"], "generation-iii");
+  assert_eq!(body["is_mythical"], false);
+  assert_eq!(body["capture_rate"], 3);
+  assert_eq!(body["evolution_chain"], 194);
+  assert_eq!(body["language"], "en");
+  assert_eq!(body["version"], "ruby");
+
+  let res = request().path("/pokemon/pikachu/full?lang=ja").reply(&router).await;
+  let body = from_slice::<Value>(res.body()).unwrap();
+  assert_eq!(body["genus"], "ねずみポケモン");
+  assert_eq!(res.headers()["content-language"], "ja");
+}
+
+#[tokio::test]
+async fn keeps_basic_response_unchanged() {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  request().path("/pokemon/pikachu/full").reply(&router).await;
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+
+  let body = from_slice::<Value>(res.body()).unwrap();
+  let fields: Vec<&str> = body.as_object().unwrap().keys().map(String::as_str).collect();
+  assert_eq!(fields, ["description", "habitat", "is_legendary", "name"]);
+  // Both routes share a cache entry
+  assert_eq!(*cache.insert_count(), 1);
+}
+
+#[tokio::test]
+async fn serves_details_of_old_entries() {
+  let cache = MockCache::new();
+  let key = CacheKey::new("pikachu", TranslationType::None);
+  let legacy: PokemonResponse = from_value(json!({ "name": "pikachu", "description": "Cached.", "habitat": "forest", "is_legendary": false }))
+    .expect("Build pokemon");
+  cache.insert(key.clone(), legacy).await;
+
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+  let res = request().path("/pokemon/pikachu/full").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  let body = from_slice::<Value>(res.body()).unwrap();
+  assert_eq!(body["description"], "Cached.");
+  assert_eq!(body["id"], 25);
+
+  // Details are taken from the species, leaving the cached entry in place
+  assert_eq!(*cache.insert_count(), 1);
+}
@@ -3,7 +3,7 @@ use std::{fs::read, convert::Infallible};
 use moka::future::Cache;
 use warp::{test::request, Filter, Reply};
 
-use truelayer_coding_challenge::{server::*, util::{TranslationType, MokaCache}, models::poke_models::PokemonResponse};
+use truelayer_coding_challenge::{server::*, util::{CacheKey, MokaCache}, models::poke_models::PokemonResponse};
 
 mod mock_impl;
 use mock_impl::{MockPokeAPI, MockTranslationAPI};
@@ -11,7 +11,7 @@ use mock_impl::{MockPokeAPI, MockTranslationAPI};
 const ROOT: &str = env!("CARGO_MANIFEST_DIR");
 
 fn setup() -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
-  let cache: MokaCache<(String, TranslationType), PokemonResponse> = MokaCache(Cache::new(1_000));
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
   router(MockPokeAPI, MockTranslationAPI, cache)
 }
 
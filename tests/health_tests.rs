@@ -0,0 +1,93 @@
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use serde_json::{from_slice, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  models::poke_models::PokemonResponse,
+  util::{CacheKey, MokaCache},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{MockPokeAPI, MockTranslationAPI};
+
+fn cache() -> MokaCache<CacheKey, PokemonResponse> {
+  MokaCache(Cache::new(1_000))
+}
+
+#[tokio::test]
+async fn liveness() {
+  let router = router(MockPokeAPI, MockTranslationAPI, cache());
+
+  let res = request().path("/healthz").reply(&router).await;
+
+  assert!(res.status().is_success());
+}
+
+#[tokio::test]
+async fn ready_with_cache_entries() {
+  let router = router(MockPokeAPI, MockTranslationAPI, cache());
+
+  request().path("/pokemon/translated/pikachu").reply(&router).await;
+  let res = request().path("/readyz").reply(&router).await;
+  let body = from_slice::<Value>(res.body()).expect("Parse json");
+
+  assert_eq!(res.status(), 200);
+  assert_eq!(body["status"], "ready");
+  assert_eq!(body["dependencies"]["pokeapi"]["status"], "up");
+  assert_eq!(body["cache"]["entries"], 2);
+}
+
+#[tokio::test]
+async fn degraded_without_translations() {
+  let pokeapi = MockServer::start_async().await;
+  let funtranslations = MockServer::start_async().await;
+
+  let pokeapi_root = pokeapi.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/");
+    then.status(200);
+  }).await;
+  let funtranslations_root = funtranslations.mock_async(|when, then| {
+    when.method(GET)
+      .path("/");
+    then.status(503);
+  }).await;
+
+  let api = API::new()
+    .override_pokeapi_uri(pokeapi.address().to_string())
+    .override_translation_uri(funtranslations.address().to_string())
+    .disable_https();
+
+  let router = router(api.clone(), api, cache());
+
+  let res = request().path("/readyz").reply(&router).await;
+  let body = from_slice::<Value>(res.body()).expect("Parse json");
+
+  assert_eq!(res.status(), 200);
+  assert_eq!(body["status"], "degraded");
+  assert_eq!(body["dependencies"]["pokeapi"]["status"], "up");
+  assert_eq!(body["dependencies"]["funtranslations"]["status"], "down");
+
+  pokeapi_root.assert_async().await;
+  funtranslations_root.assert_async().await;
+}
+
+#[tokio::test]
+async fn unavailable_without_pokeapi() {
+  let poke_client = API::new()
+    .override_uri(String::from("127.0.0.1:1"))
+    .disable_https();
+
+  let router = router(poke_client, MockTranslationAPI, cache());
+
+  let res = request().path("/readyz").reply(&router).await;
+  let body = from_slice::<Value>(res.body()).expect("Parse json");
+
+  assert_eq!(res.status(), 503);
+  assert_eq!(body["status"], "unavailable");
+  assert_eq!(body["dependencies"]["pokeapi"]["status"], "down");
+  assert!(body["dependencies"]["pokeapi"]["error"].is_string());
+}
@@ -1,4 +1,5 @@
 use std::fs::read;
+use std::time::Duration;
 
 use httpmock::MockServer;
 use httpmock::prelude::*;
@@ -8,7 +9,7 @@ use warp::test::request;
 use truelayer_coding_challenge::{
   api::API,
   models::poke_models::PokemonResponse,
-  util::{TranslationType, MokaCache},
+  util::{CacheKey, MokaCache},
   server::router,
 };
 
@@ -33,7 +34,7 @@ async fn test_basic_handler_networked() {
     .override_uri(mock.server_address().to_string())
     .disable_https();
 
-  let cache: MokaCache<(String, TranslationType), PokemonResponse> = MokaCache(Cache::new(1_000));
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
   let router = router(poke_client, MockTranslationAPI, cache);
 
   let res = request().path("/pokemon/pikachu").reply(&router).await;
@@ -60,7 +61,7 @@ async fn test_advanced_handler_networked() {
     .override_uri(mock.server_address().to_string())
     .disable_https();
 
-  let cache: MokaCache<(String, TranslationType), PokemonResponse> = MokaCache(Cache::new(1_000));
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
   let router = router(MockPokeAPI, translation_client, cache);
 
   let res = request().path("/pokemon/translated/pikachu").reply(&router).await;
@@ -96,7 +97,7 @@ async fn test_advanced_handler_yoda() {
     .override_uri(mock_diglett.server_address().to_string())
     .disable_https();
 
-  let cache: MokaCache<(String, TranslationType), PokemonResponse> = MokaCache(Cache::new(1_000));
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
   let router = router(MockPokeAPI, translation_client, cache);
 
   let res_a = request().path("/pokemon/translated/diglett").reply(&router).await;
@@ -123,7 +124,7 @@ async fn test_advanced_handler_rejection() {
     .override_uri(mock.server_address().to_string())
     .disable_https();
 
-  let cache: MokaCache<(String, TranslationType), PokemonResponse> = MokaCache(Cache::new(1_000));
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
   let router = router(MockPokeAPI, translation_client, cache);
 
   let res = request().path("/pokemon/translated/pikachu").reply(&router).await;
@@ -136,3 +137,59 @@ async fn test_advanced_handler_rejection() {
 
   mock.assert_async().await;
 }
+
+#[tokio::test]
+async fn test_basic_handler_read_timeout() {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .header("content-type", "application/json")
+      .delay(Duration::from_millis(500))
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+
+  let poke_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https()
+    .read_timeout(Duration::from_millis(50));
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(poke_client, MockTranslationAPI, cache);
+
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+
+  assert_eq!(res.status(), 504);
+}
+
+#[tokio::test]
+async fn test_advanced_handler_deadline() {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(200)
+      .header("content-type", "application/json")
+      .delay(Duration::from_millis(500))
+      .body_from_file(format!("{}/tests/assets/raw_translation_pikachu.json", ROOT));
+  }).await;
+
+  let translation_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https()
+    .deadline(Duration::from_millis(50));
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(MockPokeAPI, translation_client, cache);
+
+  let res = request().path("/pokemon/translated/pikachu").reply(&router).await;
+
+  assert!(res.status().is_success());
+  assert_eq!(
+    res.body().to_vec(),
+    read(format!("{}/tests/assets/expected_pikachu.json", ROOT)).expect("Read test data")
+  );
+}
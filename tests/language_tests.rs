@@ -0,0 +1,126 @@
+use httpmock::{MockServer, Method::GET};
+use serde_json::{from_slice, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  language::{negotiate, parse_accept_language},
+  server::{router, router_with_options, RouterOptions},
+  util::{CacheKey, CacheWrapper, TranslationType, PokError},
+};
+
+mod mock_impl;
+use mock_impl::{api, description, translated_key, MockPokeAPI, MockTranslationAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[test]
+fn negotiates_languages() {
+  let fallback = vec![String::from("en")];
+
+  assert_eq!(parse_accept_language("fr;q=0.5, de-DE, *;q=0.1, it;q=0"), ["de-de", "fr"]);
+  assert_eq!(negotiate(None, Some("fr;q=0.5, de-DE"), &fallback).unwrap(), ["de-de", "fr", "en"]);
+  // The query parameter takes precedence over the header
+  assert_eq!(negotiate(Some("ja,EN"), Some("fr"), &fallback).unwrap(), ["ja", "en"]);
+  assert_eq!(negotiate(None, None, &fallback).unwrap(), ["en"]);
+  assert!(matches!(negotiate(Some("en;drop"), None, &fallback), Err(PokError::InvalidLanguage(_))));
+}
+
+#[tokio::test]
+async fn serves_requested_language() {
+  let router = router(MockPokeAPI, MockTranslationAPI, MockCache::new());
+
+  let res = request().path("/pokemon/pikachu?lang=ja").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.headers()["content-language"], "ja");
+  assert!(description(res.body()).starts_with("尻尾を"));
+
+  let res = request().path("/pokemon/pikachu").header("accept-language", "de-CH, en;q=0.5").reply(&router).await;
+  assert_eq!(res.headers()["content-language"], "de");
+  assert!(description(res.body()).starts_with("Es streckt"));
+
+  let res = request().path("/pokemon/pikachu?lang=xx").reply(&router).await;
+  assert_eq!(res.headers()["content-language"], "en");
+  assert!(description(res.body()).starts_with("When several"));
+}
+
+#[tokio::test]
+async fn falls_back_through_configured_languages() {
+  let options = RouterOptions {
+    languages: vec![String::from("xx"), String::from("fr"), String::from("en")],
+    ..RouterOptions::default()
+  };
+  let router = router_with_options(MockPokeAPI, MockTranslationAPI, MockCache::new(), options);
+
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert_eq!(res.headers()["content-language"], "fr");
+
+  let res = request().path("/pokemon/pikachu?lang=foo-bar!").reply(&router).await;
+  assert_eq!(res.status(), 400);
+
+  // Languages are listed in one parameter, so repeating it is a bad request
+  let res = request().path("/pokemon/pikachu?lang=en&lang=ja").reply(&router).await;
+  assert_eq!(res.status(), 400);
+  let message = from_slice::<Value>(res.body()).unwrap()["message"].as_str().unwrap().to_owned();
+  assert!(message.contains("at most once"), "{}", message);
+}
+
+#[tokio::test]
+async fn caches_each_language_separately() {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  request().path("/pokemon/pikachu").reply(&router).await;
+  request().path("/pokemon/pikachu?lang=ja").reply(&router).await;
+  request().path("/pokemon/pikachu?lang=ja").reply(&router).await;
+
+  assert_eq!(*cache.insert_count(), 2);
+  let japanese = CacheKey::new("pikachu", TranslationType::None).languages(vec![String::from("ja"), String::from("en")]);
+  assert_eq!(cache.get(&japanese).await.unwrap().language(), "ja");
+  assert_eq!(cache.get(&CacheKey::new("pikachu", TranslationType::None)).await.unwrap().language(), "en");
+}
+
+#[tokio::test]
+async fn only_translates_english() {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  let res = request().path("/pokemon/translated/pikachu?lang=fr").reply(&router).await;
+  assert_eq!(res.headers()["content-language"], "fr");
+  assert!(description(res.body()).starts_with("Il lui arrive"));
+  assert_eq!(*cache.insert_count(), 1);
+}
+
+#[tokio::test]
+async fn shares_species_and_translations_across_languages_asked_for() {
+  let mock_server = MockServer::start_async().await;
+  let species = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+  let translation = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_translation_pikachu.json", ROOT));
+  }).await;
+
+  let cache = MockCache::new();
+  let router = router(api(&mock_server), api(&mock_server), cache.clone());
+
+  for path in ["/pokemon/translated/pikachu", "/pokemon/translated/pikachu?lang=xx", "/pokemon/translated/pikachu?lang=en,ja"] {
+    let res = request().path(path).reply(&router).await;
+    assert_eq!(res.headers()["content-language"], "en");
+    assert!(description(res.body()).starts_with("At which hour several of these"));
+  }
+  let res = request().path("/pokemon/pikachu?lang=ja").reply(&router).await;
+  assert!(description(res.body()).starts_with("尻尾を"));
+
+  species.assert_hits_async(1).await;
+  translation.assert_hits_async(1).await;
+  let translated = translated_key("pikachu", TranslationType::Shakespeare);
+  assert!(cache.get(&translated).await.is_some());
+}
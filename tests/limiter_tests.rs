@@ -0,0 +1,133 @@
+use std::fs::read;
+use std::time::{Duration, Instant};
+
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  breaker::CircuitBreaker,
+  limiter::{Limit, RateLimiter},
+  retry::RetryPolicy,
+  models::poke_models::PokemonResponse,
+  util::{CacheKey, TranslationType, MokaCache, PokError},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::MockPokeAPI;
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[tokio::test]
+async fn rejects_once_quota_spent() {
+  let limiter = RateLimiter::default()
+    .limit(TranslationType::Yoda, Limit { requests: 2, per: Duration::from_secs(60) });
+
+  assert!(limiter.acquire(TranslationType::Yoda).await.is_ok());
+  assert!(limiter.acquire(TranslationType::Yoda).await.is_ok());
+  assert!(matches!(limiter.acquire(TranslationType::Yoda).await, Err(PokError::RateLimited)));
+
+  // Types without limits are unaffected
+  assert!(limiter.acquire(TranslationType::Shakespeare).await.is_ok());
+}
+
+#[tokio::test]
+async fn every_limit_must_have_quota() {
+  let limiter = RateLimiter::default()
+    .limit(TranslationType::Yoda, Limit { requests: 5, per: Duration::from_secs(60) })
+    .limit(TranslationType::Yoda, Limit { requests: 1, per: Duration::from_secs(60) });
+
+  assert!(limiter.acquire(TranslationType::Yoda).await.is_ok());
+  assert!(matches!(limiter.acquire(TranslationType::Yoda).await, Err(PokError::RateLimited)));
+}
+
+#[tokio::test]
+async fn queues_within_max_wait() {
+  let limiter = RateLimiter::new(Duration::from_millis(500))
+    .limit(TranslationType::Yoda, Limit { requests: 1, per: Duration::from_millis(100) });
+
+  let start = Instant::now();
+  assert!(limiter.acquire(TranslationType::Yoda).await.is_ok());
+  assert!(limiter.acquire(TranslationType::Yoda).await.is_ok());
+
+  assert!(start.elapsed() >= Duration::from_millis(90));
+}
+
+#[tokio::test]
+async fn limited_translation_is_not_sent() {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_translation_pikachu.json", ROOT));
+  }).await;
+
+  let translation_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https()
+    .translation_limiter(RateLimiter::default()
+      .limit(TranslationType::Shakespeare, Limit { requests: 1, per: Duration::from_secs(3600) }));
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(MockPokeAPI, translation_client, cache);
+
+  let res_a = request().path("/pokemon/translated/pikachu").reply(&router).await;
+  let res_b = request().path("/pokemon/translated/arceus").reply(&router).await;
+
+  assert!(res_a.status().is_success() && res_b.status().is_success());
+  assert_eq!(
+    res_a.body().to_vec(),
+    read(format!("{}/tests/assets/expected_translated_pikachu.json", ROOT)).expect("Read test data")
+  );
+  assert!(String::from_utf8_lossy(res_b.body()).contains("shaped the universe with its 1,000 arms"));
+
+  mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn retries_spend_a_token_each() {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(429);
+  }).await;
+
+  let translation_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https()
+    .retry_policy(RetryPolicy { base_delay: Duration::from_millis(1), ..RetryPolicy::default() })
+    .translation_limiter(RateLimiter::default()
+      .limit(TranslationType::Shakespeare, Limit { requests: 2, per: Duration::from_secs(3600) }));
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(MockPokeAPI, translation_client, cache);
+  request().path("/pokemon/translated/pikachu").reply(&router).await;
+
+  // Three attempts are allowed, but the quota only covers two
+  assert_eq!(mock.hits_async().await, 2);
+}
+
+#[tokio::test]
+async fn open_breaker_spends_no_token() {
+  let limiter = RateLimiter::default()
+    .limit(TranslationType::Shakespeare, Limit { requests: 1, per: Duration::from_secs(3600) });
+  let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+  breaker.try_acquire().unwrap().failure();
+
+  let translation_client = API::new()
+    .translation_breaker(breaker)
+    .translation_limiter(limiter.clone());
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(MockPokeAPI, translation_client, cache);
+  request().path("/pokemon/translated/pikachu").reply(&router).await;
+
+  assert!(limiter.acquire(TranslationType::Shakespeare).await.is_ok());
+}
@@ -0,0 +1,52 @@
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  models::poke_models::PokemonResponse,
+  util::{CacheKey, MokaCache},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::MockTranslationAPI;
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[tokio::test]
+async fn exports_http_upstream_and_cache_metrics() {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+
+  let poke_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https();
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(poke_client, MockTranslationAPI, cache);
+
+  request().path("/pokemon/pikachu").reply(&router).await;
+  request().path("/pokemon/pikachu").reply(&router).await;
+  request().path("/pokemon/pikachu/extra").reply(&router).await;
+
+  let res = request().path("/metrics").reply(&router).await;
+  let body = String::from_utf8(res.body().to_vec()).expect("Metrics are utf-8");
+
+  assert!(res.status().is_success());
+  assert!(body.contains(r#"http_requests_total{route="/pokemon/{name}",status="200"} 2"#));
+  assert!(body.contains(r#"http_requests_total{route="unmatched",status="404"} 1"#));
+  assert!(body.contains(r#"upstream_requests_total{upstream="pokeapi"} 1"#));
+  // The miss looks again once its flight is under way
+  assert!(body.contains(r#"cache_operations_total{operation="miss",translation_type="none"} 2"#));
+  assert!(body.contains(r#"cache_operations_total{operation="hit",translation_type="none"} 1"#));
+  assert!(body.contains(r#"cache_operations_total{operation="insert",translation_type="none"} 1"#));
+  assert!(body.contains("http_request_duration_seconds_bucket"));
+}
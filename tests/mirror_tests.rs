@@ -0,0 +1,123 @@
+use std::fs::{copy, create_dir_all, read_dir, remove_dir_all, write};
+use std::path::PathBuf;
+
+use httpmock::{MockServer, Method::GET};
+use serde_json::json;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  mirror::{self, DownloadReport, Mirror},
+  util::{PokeClient, PokError},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{MockTranslationAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+/// A fresh directory, unique to the test and process
+fn mirror_dir(test: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("pokedex-mirror-{}-{}", test, std::process::id()));
+  remove_dir_all(&dir).ok();
+  create_dir_all(&dir).expect("Create mirror directory");
+  dir
+}
+
+/// A mirror holding every pokemon with test data, under the names of the 
+/// test data files
+fn populated_mirror(test: &str) -> PathBuf {
+  let dir = mirror_dir(test);
+  for name in ["arceus", "diglett", "pikachu", "regice"] {
+    let file = format!("raw_{}.json", name);
+    copy(format!("{}/tests/assets/{}", ROOT, file), dir.join(file)).expect("Copy test data");
+  }
+  dir
+}
+
+#[tokio::test]
+async fn looks_up_by_name_or_id() {
+  let dir = populated_mirror("lookup");
+  let mirror = Mirror::open(&dir).await.expect("Open mirror");
+  assert_eq!(mirror.len(), 4);
+
+  assert_eq!(mirror.get_pokemon(String::from("pikachu")).await.unwrap().name(), "pikachu");
+  assert_eq!(mirror.get_pokemon(String::from("PiKaChU")).await.unwrap().name(), "pikachu");
+  assert_eq!(mirror.get_pokemon(String::from("378")).await.unwrap().name(), "regice");
+  assert!(matches!(mirror.get_pokemon(String::from("mewtwo")).await, Err(PokError::NotFound)));
+  assert!(matches!(mirror.get_pokemon(String::from("151")).await, Err(PokError::NotFound)));
+
+  remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn serves_without_network() {
+  let dir = populated_mirror("serve");
+  let mirror = Mirror::open(&dir).await.expect("Open mirror");
+  let router = router(mirror, MockTranslationAPI, MockCache::new());
+
+  let res = request().path("/pokemon/diglett").reply(&router).await;
+  assert_eq!(res.status(), 200);
+
+  let res = request().path("/pokemon/missingno").reply(&router).await;
+  assert_eq!(res.status(), 404);
+
+  let res = request().path("/readyz").reply(&router).await;
+  assert_eq!(res.status(), 200);
+
+  remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn lists_species_by_id() {
+  let dir = populated_mirror("list");
+  write(dir.join("notes.json"), "not a species").unwrap();
+  create_dir_all(dir.join("unreadable.json")).unwrap();
+  let mirror = Mirror::open(&dir).await.expect("Open mirror");
+
+  let first = mirror.list_species(0, 3).await.unwrap();
+  let names: Vec<&str> = first.results().iter().map(|species| species.name()).collect();
+  assert_eq!(names, ["pikachu", "diglett", "regice"]);
+  assert_eq!(first.count(), 4);
+  assert!(first.has_next());
+
+  let last = mirror.list_species(3, 3).await.unwrap();
+  assert_eq!(last.results()[0].name(), "arceus");
+  assert!(!last.has_next());
+
+  remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn downloads_from_pokeapi() {
+  let server = MockServer::start_async().await;
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species");
+    then.status(200)
+      .json_body(json!({ "count": 2, "next": null, "previous": null, "results": [{ "name": "pikachu", "url": "" }, { "name": "regice", "url": "" }] }));
+  }).await;
+  for name in ["pikachu", "regice"] {
+    server.mock_async(|when, then| {
+      when.method(GET)
+        .path(format!("/api/v2/pokemon-species/{}", name));
+      then.status(200)
+        .body_from_file(format!("{}/tests/assets/raw_{}.json", ROOT, name));
+    }).await;
+  }
+
+  let dir = mirror_dir("download").join("nested");
+  let api = API::new()
+    .override_uri(server.address().to_string())
+    .disable_https();
+  let report = mirror::download(&api, &dir, 2).await.expect("Download mirror");
+
+  assert_eq!(report, DownloadReport { downloaded: 2, failed: 0 });
+  assert_eq!(read_dir(&dir).unwrap().count(), 2);
+
+  let mirror = Mirror::open(&dir).await.expect("Open mirror");
+  assert_eq!(mirror.get_pokemon(String::from("25")).await.unwrap().name(), "pikachu");
+
+  remove_dir_all(dir.parent().unwrap()).ok();
+}
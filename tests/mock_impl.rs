@@ -1,16 +1,46 @@
-use std::fs::read;
+#![allow(dead_code)]
+
+use std::fs::{read, read_dir};
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use async_trait::async_trait;
-use moka::future::Cache;
-use serde_json::from_slice;
+use moka::future::{Cache, ConcurrentCacheExt};
+use httpmock::MockServer;
+use serde_json::{from_slice, Value};
 
+use truelayer_coding_challenge::api::API;
 use truelayer_coding_challenge::models::translation_models::TranslationUnit;
-use truelayer_coding_challenge::util::{PokeClient, TranslationClient, PokError, TranslationType, CacheWrapper};
-use truelayer_coding_challenge::models::poke_models::{PokemonSpecies, PokemonResponse};
+use truelayer_coding_challenge::util::{PokeClient, TranslationClient, PokError, CacheKey, TranslationType, CacheWrapper};
+use truelayer_coding_challenge::models::poke_models::{NamedAPIResourceList, PokemonSpecies, PokemonResponse, VersionSelection};
 
 const ROOT: &str = env!("CARGO_MANIFEST_DIR");
 
+/// Parse the test data of a species, as served by Pokeapi
+pub fn species(name: &str) -> PokemonSpecies {
+  from_slice(&read(format!("{}/tests/assets/raw_{}.json", ROOT, name)).expect("Read test data"))
+    .expect("Parse test data")
+}
+
+/// The key a pokemon's translation is cached under when asked for without a 
+/// language or version, that of the game its first English description is from
+pub fn translated_key(name: &str, translation_type: TranslationType) -> CacheKey {
+  let pokemon = PokemonResponse::try_from(species(name)).expect("Describe test data");
+  let version = pokemon.version().expect("Game of test data").to_owned();
+  CacheKey::new(name, translation_type).version(VersionSelection::Version(version))
+}
+
+/// Get the description of a pokemon route's JSON body
+pub fn description(body: &[u8]) -> String {
+  from_slice::<Value>(body).unwrap()["description"].as_str().unwrap().to_owned()
+}
+
+/// An API contacting `server` for both Pokeapi and funtranslations
+pub fn api(server: &MockServer) -> API {
+  API::new()
+    .override_uri(server.address().to_string())
+    .disable_https()
+}
+
 #[derive(Clone)]
 pub(crate) struct MockPokeAPI;
 
@@ -23,7 +53,24 @@ impl PokeClient for MockPokeAPI {
   }
 
   async fn get_pokemon(&self, pokemon: String) -> Result<PokemonSpecies, PokError> {
-    from_slice::<PokemonSpecies>(&read(format!("{}/tests/assets/raw_{}.json", ROOT, pokemon)).expect("Read test data")).map_err(|e| e.into())
+    let bytes = read(format!("{}/tests/assets/raw_{}.json", ROOT, pokemon)).map_err(|_| PokError::NotFound)?;
+    from_slice::<PokemonSpecies>(&bytes).map_err(|e| e.into())
+  }
+
+  /// Lists the pokemon with test data, in alphabetical order
+  async fn list_species(&self, offset: u64, limit: u64) -> Result<NamedAPIResourceList, PokError> {
+    let mut names: Vec<String> = read_dir(format!("{}/tests/assets", ROOT)).expect("List test data")
+      .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+      .filter(|file| !file.starts_with("raw_translation_"))
+      .filter_map(|file| Some(file.strip_prefix("raw_")?.strip_suffix(".json")?.to_owned()))
+      .collect();
+    names.sort();
+
+    let count = names.len() as u64;
+    let page: Vec<String> = names.into_iter().skip(offset as usize).take(limit as usize).collect();
+    let next = if offset + limit < count { Some(String::from("next")) } else { None };
+
+    Ok(NamedAPIResourceList::new(count, next, page))
   }
 }
 
@@ -49,11 +96,17 @@ impl TranslationClient for MockTranslationAPI {
 
 #[derive(Clone)]
 pub struct MockCache {
-  cache: Cache<(String, TranslationType), PokemonResponse>,
+  cache: Cache<CacheKey, PokemonResponse>,
   get_count: Arc<Mutex<usize>>,
   insert_count: Arc<Mutex<usize>>,
 }
 
+impl Default for MockCache {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl MockCache {
   pub fn new() -> Self {
     Self {
@@ -63,28 +116,47 @@ impl MockCache {
     }
   }
 
-  pub fn get_count(&self) -> MutexGuard<usize> {
+  pub fn get_count(&self) -> MutexGuard<'_, usize> {
     self.get_count.lock().unwrap()
   }
 
-  pub fn insert_count(&self) -> MutexGuard<usize> {
+  pub fn insert_count(&self) -> MutexGuard<'_, usize> {
     self.insert_count.lock().unwrap()
   }
 }
 
 #[async_trait]
-impl CacheWrapper<(String, TranslationType), PokemonResponse> for MockCache {
-  fn get(&self, key: &(std::string::String, TranslationType)) -> Option<PokemonResponse> {
-    let mut count = self.get_count.lock().unwrap();
-    *count += 1;
+impl CacheWrapper<CacheKey, PokemonResponse> for MockCache {
+  async fn get(&self, key: &CacheKey) -> Option<PokemonResponse> {
+    {
+      let mut count = self.get_count.lock().unwrap();
+      *count += 1;
+    }
     self.cache.get(key)
   }
 
-  async fn insert(&self, key: (std::string::String, TranslationType), value: PokemonResponse) {
+  async fn insert(&self, key: CacheKey, value: PokemonResponse) {
     {
       let mut count = self.insert_count.lock().unwrap();
       *count += 1;
     }
     self.cache.insert(key, value).await;
   }
+
+  async fn invalidate(&self, key: &CacheKey) {
+    self.cache.invalidate(key).await;
+  }
+
+  async fn invalidate_all(&self) {
+    self.cache.invalidate_all();
+  }
+
+  fn keys(&self) -> Vec<CacheKey> {
+    self.cache.iter().map(|(key, _)| (*key).clone()).collect()
+  }
+
+  fn entry_count(&self) -> u64 {
+    self.cache.sync();
+    self.cache.entry_count()
+  }
 }
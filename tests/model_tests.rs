@@ -26,6 +26,16 @@ fn deserialize_translation_api_translation() {
   assert_eq!(translation.contents().translated(), "At which hour several of these pokémon gather,  their electricity couldst buildeth and cause lightning storms.")
 }
 
+#[test]
+fn deserialize_translation_without_text() {
+  let json = br#"{ "contents": { "translated": "Translated.", "translation": "yoda" } }"#;
+
+  let translation = from_slice::<translation_models::TranslationUnit>(json).expect("Parse json");
+
+  assert_eq!(translation.contents().translated(), "Translated.");
+  assert_eq!(translation.contents().text(), "");
+}
+
 #[test]
 fn deserialize_poke_api_species_legendary() {
   let json = read(format!("{}/tests/assets/raw_regice.json", ROOT)).expect("Read test data");
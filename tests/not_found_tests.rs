@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use serde_json::{from_slice, Value};
+use tokio::time::sleep;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  models::poke_models::PokemonResponse,
+  util::{CacheKey, MokaCache},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{api, MockTranslationAPI};
+
+fn cache() -> MokaCache<CacheKey, PokemonResponse> {
+  MokaCache(Cache::new(1_000))
+}
+
+async fn unknown_pokemon(server: &MockServer) -> httpmock::Mock<'_> {
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/notapokemon");
+    then.status(404)
+      .body("Not Found");
+  }).await
+}
+
+#[tokio::test]
+async fn unknown_pokemon_is_not_found() {
+  let server = MockServer::start_async().await;
+  unknown_pokemon(&server).await;
+  let router = router(api(&server), MockTranslationAPI, cache());
+
+  for path in ["/pokemon/notapokemon", "/pokemon/translated/notapokemon"] {
+    let res = request().path(path).reply(&router).await;
+    let body = from_slice::<Value>(res.body()).expect("Parse json");
+
+    assert_eq!(res.status(), 404);
+    assert_eq!(body["message"], "Pokemon not found");
+  }
+}
+
+#[tokio::test]
+async fn remembers_unknown_pokemon() {
+  let server = MockServer::start_async().await;
+  let mock = unknown_pokemon(&server).await;
+  let poke_client = api(&server).cache_not_found(Duration::from_millis(100));
+  let router = router(poke_client, MockTranslationAPI, cache());
+
+  for _ in 0..3 {
+    let res = request().path("/pokemon/notapokemon").reply(&router).await;
+    assert_eq!(res.status(), 404);
+  }
+  mock.assert_hits_async(1).await;
+
+  // Forgotten once the negative entry expires
+  sleep(Duration::from_millis(150)).await;
+  let res = request().path("/pokemon/notapokemon").reply(&router).await;
+  assert_eq!(res.status(), 404);
+  mock.assert_hits_async(2).await;
+}
+
+#[tokio::test]
+async fn forgets_unknown_pokemon_without_negative_caching() {
+  let server = MockServer::start_async().await;
+  let mock = unknown_pokemon(&server).await;
+  let router = router(api(&server), MockTranslationAPI, cache());
+
+  request().path("/pokemon/notapokemon").reply(&router).await;
+  request().path("/pokemon/notapokemon").reply(&router).await;
+
+  mock.assert_hits_async(2).await;
+}
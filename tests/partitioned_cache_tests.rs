@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use moka::future::Cache;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  cache::partitioned::PartitionedCache,
+  models::poke_models::PokemonResponse,
+  util::{CacheWrapper, MokaCache, CacheKey, TranslationType},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{translated_key, MockPokeAPI, MockTranslationAPI, MockCache};
+
+type Partitions = PartitionedCache<CacheKey, PokemonResponse, MokaCache<CacheKey, PokemonResponse>>;
+
+async fn pikachu(cache: &MockCache, translation_type: TranslationType) -> PokemonResponse {
+  let key = match translation_type {
+    TranslationType::None => CacheKey::new("pikachu", translation_type),
+    _ => translated_key("pikachu", translation_type),
+  };
+  cache.get(&key).await.expect("Cached pikachu")
+}
+
+#[tokio::test]
+async fn routes_entries_by_translation_type() {
+  let (untranslated, translated) = (MockCache::new(), MockCache::new());
+  let cache = PartitionedCache::new(untranslated.clone(), translated.clone());
+
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+  request().path("/pokemon/translated/pikachu").reply(&router).await;
+
+  assert_eq!(*untranslated.insert_count(), 1);
+  assert_eq!(*translated.insert_count(), 1);
+  assert_ne!(
+    pikachu(&untranslated, TranslationType::None).await.description(),
+    pikachu(&translated, TranslationType::Shakespeare).await.description(),
+  );
+  assert_eq!(cache.entry_count(), 2);
+}
+
+#[tokio::test]
+async fn untranslated_entries_cannot_evict_translations() {
+  let cache: Partitions = PartitionedCache::new(MokaCache(Cache::new(2)), MokaCache(Cache::new(2)));
+  let translated_key = CacheKey::new("pikachu", TranslationType::Shakespeare);
+  let pokemon = pikachu(&populated().await, TranslationType::None).await;
+
+  cache.insert(translated_key.clone(), pokemon.clone()).await;
+  for i in 0..50 {
+    cache.insert(CacheKey::new(format!("pokemon-{}", i), TranslationType::None), pokemon.clone()).await;
+  }
+
+  assert!(cache.get(&translated_key).await.is_some());
+  assert!(cache.entry_count() <= 3);
+}
+
+#[tokio::test]
+async fn translations_outlive_untranslated_entries() {
+  let untranslated = Cache::builder().time_to_live(Duration::from_millis(50)).build();
+  let cache: Partitions = PartitionedCache::new(MokaCache(untranslated), MokaCache(Cache::new(10)));
+  let pokemon = pikachu(&populated().await, TranslationType::None).await;
+
+  cache.insert(CacheKey::new("pikachu", TranslationType::None), pokemon.clone()).await;
+  cache.insert(CacheKey::new("pikachu", TranslationType::Yoda), pokemon).await;
+  tokio::time::sleep(Duration::from_millis(100)).await;
+
+  assert!(cache.get(&CacheKey::new("pikachu", TranslationType::None)).await.is_none());
+  assert!(cache.get(&CacheKey::new("pikachu", TranslationType::Yoda)).await.is_some());
+}
+
+/// A cache holding untranslated pikachu, fetched through the router
+async fn populated() -> MockCache {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+  request().path("/pokemon/pikachu").reply(&router).await;
+  cache
+}
@@ -0,0 +1,117 @@
+use httpmock::{MockServer, Method::GET};
+use serde_json::json;
+
+use truelayer_coding_challenge::{
+  prewarm::{Prewarmer, PrewarmReport},
+  species::SpeciesCache,
+  util::{CacheWrapper, CacheKey, TranslationType},
+};
+
+mod mock_impl;
+use mock_impl::{api, MockPokeAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[tokio::test]
+async fn warms_every_species() {
+  let cache = MockCache::new();
+  let report = Prewarmer::new().page_size(3).concurrency(2)
+    .run(MockPokeAPI, SpeciesCache::new(), cache.clone()).await
+    .expect("Crawl species");
+
+  assert_eq!(report, PrewarmReport { listed: 4, warmed: 4, skipped: 0, failed: 0 });
+  for name in ["arceus", "diglett", "pikachu", "regice"] {
+    assert!(cache.get(&CacheKey::new(name, TranslationType::None)).await.is_some());
+  }
+  assert_eq!(cache.entry_count(), 4);
+}
+
+#[tokio::test]
+async fn skips_cached_species() {
+  let cache = MockCache::new();
+  Prewarmer::new().run(MockPokeAPI, SpeciesCache::new(), cache.clone()).await.expect("Crawl species");
+
+  let report = Prewarmer::new().run(MockPokeAPI, SpeciesCache::new(), cache.clone()).await.expect("Crawl species again");
+
+  assert_eq!(report, PrewarmReport { listed: 4, warmed: 0, skipped: 4, failed: 0 });
+  assert_eq!(*cache.insert_count(), 4);
+}
+
+#[tokio::test]
+async fn pages_through_pokeapi() {
+  let server = MockServer::start_async().await;
+  let first_page = server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species")
+      .query_param("offset", "0")
+      .query_param("limit", "1");
+    then.status(200)
+      .json_body(json!({ "count": 2, "next": "next", "previous": null, "results": [{ "name": "pikachu", "url": "" }] }));
+  }).await;
+  let second_page = server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species")
+      .query_param("offset", "1")
+      .query_param("limit", "1");
+    then.status(200)
+      .json_body(json!({ "count": 2, "next": null, "previous": "previous", "results": [{ "name": "missingno", "url": "" }] }));
+  }).await;
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+
+  let cache = MockCache::new();
+  let report = Prewarmer::new().page_size(1).run(api(&server), SpeciesCache::new(), cache.clone()).await.expect("Crawl species");
+
+  first_page.assert_async().await;
+  second_page.assert_async().await;
+  // Nothing else is mocked, so missingno is not found
+  assert_eq!(report, PrewarmReport { listed: 2, warmed: 1, skipped: 0, failed: 1 });
+  assert!(cache.get(&CacheKey::new("pikachu", TranslationType::None)).await.is_some());
+}
+
+#[tokio::test]
+async fn abandons_crawl_when_listing_fails() {
+  let server = MockServer::start_async().await;
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species");
+    then.status(503);
+  }).await;
+
+  let cache = MockCache::new();
+  let res = Prewarmer::new().run(api(&server), SpeciesCache::new(), cache.clone()).await;
+
+  assert!(res.is_err());
+  assert_eq!(cache.entry_count(), 0);
+}
+
+#[tokio::test]
+async fn fetches_through_species_cache() {
+  let server = MockServer::start_async().await;
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species");
+    then.status(200)
+      .json_body(json!({ "count": 1, "next": null, "previous": null, "results": [{ "name": "pikachu", "url": "" }] }));
+  }).await;
+  let pikachu = server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+
+  // Held from an earlier request, so the crawl costs no further fetch
+  let species = SpeciesCache::new();
+  species.get("pikachu", &api(&server)).await.expect("Fetch species");
+
+  let cache = MockCache::new();
+  let report = Prewarmer::new().run(api(&server), species.clone(), cache.clone()).await.expect("Crawl species");
+
+  assert_eq!(report, PrewarmReport { listed: 1, warmed: 1, skipped: 0, failed: 0 });
+  pikachu.assert_hits_async(1).await;
+}
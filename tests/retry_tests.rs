@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use httpmock::{MockServer, Method::GET};
+use hyper::{StatusCode, header::HeaderValue};
+use moka::future::Cache;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  models::poke_models::PokemonResponse,
+  retry::{RetryPolicy, parse_retry_after},
+  util::{CacheKey, MokaCache},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::MockTranslationAPI;
+
+fn fast_policy() -> RetryPolicy {
+  RetryPolicy {
+    max_attempts: 3,
+    base_delay: Duration::from_millis(1),
+    max_delay: Duration::from_millis(50),
+    ..Default::default()
+  }
+}
+
+async fn hits_for(status: u16, retry_after: Option<&str>) -> (usize, StatusCode) {
+  let mock_server = MockServer::start_async().await;
+
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    let then = then.status(status);
+    if let Some(retry_after) = retry_after {
+      then.header("retry-after", retry_after);
+    }
+  }).await;
+
+  let poke_client = API::new()
+    .override_uri(mock.server_address().to_string())
+    .disable_https()
+    .retry_policy(fast_policy());
+
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(poke_client, MockTranslationAPI, cache);
+
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+
+  (mock.hits_async().await, res.status())
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+  let (hits, status) = hits_for(503, None).await;
+
+  assert_eq!(hits, 3);
+  assert_eq!(status, StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() {
+  let (hits, _) = hits_for(404, None).await;
+
+  assert_eq!(hits, 1);
+}
+
+#[tokio::test]
+async fn honours_retry_after() {
+  let (short_hits, _) = hits_for(429, Some("0")).await;
+  assert_eq!(short_hits, 3);
+
+  // Asked to wait longer than the policy's maximum delay, so gives up immediately
+  let (long_hits, _) = hits_for(429, Some("60")).await;
+  assert_eq!(long_hits, 1);
+}
+
+#[test]
+fn backoff_is_exponential_and_capped() {
+  let policy = RetryPolicy {
+    jitter: 0.0,
+    ..fast_policy()
+  };
+
+  assert_eq!(policy.backoff(1), Duration::from_millis(1));
+  assert_eq!(policy.backoff(2), Duration::from_millis(2));
+  assert_eq!(policy.backoff(3), Duration::from_millis(4));
+  assert_eq!(policy.backoff(20), Duration::from_millis(50));
+
+  let jittered = fast_policy().backoff(6);
+  assert!(jittered <= Duration::from_millis(32) && jittered >= Duration::from_millis(16));
+}
+
+#[test]
+fn parses_retry_after() {
+  assert_eq!(parse_retry_after(&HeaderValue::from_static("120")), Some(Duration::from_secs(120)));
+  assert_eq!(parse_retry_after(&HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT")), Some(Duration::ZERO));
+  assert_eq!(parse_retry_after(&HeaderValue::from_static("soon")), None);
+}
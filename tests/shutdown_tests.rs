@@ -0,0 +1,55 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::{Client, Uri};
+use tokio::{sync::oneshot, time::sleep};
+use warp::Filter;
+
+use truelayer_coding_challenge::shutdown::{serve, DrainSummary};
+
+/// A route that takes `delay` to respond
+fn slow(delay: Duration) -> impl Filter<Extract = (&'static str,), Error = Infallible> + Clone {
+  warp::any().then(move || async move {
+    sleep(delay).await;
+    "done"
+  })
+}
+
+async fn shutdown_during_request(handler: Duration, drain: Duration) -> (DrainSummary, bool) {
+  let (stop_tx, stop_rx) = oneshot::channel::<()>();
+  let (addr, server) = serve(slow(handler), SocketAddr::from(([127, 0, 0, 1], 0)), drain, async {
+    stop_rx.await.ok();
+  }).expect("Bind server");
+  let server = tokio::spawn(server);
+
+  let request = tokio::spawn(async move {
+    let uri: Uri = format!("http://{}/", addr).parse().unwrap();
+    Client::new().get(uri).await.map(|res| res.status().is_success()).unwrap_or(false)
+  });
+
+  sleep(Duration::from_millis(50)).await;
+  stop_tx.send(()).unwrap();
+
+  let summary = server.await.expect("Server task");
+  let completed = tokio::time::timeout(Duration::from_millis(500), request).await
+    .map(|res| res.unwrap())
+    .unwrap_or(false);
+
+  (summary, completed)
+}
+
+#[tokio::test]
+async fn drains_in_flight_requests() {
+  let (summary, completed) = shutdown_during_request(Duration::from_millis(200), Duration::from_secs(5)).await;
+
+  assert_eq!(summary, DrainSummary { in_flight: 1, drained: 1, abandoned: 0 });
+  assert!(completed);
+}
+
+#[tokio::test]
+async fn abandons_requests_after_deadline() {
+  let (summary, _) = shutdown_during_request(Duration::from_secs(5), Duration::from_millis(50)).await;
+
+  assert_eq!(summary, DrainSummary { in_flight: 1, drained: 0, abandoned: 1 });
+}
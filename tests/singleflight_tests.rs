@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use serde_json::{from_slice, from_value, json, Value};
+use tokio::time::sleep;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  api::API,
+  models::poke_models::PokemonResponse,
+  singleflight::SingleFlight,
+  util::{CacheKey, CacheWrapper, MokaCache, PokError, TranslationType},
+  server::router,
+};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+/// A cache missing its first lookup, as though the entry were inserted just 
+/// after
+#[derive(Clone)]
+struct LateCache {
+  inner: MokaCache<CacheKey, PokemonResponse>,
+  missed: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl CacheWrapper<CacheKey, PokemonResponse> for LateCache {
+  async fn get(&self, key: &CacheKey) -> Option<PokemonResponse> {
+    if self.missed.swap(true, Ordering::SeqCst) {
+      self.inner.get(key).await
+    } else {
+      None
+    }
+  }
+
+  async fn insert(&self, key: CacheKey, value: PokemonResponse) {
+    self.inner.insert(key, value).await
+  }
+
+  async fn invalidate(&self, key: &CacheKey) {
+    self.inner.invalidate(key).await
+  }
+
+  async fn invalidate_all(&self) {
+    self.inner.invalidate_all().await
+  }
+
+  fn keys(&self) -> Vec<CacheKey> {
+    self.inner.keys()
+  }
+
+  fn entry_count(&self) -> u64 {
+    self.inner.entry_count()
+  }
+}
+
+/// Run `callers` flights for the same key at once, each taking 50ms
+async fn concurrent(callers: usize, result: fn() -> Result<u32, PokError>) -> (Vec<Result<u32, String>>, usize) {
+  let flights: SingleFlight<&str, u32> = SingleFlight::new();
+  let runs = Arc::new(AtomicUsize::new(0));
+
+  let tasks: Vec<_> = (0..callers).map(|_| {
+    let (flights, runs) = (flights.clone(), runs.clone());
+    tokio::spawn(async move {
+      flights.run("mewtwo", || async move {
+        runs.fetch_add(1, Ordering::SeqCst);
+        sleep(Duration::from_millis(50)).await;
+        result()
+      }).await.map_err(|err| err.to_string())
+    })
+  }).collect();
+
+  let mut results = Vec::new();
+  for task in tasks {
+    results.push(task.await.unwrap());
+  }
+
+  (results, runs.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn concurrent_callers_share_one_flight() {
+  let (results, runs) = concurrent(50, || Ok(150)).await;
+
+  assert_eq!(runs, 1);
+  assert!(results.iter().all(|res| res == &Ok(150)));
+}
+
+#[tokio::test]
+async fn concurrent_callers_share_errors() {
+  let (results, runs) = concurrent(10, || Err(PokError::Timeout)).await;
+
+  assert_eq!(runs, 1);
+  assert!(results.iter().all(|res| res == &Err(PokError::Timeout.to_string())));
+}
+
+#[tokio::test]
+async fn follower_takes_over_from_cancelled_leader() {
+  let flights: SingleFlight<&str, u32> = SingleFlight::new();
+
+  let leader = tokio::spawn({
+    let flights = flights.clone();
+    async move {
+      flights.run("mewtwo", || async {
+        sleep(Duration::from_secs(10)).await;
+        Ok(1)
+      }).await
+    }
+  });
+  sleep(Duration::from_millis(20)).await;
+
+  let follower = tokio::spawn({
+    let flights = flights.clone();
+    async move {
+      flights.run("mewtwo", || async { Ok(2) }).await.unwrap()
+    }
+  });
+  sleep(Duration::from_millis(20)).await;
+  leader.abort();
+
+  assert_eq!(tokio::time::timeout(Duration::from_secs(1), follower).await.unwrap().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn concurrent_misses_make_one_upstream_request_each() {
+  let mock_server = MockServer::start_async().await;
+
+  let species = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .delay(Duration::from_millis(100))
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+  let translation = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/translate/shakespeare");
+    then.status(200)
+      .delay(Duration::from_millis(100))
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_translation_pikachu.json", ROOT));
+  }).await;
+
+  let api = API::new()
+    .override_uri(mock_server.address().to_string())
+    .disable_https();
+  let cache: MokaCache<CacheKey, PokemonResponse> = MokaCache(Cache::new(1_000));
+  let router = router(api.clone(), api, cache);
+
+  let requests: Vec<_> = (0..20).map(|_| {
+    let router = router.clone();
+    tokio::spawn(async move {
+      request().path("/pokemon/translated/pikachu").reply(&router).await
+    })
+  }).collect();
+
+  for res in requests {
+    let res = res.await.unwrap();
+    assert!(res.status().is_success());
+    assert!(String::from_utf8_lossy(res.body()).contains("At which hour several of these"));
+  }
+
+  species.assert_hits_async(1).await;
+  translation.assert_hits_async(1).await;
+}
+
+#[tokio::test]
+async fn flights_recheck_cache_before_fetching() {
+  let mock_server = MockServer::start_async().await;
+  let species = mock_server.mock_async(|when, then| {
+    when.method(GET);
+    then.status(500);
+  }).await;
+
+  let cache = LateCache { inner: MokaCache(Cache::new(1_000)), missed: Default::default() };
+  let landed: PokemonResponse = from_value(json!({ "name": "pikachu", "description": "Landed.", "habitat": "forest", "is_legendary": false }))
+    .expect("Build pokemon");
+  cache.insert(CacheKey::new("pikachu", TranslationType::None), landed).await;
+
+  let api = API::new()
+    .override_uri(mock_server.address().to_string())
+    .disable_https();
+  let router = router(api.clone(), api, cache);
+
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(from_slice::<Value>(res.body()).unwrap()["description"], "Landed.");
+  species.assert_hits_async(0).await;
+}
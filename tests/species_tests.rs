@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use httpmock::{MockServer, Method::GET};
+
+use truelayer_coding_challenge::{
+  config::CachePolicy,
+  species::SpeciesCache,
+};
+
+mod mock_impl;
+use mock_impl::api;
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+#[tokio::test]
+async fn holds_species_no_longer_than_entries() {
+  let mock_server = MockServer::start_async().await;
+  let mock = mock_server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(200)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await;
+
+  let policy = CachePolicy {
+    time_to_live_secs: Some(1),
+    ..CachePolicy::default()
+  };
+  let species = SpeciesCache::from_policy(&policy);
+  let api = api(&mock_server);
+
+  species.get("pikachu", &api).await.expect("Fetch species");
+  species.get("pikachu", &api).await.expect("Hold species");
+  mock.assert_hits_async(1).await;
+
+  // Expired along with the entries described from it
+  tokio::time::sleep(Duration::from_millis(1_100)).await;
+  species.get("pikachu", &api).await.expect("Fetch species again");
+  mock.assert_hits_async(2).await;
+}
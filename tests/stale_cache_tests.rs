@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use httpmock::{MockServer, Method::GET};
+use moka::future::Cache;
+use tokio::time::sleep;
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  cache::expiring::ExpiringCache,
+  models::poke_models::PokemonResponse,
+  util::{CacheWrapper, Freshness, CacheKey, TranslationType},
+  server::{router, STALE_HEADER},
+};
+
+mod mock_impl;
+use mock_impl::api;
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+type Expiring = ExpiringCache<CacheKey, PokemonResponse>;
+
+fn cache(soft_ttl: Option<u64>, hard_ttl: Option<u64>) -> Expiring {
+  ExpiringCache::new(Cache::new(1_000))
+    .soft_ttl(soft_ttl.map(Duration::from_millis))
+    .hard_ttl(hard_ttl.map(Duration::from_millis))
+}
+
+async fn pokeapi(server: &MockServer, status: u16) -> httpmock::Mock<'_> {
+  server.mock_async(|when, then| {
+    when.method(GET)
+      .path("/api/v2/pokemon-species/pikachu");
+    then.status(status)
+      .header("content-type", "application/json")
+      .body_from_file(format!("{}/tests/assets/raw_pikachu.json", ROOT));
+  }).await
+}
+
+#[tokio::test]
+async fn values_go_stale_then_expire() {
+  let cache = cache(Some(50), Some(150));
+  let key = CacheKey::new("pikachu", TranslationType::None);
+  let pokemon: PokemonResponse = serde_json::from_slice(
+    &std::fs::read(format!("{}/tests/assets/expected_pikachu.json", ROOT)).unwrap()
+  ).unwrap();
+
+  cache.insert(key.clone(), pokemon).await;
+  assert_eq!(cache.lookup(&key).await.unwrap().1, Freshness::Fresh);
+
+  sleep(Duration::from_millis(75)).await;
+  assert_eq!(cache.lookup(&key).await.unwrap().1, Freshness::Stale);
+  assert!(cache.get(&key).await.is_some());
+
+  sleep(Duration::from_millis(100)).await;
+  assert_eq!(cache.lookup(&key).await.unwrap().1, Freshness::Expired);
+  assert!(cache.get(&key).await.is_none());
+}
+
+#[tokio::test]
+async fn serves_stale_while_revalidating() {
+  let server = MockServer::start_async().await;
+  let species = pokeapi(&server, 200).await;
+  let router = router(api(&server), api(&server), cache(Some(300), None));
+
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert!(res.headers().get(STALE_HEADER).is_none());
+
+  sleep(Duration::from_millis(350)).await;
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert!(res.status().is_success());
+  assert_eq!(res.headers()[STALE_HEADER], "revalidating");
+
+  // The background refresh replaces the stale value
+  sleep(Duration::from_millis(50)).await;
+  species.assert_hits_async(2).await;
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert!(res.headers().get(STALE_HEADER).is_none());
+  species.assert_hits_async(2).await;
+}
+
+#[tokio::test]
+async fn refreshes_expired_values_before_serving() {
+  let server = MockServer::start_async().await;
+  let species = pokeapi(&server, 200).await;
+  let router = router(api(&server), api(&server), cache(None, Some(50)));
+
+  request().path("/pokemon/pikachu").reply(&router).await;
+  sleep(Duration::from_millis(75)).await;
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+
+  assert!(res.status().is_success());
+  assert!(res.headers().get(STALE_HEADER).is_none());
+  species.assert_hits_async(2).await;
+}
+
+#[tokio::test]
+async fn serves_expired_values_on_upstream_error() {
+  let server = MockServer::start_async().await;
+  let species = pokeapi(&server, 200).await;
+  let router = router(api(&server), api(&server), cache(None, Some(50)));
+
+  let fresh = request().path("/pokemon/pikachu").reply(&router).await;
+
+  species.delete_async().await;
+  let failing = pokeapi(&server, 503).await;
+  sleep(Duration::from_millis(75)).await;
+  let stale = request().path("/pokemon/pikachu").reply(&router).await;
+
+  assert_eq!(stale.status(), 200);
+  assert_eq!(stale.headers()[STALE_HEADER], "upstream-error");
+  assert_eq!(stale.body(), fresh.body());
+  failing.assert_hits_async(1).await;
+}
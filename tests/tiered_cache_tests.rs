@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  cache::{redis::RedisStore, tiered::TieredCache},
+  models::poke_models::PokemonResponse,
+  util::{CacheWrapper, CacheKey, TranslationType},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{translated_key, MockPokeAPI, MockTranslationAPI, MockCache};
+
+mod fake_redis;
+use fake_redis::FakeRedis;
+
+type Replica = TieredCache<CacheKey, PokemonResponse, MockCache>;
+
+fn replica(l1: &MockCache, address: String) -> Replica {
+  TieredCache::new(l1.clone(), RedisStore::new(address).retry_interval(Duration::from_millis(50)))
+}
+
+#[tokio::test]
+async fn replicas_share_entries() {
+  let fake = FakeRedis::start().await;
+  let (l1_a, l1_b) = (MockCache::new(), MockCache::new());
+
+  let replica_a = router(MockPokeAPI, MockTranslationAPI, replica(&l1_a, fake.address()));
+  let res = request().path("/pokemon/translated/pikachu").reply(&replica_a).await;
+  assert!(res.status().is_success());
+  assert_eq!(fake.entry_count(), 2);
+
+  // Read through from L2, filling L1 on the way
+  let replica_b = replica(&l1_b, fake.address());
+  let key = translated_key("pikachu", TranslationType::Shakespeare);
+  let shared = replica_b.get(&key).await.expect("Entry shared through L2");
+
+  assert_eq!(shared.description(), l1_a.get(&key).await.unwrap().description());
+  assert_eq!(*l1_b.insert_count(), 1);
+  assert!(l1_b.get(&key).await.is_some());
+}
+
+#[tokio::test]
+async fn writes_through_with_time_to_live() {
+  let fake = FakeRedis::start().await;
+  let l1 = MockCache::new();
+  let cache = replica(&l1, fake.address()).time_to_live(Some(Duration::from_secs(60)));
+
+  let first_run = router(MockPokeAPI, MockTranslationAPI, cache);
+  request().path("/pokemon/pikachu").reply(&first_run).await;
+
+  // Keyed as before keys named languages, so entries written then are still read
+  let key = r#"pokedex:["pikachu","None"]"#;
+  let stored = fake.value(key).expect("Entry written through");
+  assert_eq!(serde_json::from_str::<PokemonResponse>(&stored).unwrap().name(), "pikachu");
+  assert_eq!(fake.time_to_live(key), Some(60_000));
+  assert_eq!(*l1.insert_count(), 1);
+}
+
+#[tokio::test]
+async fn falls_back_to_l1_when_l2_down() {
+  let l1 = MockCache::new();
+  let cache = replica(&l1, String::from("127.0.0.1:1"));
+  let key = CacheKey::new("pikachu", TranslationType::None);
+
+  let start = Instant::now();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+
+  assert!(res.status().is_success());
+  assert!(cache.get(&key).await.is_some());
+  assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn recovers_when_l2_returns() {
+  let fake = FakeRedis::start().await;
+  let cache = replica(&MockCache::new(), fake.address());
+  let key = CacheKey::new("pikachu", TranslationType::None);
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  fake.set_down(true);
+  request().path("/pokemon/pikachu").reply(&router).await;
+  assert_eq!(fake.entry_count(), 0);
+  assert!(cache.get(&key).await.is_some());
+
+  // Skipped while marked down, then retried
+  fake.set_down(false);
+  request().path("/pokemon/diglett").reply(&router).await;
+  assert_eq!(fake.entry_count(), 0);
+
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  request().path("/pokemon/regice").reply(&router).await;
+  assert_eq!(fake.entry_count(), 1);
+}
+
+#[tokio::test]
+async fn invalidates_both_tiers() {
+  let fake = FakeRedis::start().await;
+  let l1 = MockCache::new();
+  let cache = replica(&l1, fake.address());
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+  request().path("/pokemon/translated/pikachu").reply(&router).await;
+  assert_eq!(fake.entry_count(), 2);
+
+  cache.invalidate(&translated_key("pikachu", TranslationType::Shakespeare)).await;
+  assert_eq!(fake.entry_count(), 1);
+  assert_eq!(cache.keys(), vec![CacheKey::new("pikachu", TranslationType::None)]);
+
+  cache.invalidate_all().await;
+  assert_eq!(fake.entry_count(), 0);
+  assert_eq!(l1.entry_count(), 0);
+}
+
+#[tokio::test]
+async fn drops_connection_of_cancelled_command() {
+  let fake = FakeRedis::start().await;
+  let store = RedisStore::new(fake.address()).timeout(Duration::from_secs(5));
+  store.set("first", b"1", None).await.unwrap();
+  store.set("second", b"2", None).await.unwrap();
+
+  // Cancelled while waiting on its reply, which arrives after
+  fake.set_delay(Duration::from_millis(100));
+  assert!(tokio::time::timeout(Duration::from_millis(10), store.get("first")).await.is_err());
+  fake.set_delay(Duration::ZERO);
+
+  assert_eq!(store.get("second").await.unwrap(), Some(b"2".to_vec()));
+}
+
+#[tokio::test]
+async fn stalled_store_does_not_queue_callers_past_timeout() {
+  let fake = FakeRedis::start().await;
+  let store = RedisStore::new(fake.address()).timeout(Duration::from_millis(100));
+  store.ping().await.unwrap();
+
+  // Each caller waits on the one before for the connection, which would take
+  // a second in all were the wait not part of each caller's timeout
+  fake.set_delay(Duration::from_millis(250));
+  let start = Instant::now();
+  let callers: Vec<_> = (0..10).map(|_| {
+    let store = store.clone();
+    tokio::spawn(async move { store.get("key").await })
+  }).collect();
+
+  for caller in callers {
+    assert!(caller.await.unwrap().is_err());
+  }
+  assert!(start.elapsed() < Duration::from_millis(200), "took {:?}", start.elapsed());
+}
@@ -0,0 +1,179 @@
+use std::fs::{read, read_to_string, write};
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+use async_trait::async_trait;
+use serde_json::{from_slice, from_value, json, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  models::{poke_models::PokemonResponse, translation_models::TranslationUnit},
+  translation::{local::{LocalTranslator, Rules}, TranslationRegistry},
+  util::{TranslationClient, TranslationType, PokError},
+  server::router,
+};
+
+mod mock_impl;
+use mock_impl::{MockPokeAPI, MockTranslationAPI, MockCache};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+/// A backend that always fails, counting its attempts
+#[derive(Clone, Default)]
+struct FailingTranslator(Arc<AtomicUsize>);
+
+#[async_trait]
+impl TranslationClient for FailingTranslator {
+  const TRANSLATION_API: &'static str = "failing";
+
+  fn get_translation_url(&self) -> String {
+    String::new()
+  }
+
+  async fn translate(&self, _pokemon: &PokemonResponse, _translate_to: TranslationType) -> Result<String, PokError> {
+    self.0.fetch_add(1, Ordering::SeqCst);
+    Err(PokError::RateLimited)
+  }
+
+  async fn ping(&self) -> Result<(), PokError> {
+    Err(PokError::CircuitOpen)
+  }
+}
+
+fn pokemon(description: &str) -> PokemonResponse {
+  from_value(json!({ "name": "pikachu", "description": description, "habitat": "forest", "is_legendary": false }))
+    .expect("Build pokemon")
+}
+
+#[tokio::test]
+async fn falls_back_in_order() {
+  let failing = FailingTranslator::default();
+  let registry = TranslationRegistry::new()
+    .chain(TranslationType::Shakespeare, [Arc::new(failing.clone()) as _, Arc::new(LocalTranslator::new()) as _, Arc::new(MockTranslationAPI) as _]);
+
+  assert_eq!(registry.backend_names(TranslationType::Shakespeare), ["failing", "local", ""]);
+
+  let translated = registry.translate(&pokemon("Are you here?"), TranslationType::Shakespeare).await.unwrap();
+  assert_eq!(translated, "Art thee hither?");
+  assert_eq!(failing.0.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn reports_last_failure() {
+  let registry = TranslationRegistry::new()
+    .chain(TranslationType::Yoda, [Arc::new(FailingTranslator::default()) as _])
+    .chain(TranslationType::Shakespeare, []);
+
+  let res = registry.translate(&pokemon("Hello."), TranslationType::Yoda).await;
+  assert!(matches!(res, Err(PokError::RateLimited)));
+
+  let res = registry.translate(&pokemon("Hello."), TranslationType::Shakespeare).await;
+  assert!(matches!(res, Err(PokError::NoTranslator)));
+}
+
+#[tokio::test]
+async fn pings_any_backend_in_each_chain() {
+  let registry = TranslationRegistry::new()
+    .chain(TranslationType::Yoda, [Arc::new(FailingTranslator::default()) as _, Arc::new(LocalTranslator::new()) as _]);
+  assert!(registry.ping().await.is_ok());
+
+  let registry = registry
+    .chain(TranslationType::Shakespeare, [Arc::new(FailingTranslator::default()) as _]);
+  assert!(registry.ping().await.is_err());
+}
+
+#[tokio::test]
+async fn translates_locally() {
+  let local = LocalTranslator::new();
+
+  let translated = local.translate(&pokemon("When YOU are hungry, your Pokemon could eat."), TranslationType::Shakespeare).await.unwrap();
+  assert_eq!(translated, "At which hour THEE art hungry, thy Pokemon couldst eat.");
+
+  let translated = local.translate(&pokemon("This pokemon is rarely seen. It hides."), TranslationType::Yoda).await.unwrap();
+  assert_eq!(translated, "Rarely seen, this pokemon is. It hides.");
+}
+
+#[tokio::test]
+async fn serves_local_translations() {
+  let registry = TranslationRegistry::new()
+    .chain(TranslationType::Shakespeare, [Arc::new(LocalTranslator::new()) as _]);
+  let router = router(MockPokeAPI, registry, MockCache::new());
+
+  let res = request().path("/pokemon/translated/pikachu").reply(&router).await;
+  assert_eq!(res.status(), 200);
+
+  let body = from_slice::<Value>(res.body()).unwrap();
+  assert_eq!(body["description"], "At which hour several of these POKéMON gather, their electricity couldst buildeth and cause lightning storms.");
+}
+
+#[test]
+fn follows_custom_rules() {
+  let rules = Rules::empty()
+    .word("pokemon", "beast")
+    .auxiliary("seems");
+
+  assert_eq!(rules.shakespeare("You see a POKEMON."), "You see a BEAST.");
+  assert_eq!(rules.yoda("The pokemon seems tired, and sleeps."), "Tired, the pokemon seems, and sleeps.");
+  // Without an auxiliary, or a pronoun or determiner leading, nothing moves
+  assert_eq!(rules.yoda("Pikachu is tired."), "Pikachu is tired.");
+}
+
+/// Translate the text of every funtranslations fixture locally, comparing
+/// against the golden files `tests/assets/golden_{type}_{name}.txt`
+///
+/// Set `UPDATE_GOLDEN` to rewrite the golden files after changing the rules.
+#[test]
+fn matches_golden_translations() {
+  let rules = Rules::default();
+
+  for name in ["diglett", "pikachu", "regice"] {
+    let fixture = from_slice::<TranslationUnit>(&read(format!("{}/tests/assets/raw_translation_{}.json", ROOT, name))
+      .expect("Read test data"))
+      .expect("Parse test data");
+
+    for translate_to in [TranslationType::Yoda, TranslationType::Shakespeare] {
+      let path = format!("{}/tests/assets/golden_{}_{}.txt", ROOT, translate_to, name);
+      let translated = rules.translate(fixture.contents().text(), translate_to);
+
+      if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write(&path, format!("{}\n", translated)).expect("Write golden file");
+      }
+      assert_eq!(translated, read_to_string(&path).expect("Read golden file").trim_end(), "{}", path);
+    }
+  }
+}
+
+/// Split text into sentences, lowercased and with whitespace removed, as
+/// funtranslations lowercases names and drops the spaces after full stops
+fn sentences(text: &str) -> Vec<String> {
+  text.split('.')
+    .map(|sentence| sentence.split_whitespace().collect::<String>().to_lowercase())
+    .filter(|sentence| !sentence.is_empty())
+    .collect()
+}
+
+/// Compare local translations against those funtranslations gave for the
+/// fixtures, which the golden files alone do not - they only record what the
+/// local rules did when last updated
+///
+/// The diglett and regice fixtures are labelled Shakespeare but hold Yoda's
+/// speech, as funtranslations served at the time.
+#[test]
+fn matches_funtranslations() {
+  let rules = Rules::default();
+  let fixture = |name: &str| from_slice::<TranslationUnit>(&read(format!("{}/tests/assets/raw_translation_{}.json", ROOT, name))
+    .expect("Read test data"))
+    .expect("Parse test data");
+
+  for (name, translate_to) in [("pikachu", TranslationType::Shakespeare), ("regice", TranslationType::Yoda)] {
+    let fixture = fixture(name);
+    let translated = rules.translate(fixture.contents().text(), translate_to);
+    assert_eq!(sentences(&translated), sentences(fixture.contents().translated()), "{} {}", translate_to, name);
+  }
+
+  // Funtranslations also moves a trailing phrase to the front, which the
+  // local rules leave be, so only the second sentence matches
+  let fixture = fixture("diglett");
+  let translated = rules.translate(fixture.contents().text(), TranslationType::Yoda);
+  assert_eq!(sentences(&translated)[1], sentences(fixture.contents().translated())[1]);
+  assert_eq!(sentences(&translated)[1], "aboveground,itsometimesappears");
+}
@@ -0,0 +1,104 @@
+use serde_json::{from_slice, Value};
+use warp::test::request;
+
+use truelayer_coding_challenge::{
+  models::poke_models::{PokemonResponse, VersionSelection},
+  server::router,
+  util::{CacheKey, CacheWrapper, TranslationType, PokError},
+};
+
+mod mock_impl;
+use mock_impl::{description, species, MockPokeAPI, MockTranslationAPI, MockCache};
+
+#[test]
+fn parses_version_selections() {
+  assert_eq!("first".parse::<VersionSelection>().unwrap(), VersionSelection::First);
+  assert_eq!("Latest".parse::<VersionSelection>().unwrap(), VersionSelection::Latest);
+  assert_eq!("random".parse::<VersionSelection>().unwrap(), VersionSelection::Random);
+  assert_eq!("omega-ruby".parse::<VersionSelection>().unwrap(), VersionSelection::Version(String::from("omega-ruby")));
+  assert!("sword;drop".parse::<VersionSelection>().is_err());
+}
+
+#[test]
+fn selects_descriptions_by_version() {
+  let english = [String::from("en")];
+
+  let pokemon = PokemonResponse::from_species(&species("pikachu"), &english, &VersionSelection::First).unwrap();
+  assert_eq!(pokemon.version(), Some("red"));
+  assert!(pokemon.description().starts_with("When several"));
+
+  let pokemon = PokemonResponse::from_species(&species("pikachu"), &english, &VersionSelection::Latest).unwrap();
+  assert_eq!(pokemon.version(), Some("shield"));
+
+  let pokemon = PokemonResponse::from_species(&species("pikachu"), &english, &VersionSelection::Version(String::from("sword"))).unwrap();
+  assert_eq!(pokemon.version(), Some("sword"));
+  assert!(pokemon.description().starts_with("Pikachu that can generate"));
+
+  // A version missing in the preferred language is looked for in the next
+  let languages = [String::from("ja"), String::from("en")];
+  let pokemon = PokemonResponse::from_species(&species("pikachu"), &languages, &VersionSelection::Version(String::from("red"))).unwrap();
+  assert_eq!((pokemon.language(), pokemon.version()), ("en", Some("red")));
+
+  let res = PokemonResponse::from_species(&species("pikachu"), &english, &VersionSelection::Version(String::from("scarlet")));
+  assert!(matches!(res, Err(PokError::VersionNotFound(version)) if version == "scarlet"));
+}
+
+#[test]
+fn selects_random_descriptions_stably() {
+  let english = [String::from("en")];
+
+  let first = PokemonResponse::from_species(&species("pikachu"), &english, &VersionSelection::Random).unwrap();
+  let second = PokemonResponse::from_species(&species("pikachu"), &english, &VersionSelection::Random).unwrap();
+  assert_eq!(first.version(), second.version());
+  assert_eq!(first.description(), second.description());
+}
+
+#[tokio::test]
+async fn serves_requested_version() {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  let res = request().path("/pokemon/pikachu").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.headers()["x-game-version"], "red");
+
+  let res = request().path("/pokemon/pikachu?version=sword").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.headers()["x-game-version"], "sword");
+  assert!(description(res.body()).starts_with("Pikachu that can generate"));
+  // The public JSON is unchanged
+  assert_eq!(from_slice::<Value>(res.body()).unwrap().as_object().unwrap().len(), 4);
+
+  let res = request().path("/pokemon/pikachu?version=scarlet").reply(&router).await;
+  assert_eq!(res.status(), 404);
+
+  let res = request().path("/pokemon/pikachu?version=sword%3Bdrop").reply(&router).await;
+  assert_eq!(res.status(), 400);
+  let res = request().path("/pokemon/pikachu?version=sword&version=shield").reply(&router).await;
+  assert_eq!(res.status(), 400);
+
+  // Each way of choosing a version is cached separately
+  assert_eq!(*cache.insert_count(), 2);
+  let sword = CacheKey::new("pikachu", TranslationType::None).version(VersionSelection::Version(String::from("sword")));
+  assert_eq!(cache.get(&sword).await.unwrap().version(), Some("sword"));
+}
+
+#[tokio::test]
+async fn translates_requested_version() {
+  let cache = MockCache::new();
+  let router = router(MockPokeAPI, MockTranslationAPI, cache.clone());
+
+  let res = request().path("/pokemon/translated/pikachu?version=latest").reply(&router).await;
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.headers()["x-game-version"], "shield");
+
+  // Translations are cached by the game served, not the way it was chosen
+  let key = CacheKey::new("pikachu", TranslationType::Shakespeare).version(VersionSelection::Version(String::from("shield")));
+  assert_eq!(cache.get(&key).await.unwrap().version(), Some("shield"));
+  assert!(cache.get(&CacheKey::new("pikachu", TranslationType::Shakespeare)).await.is_none());
+
+  // Naming the same game only caches its untranslated description
+  let res = request().path("/pokemon/translated/pikachu?version=shield").reply(&router).await;
+  assert_eq!(res.headers()["x-game-version"], "shield");
+  assert_eq!(*cache.insert_count(), 3);
+}